@@ -1,3 +1,4 @@
 pub mod server;
 mod v1;
 pub mod v2;
+pub mod webhook;
@@ -14,7 +14,10 @@ use crate::shutdown::Controller;
 use crate::types::IdentityConfig;
 use crate::{
 	api::v1,
-	network::rpc::{self},
+	network::{
+		p2p::PeerReputation,
+		rpc::{self},
+	},
 	types::{RuntimeConfig, State},
 };
 use color_eyre::eyre::WrapErr;
@@ -36,6 +39,7 @@ pub struct Server<T: Database> {
 	pub network_version: String,
 	pub node_client: rpc::Client,
 	pub ws_clients: v2::types::WsClients,
+	pub peer_reputation: PeerReputation,
 	pub shutdown: Controller<String>,
 }
 
@@ -65,6 +69,7 @@ impl<T: Database + Clone + Send + Sync + 'static> Server<T> {
 			self.identity_cfg,
 			self.node_client.clone(),
 			self.ws_clients.clone(),
+			self.peer_reputation,
 			self.db.clone(),
 		);
 
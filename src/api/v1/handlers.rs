@@ -1,9 +1,8 @@
 use super::types::{AppDataQuery, ClientResponse, ConfidenceResponse, LatestBlockResponse, Status};
 use crate::{
 	api::v1::types::{Extrinsics, ExtrinsicsDataResponse},
-	data::{Database, Key},
+	data::{self, Database, Key},
 	types::{Mode, OptionBlockRange, State},
-	utils::calculate_confidence,
 };
 use avail_subxt::{
 	api::runtime_types::{da_control::pallet::Call, da_runtime::RuntimeCall},
@@ -33,9 +32,8 @@ pub fn confidence(
 	state: Arc<Mutex<State>>,
 ) -> ClientResponse<ConfidenceResponse> {
 	info!("Got request for confidence for block {block_num}");
-	let res = match db.get(Key::VerifiedCellCount(block_num)) {
-		Ok(Some(count)) => {
-			let confidence = calculate_confidence(count);
+	let res = match data::get_confidence(&db, block_num) {
+		Ok(Some((confidence, _))) => {
 			let serialised_confidence = serialised_confidence(block_num, confidence);
 			ClientResponse::Normal(ConfidenceResponse {
 				block: block_num,
@@ -71,15 +69,12 @@ pub fn status(
 	let Some(last) = state.confidence_achieved.last() else {
 		return ClientResponse::NotFound;
 	};
-	let res = match db.get(Key::VerifiedCellCount(last)) {
-		Ok(Some(count)) => {
-			let confidence = calculate_confidence(count);
-			ClientResponse::Normal(Status {
-				block_num: last,
-				confidence,
-				app_id,
-			})
-		},
+	let res = match data::get_confidence(&db, last) {
+		Ok(Some((confidence, _))) => ClientResponse::Normal(Status {
+			block_num: last,
+			confidence,
+			app_id,
+		}),
 		Ok(None) => ClientResponse::NotFound,
 
 		Err(e) => ClientResponse::Error(e),
@@ -1,27 +1,29 @@
 use super::{
 	transactions,
 	types::{
-		block_status, filter_fields, Block, BlockStatus, DataQuery, DataResponse, DataTransaction,
-		Error, FieldsQueryParameter, Header, Status, SubmitResponse, Subscription, SubscriptionId,
-		Transaction, Version, WsClients,
+		block_status, filter_fields, Block, BlockStatus, Cell, CellsResponse, DataQuery,
+		DataResponse, DataTransaction, DatabaseStats, Error, FieldsQueryParameter, FinalityProof,
+		Header, SamplingAudit, Status, SubmitResponse, Subscription, SubscriptionId, Transaction,
+		Version, WsClients,
 	},
 	ws,
 };
 use crate::{
 	api::v2::types::{ErrorCode, InternalServerError},
-	data::Database,
-	data::Key,
+	data::{self, Database, Key, Unavailability, VerifiedCell, COLUMN_FAMILIES},
 	types::{RuntimeConfig, State},
-	utils::calculate_confidence,
 };
 use avail_subxt::primitives;
 use color_eyre::{eyre::eyre, Result};
 use hyper::StatusCode;
 use std::{
 	convert::Infallible,
-	sync::{Arc, Mutex},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
 };
-use tracing::error;
+use tracing::{error, info};
 use uuid::Uuid;
 use warp::{ws::Ws, Rejection, Reply};
 
@@ -101,12 +103,20 @@ pub async fn block(
 		return Err(Error::not_found());
 	};
 
-	let confidence = db
-		.get(Key::VerifiedCellCount(block_number))
-		.map_err(Error::internal_server_error)?
-		.map(calculate_confidence);
+	let confidence =
+		data::get_confidence(&db, block_number).map_err(Error::internal_server_error)?;
+
+	let unavailability = db
+		.get::<Unavailability>(Key::Unavailability(block_number))
+		.map_err(Error::internal_server_error)?;
+
+	// Sampling outcome takes precedence, since processed blocks ranges don't track failed blocks
+	let block_status = match unavailability {
+		Some(_) if confidence.is_none() => BlockStatus::Incomplete,
+		_ => block_status,
+	};
 
-	Ok(Block::new(block_status, confidence))
+	Ok(Block::new(block_status, confidence, unavailability))
 }
 
 pub async fn block_header(
@@ -134,6 +144,109 @@ pub async fn block_header(
 		.map_err(Error::internal_server_error)
 }
 
+pub async fn block_cells(
+	block_number: u32,
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database,
+) -> Result<CellsResponse, Error> {
+	let state = state.lock().expect("Lock should be acquired");
+
+	let Some(block_status) = block_status(&config.sync_start_block, &state, block_number) else {
+		return Err(Error::not_found());
+	};
+
+	if !matches!(
+		block_status,
+		BlockStatus::VerifyingData | BlockStatus::Finished
+	) {
+		return Err(Error::bad_request_unknown("Block cells are not available"));
+	};
+
+	// Cells are not stored if they were pruned, or if block was verified by an older version
+	let Some(cells) = db
+		.get::<Vec<VerifiedCell>>(Key::VerifiedCells(block_number))
+		.map_err(Error::internal_server_error)?
+	else {
+		return Err(Error::not_found());
+	};
+
+	let cells = cells
+		.into_iter()
+		.map(Cell::try_from)
+		.collect::<Result<_>>()
+		.map_err(Error::internal_server_error)?;
+
+	Ok(CellsResponse {
+		block_number,
+		cells,
+	})
+}
+
+pub async fn block_finality(
+	block_number: u32,
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database,
+) -> Result<FinalityProof, Error> {
+	let state = state.lock().expect("Lock should be acquired");
+
+	let Some(block_status) = block_status(&config.sync_start_block, &state, block_number) else {
+		return Err(Error::not_found());
+	};
+
+	if matches!(
+		block_status,
+		BlockStatus::Unavailable | BlockStatus::Pending | BlockStatus::VerifyingHeader
+	) {
+		return Err(Error::bad_request_unknown(
+			"Block finality proof is not available",
+		));
+	};
+
+	// Proofs are stored only for the headers verified by the subscription to the finalized blocks
+	let Some(proof) = db
+		.get::<data::FinalityProof>(Key::FinalityProof(block_number))
+		.map_err(Error::internal_server_error)?
+	else {
+		return Err(Error::not_found());
+	};
+
+	Ok(FinalityProof::new(block_number, proof))
+}
+
+pub async fn block_sampling(
+	block_number: u32,
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database,
+) -> Result<SamplingAudit, Error> {
+	let state = state.lock().expect("Lock should be acquired");
+
+	let Some(block_status) = block_status(&config.sync_start_block, &state, block_number) else {
+		return Err(Error::not_found());
+	};
+
+	if matches!(
+		block_status,
+		BlockStatus::Unavailable | BlockStatus::Pending
+	) {
+		return Err(Error::bad_request_unknown(
+			"Block sampling audit is not available",
+		));
+	};
+
+	// Audit records are stored only for the blocks sampled by the light client
+	let Some(audit) = db
+		.get::<data::SamplingAudit>(Key::SamplingAudit(block_number))
+		.map_err(Error::internal_server_error)?
+	else {
+		return Err(Error::not_found());
+	};
+
+	Ok(SamplingAudit::new(block_number, audit))
+}
+
 pub async fn block_data(
 	block_number: u32,
 	query: DataQuery,
@@ -182,6 +295,35 @@ pub async fn block_data(
 	})
 }
 
+pub async fn database_stats(db: impl Database) -> Result<DatabaseStats, Error> {
+	DatabaseStats::new(&db).map_err(Error::internal_server_error)
+}
+
+pub async fn compact_database(
+	db: impl Database + Send + 'static,
+	compacting: Arc<AtomicBool>,
+) -> Result<DatabaseStats, Error> {
+	if compacting.swap(true, Ordering::SeqCst) {
+		return Err(Error::conflict("Database compaction is already running"));
+	}
+
+	// Compaction can take a while, so it is not run on the async runtime threads.
+	// Guard is released by the compaction task, since it runs to completion even if the request is dropped.
+	tokio::task::spawn_blocking(move || {
+		let compacted = COLUMN_FAMILIES.iter().try_for_each(|column_family| {
+			info!(column_family, "Compacting column family...");
+			db.compact(column_family)
+		});
+		compacting.store(false, Ordering::SeqCst);
+		compacted?;
+		info!("Database compaction finished");
+		DatabaseStats::new(&db)
+	})
+	.await
+	.map_err(|error| Error::internal_server_error(error.into()))?
+	.map_err(Error::internal_server_error)
+}
+
 pub async fn handle_rejection(error: Rejection) -> Result<impl Reply, Rejection> {
 	if error.find::<InternalServerError>().is_some() {
 		return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
@@ -3,22 +3,22 @@ use sp_core::sr25519::Pair;
 use std::{
 	convert::Infallible,
 	fmt::Display,
-	sync::{Arc, Mutex},
+	sync::{atomic::AtomicBool, Arc, Mutex},
 };
 use subxt::tx::PairSigner;
-use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, warn};
 use warp::{Filter, Rejection, Reply};
 
 use self::{
 	handlers::{handle_rejection, log_internal_server_error},
-	types::{DataQuery, PublishMessage, Version, WsClients},
+	types::{DataQuery, PeerScores, PublishMessage, Version, WsClients},
 };
 
 use crate::{
 	api::v2::types::Topic,
 	data::Database,
-	network::rpc::Client,
+	network::{p2p::PeerReputation, rpc::Client},
 	types::{IdentityConfig, RuntimeConfig, State},
 };
 
@@ -40,6 +40,13 @@ fn with_db<T: Database + Clone + Send>(
 	warp::any().map(move || db.clone())
 }
 
+/// Rejects requests to the admin routes if the admin API is not enabled.
+fn with_admin(enabled: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+	warp::any()
+		.and_then(move || optionally(enabled.then_some(())))
+		.untuple_one()
+}
+
 fn with_ws_clients(
 	clients: WsClients,
 ) -> impl Filter<Extract = (WsClients,), Error = Infallible> + Clone {
@@ -93,6 +100,48 @@ fn block_header_route(
 		.map(log_internal_server_error)
 }
 
+fn block_cells_route(
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "blocks" / u32 / "cells")
+		.and(warp::get())
+		.and(warp::any().map(move || config.clone()))
+		.and(warp::any().map(move || state.clone()))
+		.and(with_db(db))
+		.then(handlers::block_cells)
+		.map(log_internal_server_error)
+}
+
+fn block_finality_route(
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "blocks" / u32 / "finality")
+		.and(warp::get())
+		.and(warp::any().map(move || config.clone()))
+		.and(warp::any().map(move || state.clone()))
+		.and(with_db(db))
+		.then(handlers::block_finality)
+		.map(log_internal_server_error)
+}
+
+fn block_sampling_route(
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "blocks" / u32 / "sampling")
+		.and(warp::get())
+		.and(warp::any().map(move || config.clone()))
+		.and(warp::any().map(move || state.clone()))
+		.and(with_db(db))
+		.then(handlers::block_sampling)
+		.map(log_internal_server_error)
+}
+
 fn block_data_route(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
@@ -108,6 +157,42 @@ fn block_data_route(
 		.map(log_internal_server_error)
 }
 
+fn database_stats_route(
+	admin_api_enable: bool,
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "admin" / "database")
+		.and(warp::get())
+		.and(with_admin(admin_api_enable))
+		.and(with_db(db))
+		.then(handlers::database_stats)
+		.map(log_internal_server_error)
+}
+
+fn compact_database_route(
+	admin_api_enable: bool,
+	db: impl Database + Clone + Send + 'static,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	let compacting = Arc::new(AtomicBool::new(false));
+	warp::path!("v2" / "admin" / "database" / "compact")
+		.and(warp::post())
+		.and(with_admin(admin_api_enable))
+		.and(with_db(db))
+		.and(warp::any().map(move || compacting.clone()))
+		.then(handlers::compact_database)
+		.map(log_internal_server_error)
+}
+
+fn peer_scores_route(
+	reputation: PeerReputation,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "peers" / "scores")
+		.and(warp::get())
+		.map(move || PeerScores {
+			peers: reputation.scores(),
+		})
+}
+
 fn submit_route(
 	submitter: Option<Arc<impl transactions::Submit + Clone + Send + Sync>>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
@@ -156,6 +241,13 @@ pub async fn publish<T: Clone + TryInto<PublishMessage>>(
 	loop {
 		let message = match receiver.recv().await {
 			Ok(value) => value,
+			Err(RecvError::Lagged(skipped)) => {
+				warn!(
+					?topic,
+					"Publisher lagged behind, {skipped} messages skipped"
+				);
+				continue;
+			},
 			Err(error) => {
 				error!(?topic, "Cannot receive message: {error}");
 				return;
@@ -170,7 +262,7 @@ pub async fn publish<T: Clone + TryInto<PublishMessage>>(
 			},
 		};
 
-		match clients.publish(&topic, message).await {
+		match clients.publish(&message.topic(), message).await {
 			Ok(results) => {
 				let published = results.iter().filter(|&result| result.is_ok()).count();
 				let failed = results.iter().filter(|&result| result.is_err()).count();
@@ -193,7 +285,8 @@ pub fn routes(
 	identity_config: IdentityConfig,
 	rpc_client: Client,
 	ws_clients: WsClients,
-	db: impl Database + Clone + Send,
+	peer_reputation: PeerReputation,
+	db: impl Database + Clone + Send + 'static,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	let version = Version {
 		version,
@@ -219,7 +312,21 @@ pub fn routes(
 			state.clone(),
 			db.clone(),
 		))
+		.or(block_cells_route(config.clone(), state.clone(), db.clone()))
+		.or(block_finality_route(
+			config.clone(),
+			state.clone(),
+			db.clone(),
+		))
+		.or(block_sampling_route(
+			config.clone(),
+			state.clone(),
+			db.clone(),
+		))
 		.or(block_data_route(config.clone(), state.clone(), db.clone()))
+		.or(database_stats_route(config.admin_api_enable, db.clone()))
+		.or(compact_database_route(config.admin_api_enable, db))
+		.or(peer_scores_route(peer_reputation))
 		.or(subscriptions_route(ws_clients.clone()))
 		.or(submit_route(submitter.clone()))
 		.or(ws_route(ws_clients, version, config, submitter, state))
@@ -231,12 +338,18 @@ mod tests {
 	use super::{transactions, types::Transaction};
 	use crate::{
 		api::v2::types::{
-			DataField, ErrorCode, SubmitResponse, Subscription, SubscriptionId, Topic, Version,
-			WsClients, WsError, WsResponse,
+			DataField, DatabaseStats, ErrorCode, PeerScores, SubmitResponse, Subscription,
+			SubscriptionId, Topic, Version, WsClients, WsError, WsResponse,
 		},
+		confidence::ConfidenceModel,
 		data::Key,
-		data::{mem_db, Database},
-		types::{BlockRange, OptionBlockRange, RuntimeConfig, State},
+		data::{
+			mem_db, CellSource, ConfidenceParams, Database, FinalityProof, SampledCell,
+			SamplingAudit, Unavailability, UnavailabilityReason, VerifiedCell, COLUMN_FAMILIES,
+			CONFIDENCE_FACTOR_CF,
+		},
+		network::p2p::{Misbehaviour, PeerReputation},
+		types::{BlockRange, OptionBlockRange, RetentionPolicy, RuntimeConfig, State},
 	};
 	use async_trait::async_trait;
 	use avail_subxt::utils::H256;
@@ -250,10 +363,13 @@ mod tests {
 	};
 	use hyper::StatusCode;
 	use kate_recovery::matrix::Partition;
+	use libp2p::PeerId;
+	use sp_core::ed25519;
 	use std::{
 		collections::HashSet,
 		str::FromStr,
 		sync::{Arc, Mutex},
+		time::Duration,
 	};
 	use subxt::config::substrate::Digest;
 	use test_case::test_case;
@@ -342,6 +458,41 @@ mod tests {
 		assert_eq!(response.body(), &expected);
 	}
 
+	#[tokio::test]
+	async fn status_route_retained() {
+		let runtime_config = RuntimeConfig {
+			confidence_retention: Some(RetentionPolicy {
+				max_blocks: Some(5),
+				..Default::default()
+			}),
+			..Default::default()
+		};
+		let state = Arc::new(Mutex::new(State::default()));
+		{
+			let mut state = state.lock().unwrap();
+			state.latest = 30;
+			state.header_verified.set(20);
+			state.header_verified.set(30);
+			state.confidence_achieved.set(25);
+			state.confidence_achieved.set(29);
+			state.retained.confidence = Some(25);
+		}
+
+		let route = super::status_route(runtime_config, state);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/status")
+			.reply(&route)
+			.await;
+
+		let gen_hash = H256::default();
+		let expected = format!(
+			r#"{{"modes":["light"],"genesis_hash":"{:#x}","network":"{NETWORK}","blocks":{{"latest":30,"available":{{"first":25,"last":29}},"retained":{{"headers":{{"first":20,"last":30}},"confidence":{{"first":25,"last":29}},"verified_cells":{{"first":25,"last":29}}}}}}}}"#,
+			gen_hash
+		);
+		assert_eq!(response.body(), &expected);
+	}
+
 	#[test_case(1, 2)]
 	#[test_case(10, 11)]
 	#[test_case(10, 20)]
@@ -386,13 +537,79 @@ mod tests {
 		assert_eq!(response.status(), StatusCode::OK);
 		assert_eq!(
 			response.body(),
-			r#"{"status":"finished","confidence":93.75}"#
+			r#"{"status":"finished","confidence":93.75,"confidence_model":"naive"}"#
+		);
+	}
+
+	#[tokio::test]
+	async fn block_route_finished_erasure_coded() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State::default()));
+		{
+			let mut state = state.lock().unwrap();
+			state.latest = 10;
+			state.header_verified.set(10);
+			state.data_verified.set(10);
+		}
+		let db = mem_db::MemoryDB::default();
+		// Unavailable block with 1x4 matrix has at most 6 available cells
+		_ = db.put(Key::VerifiedCellCount(10), 7);
+		_ = db.put(
+			Key::ConfidenceParams(10),
+			ConfidenceParams {
+				model: ConfidenceModel::ErasureCoded,
+				rows: 1,
+				cols: 4,
+			},
+		);
+		let route = super::block_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/10")
+			.reply(&route)
+			.await;
+
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.body(),
+			r#"{"status":"finished","confidence":100.0,"confidence_model":"erasure-coded"}"#
 		);
 	}
 
 	#[test_case(0, r#"Block header is not available"#  ; "Block is unavailable")]
 	#[test_case(6, r#"Block header is not available"#  ; "Block is pending")]
 	#[test_case(10, r#"Block header is not available"#  ; "Block is in verifying-header state")]
+	#[tokio::test]
+	async fn block_route_incomplete() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State::default()));
+		{
+			let mut state = state.lock().unwrap();
+			state.latest = 10;
+			state.header_verified.set(10);
+		}
+		let db = mem_db::MemoryDB::default();
+		_ = db.put(
+			Key::Unavailability(10),
+			Unavailability {
+				reason: UnavailabilityReason::ProofVerificationFailed,
+				missing_cells: 2,
+			},
+		);
+		let route = super::block_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/10")
+			.reply(&route)
+			.await;
+
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.body(),
+			r#"{"status":"incomplete","confidence":null,"unavailability":{"reason":"proof-verification-failed","missing_cells":2}}"#
+		);
+	}
+
 	#[tokio::test]
 	async fn block_header_route_bad_request(block_number: u32, expected: &str) {
 		let config = RuntimeConfig {
@@ -473,6 +690,276 @@ mod tests {
 		);
 	}
 
+	#[test_case(0, r#"Block cells are not available"#  ; "Block is unavailable")]
+	#[test_case(6, r#"Block cells are not available"#  ; "Block is pending")]
+	#[test_case(9, r#"Block cells are not available"#  ; "Block is in verifying-confidence state")]
+	#[tokio::test]
+	async fn block_cells_route_bad_request(block_number: u32, expected: &str) {
+		let config = RuntimeConfig {
+			sync_start_block: Some(1),
+			..Default::default()
+		};
+		let state = Arc::new(Mutex::new(State {
+			latest: 10,
+			sync_latest: Some(5),
+			header_verified: Some(BlockRange::init(9)),
+			confidence_achieved: Some(BlockRange::init(10)),
+			..Default::default()
+		}));
+		let db = mem_db::MemoryDB::default();
+		let route = super::block_cells_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path(&format!("/v2/blocks/{block_number}/cells"))
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+		assert_eq!(response.body(), expected);
+	}
+
+	#[tokio::test]
+	async fn block_cells_route_ok() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State {
+			latest: 5,
+			header_verified: Some(BlockRange::init(5)),
+			confidence_achieved: Some(BlockRange::init(5)),
+			..Default::default()
+		}));
+		let db = mem_db::MemoryDB::default();
+		let mut content = vec![1u8; 48];
+		content.extend(vec![2u8; 32]);
+		_ = db.put(
+			Key::VerifiedCells(5),
+			vec![VerifiedCell {
+				row: 1,
+				col: 2,
+				content,
+			}],
+		);
+		let route = super::block_cells_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/5/cells")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.body(),
+			&format!(
+				r#"{{"block_number":5,"cells":[{{"position":{{"row":1,"col":2}},"proof":"0x{}","data":"0x{}"}}]}}"#,
+				"01".repeat(48),
+				"02".repeat(32)
+			)
+		);
+	}
+
+	#[tokio::test]
+	async fn block_finality_route_not_found() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State {
+			latest: 5,
+			header_verified: Some(BlockRange::init(5)),
+			..Default::default()
+		}));
+		let db = mem_db::MemoryDB::default();
+		let route = super::block_finality_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/5/finality")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn block_finality_route_ok() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State {
+			latest: 5,
+			header_verified: Some(BlockRange { first: 4, last: 5 }),
+			..Default::default()
+		}));
+		let db = mem_db::MemoryDB::default();
+		_ = db.put(
+			Key::FinalityProof(4),
+			FinalityProof {
+				justification_block_number: 5,
+				set_id: 2,
+				validator_set: vec![ed25519::Public::from_raw([1u8; 32])],
+				justification: vec![1, 2, 3],
+			},
+		);
+		let route = super::block_finality_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/4/finality")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.body(),
+			&format!(
+				r#"{{"block_number":4,"justification_block_number":5,"set_id":2,"validator_set":["0x{}"],"justification":"0x010203"}}"#,
+				"01".repeat(32)
+			)
+		);
+	}
+
+	#[tokio::test]
+	async fn block_sampling_route_not_found() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State {
+			latest: 5,
+			confidence_achieved: Some(BlockRange::init(5)),
+			..Default::default()
+		}));
+		let db = mem_db::MemoryDB::default();
+		let route = super::block_sampling_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/5/sampling")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn block_sampling_route_ok() {
+		let config = RuntimeConfig::default();
+		let state = Arc::new(Mutex::new(State {
+			latest: 5,
+			confidence_achieved: Some(BlockRange::init(5)),
+			..Default::default()
+		}));
+		let db = mem_db::MemoryDB::default();
+		_ = db.put(
+			Key::SamplingAudit(5),
+			SamplingAudit {
+				seed: [1u8; 32],
+				cells: vec![
+					SampledCell {
+						row: 0,
+						col: 1,
+						source: Some(CellSource::Dht),
+						verified: false,
+						round: 1,
+					},
+					SampledCell {
+						row: 1,
+						col: 2,
+						source: None,
+						verified: false,
+						round: 2,
+					},
+				],
+				rounds: 2,
+				duration_ms: 150,
+			},
+		);
+		let route = super::block_sampling_route(config, state, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/5/sampling")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.body(),
+			&format!(
+				r#"{{"block_number":5,"seed":"0x{}","rounds":2,"duration_ms":150,"cells":[{{"position":{{"row":0,"col":1}},"source":"dht","verified":false,"round":1}},{{"position":{{"row":1,"col":2}},"source":null,"verified":false,"round":2}}]}}"#,
+				"01".repeat(32)
+			)
+		);
+	}
+
+	#[tokio::test]
+	async fn peer_scores_route_ok() {
+		let reputation = PeerReputation::new(-10, Duration::from_secs(60), HashSet::new());
+		let blocked = PeerId::random();
+		let penalised = PeerId::random();
+		reputation.penalise(blocked, Misbehaviour::InvalidProof);
+		reputation.penalise(penalised, Misbehaviour::Timeout);
+
+		let route = super::peer_scores_route(reputation);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/peers/scores")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		let scores: PeerScores = serde_json::from_slice(response.body()).unwrap();
+		assert_eq!(scores.peers.len(), 2);
+		assert_eq!(scores.peers[0].peer_id, blocked.to_string());
+		assert_eq!(scores.peers[0].score, -20);
+		assert!(scores.peers[0].blocked_for.is_some());
+		assert_eq!(scores.peers[1].peer_id, penalised.to_string());
+		assert_eq!(scores.peers[1].score, -5);
+		assert!(scores.peers[1].blocked_for.is_none());
+	}
+
+	#[tokio::test]
+	async fn database_stats_route_ok() {
+		let db = mem_db::MemoryDB::default();
+		_ = db.put(Key::VerifiedCellCount(1), 10u32);
+		let route = super::database_stats_route(true, db);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/admin/database")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		let stats: DatabaseStats = serde_json::from_slice(response.body()).unwrap();
+		assert_eq!(stats.column_families.len(), COLUMN_FAMILIES.len());
+		let confidence = stats
+			.column_families
+			.iter()
+			.find(|column_family| column_family.name == CONFIDENCE_FACTOR_CF)
+			.unwrap();
+		assert_eq!(confidence.estimated_keys, 1);
+		assert_eq!(confidence.live_data_size, 8);
+	}
+
+	#[tokio::test]
+	async fn compact_database_route_ok() {
+		let db = mem_db::MemoryDB::default();
+		let route = super::compact_database_route(true, db);
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/admin/database/compact")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	#[tokio::test]
+	async fn admin_routes_disabled() {
+		let db = mem_db::MemoryDB::default();
+		let route = super::database_stats_route(false, db.clone());
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/admin/database")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+		let route = super::compact_database_route(false, db);
+		let response = warp::test::request()
+			.method("POST")
+			.path("/v2/admin/database/compact")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn compact_database_already_running() {
+		let db = mem_db::MemoryDB::default();
+		let compacting = Arc::new(std::sync::atomic::AtomicBool::new(true));
+		let result = super::handlers::compact_database(db, compacting).await;
+		assert!(matches!(result, Err(error) if error.error_code == ErrorCode::Conflict));
+	}
+
 	#[test_case(0, r#"Block data is not available"#  ; "Block is unavailable")]
 	#[test_case(6, r#"Block data is not available"#  ; "Block is pending")]
 	#[test_case(8, r#"Block data is not available"#  ; "Block is in verifying-data state")]
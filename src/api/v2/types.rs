@@ -25,9 +25,12 @@ use warp::{
 };
 
 use crate::{
-	network::rpc::Event as RpcEvent,
+	confidence::ConfidenceModel,
+	data::{self, Database, Unavailability, VerifiedCell, COLUMN_FAMILIES},
+	network::{p2p::PeerScore, rpc::Event as RpcEvent},
 	types::{
-		self, block_matrix_partition_format, BlockVerified, OptionBlockRange, RuntimeConfig, State,
+		self, block_matrix_partition_format, BlockVerified, OptionBlockRange, RetentionConfig,
+		RuntimeConfig, State,
 	},
 	utils::decode_app_data,
 };
@@ -73,6 +76,56 @@ pub struct HistoricalSync {
 	pub app_data: Option<BlockRange>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct Retained {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub headers: Option<BlockRange>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub confidence: Option<BlockRange>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub app_data: Option<BlockRange>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub verified_cells: Option<BlockRange>,
+}
+
+fn retained_range(
+	retained: Option<u32>,
+	range: &Option<types::BlockRange>,
+	sync_range: &Option<types::BlockRange>,
+) -> Option<BlockRange> {
+	let first = retained.or(sync_range.first()).or(range.first())?;
+	let last = range.last().or(sync_range.last())?;
+	(first <= last).then_some(BlockRange { first, last })
+}
+
+impl Retained {
+	fn new(state: &State) -> Self {
+		let retained = &state.retained;
+		Retained {
+			headers: retained_range(
+				retained.block_header,
+				&state.header_verified,
+				&state.sync_header_verified,
+			),
+			confidence: retained_range(
+				retained.confidence,
+				&state.confidence_achieved,
+				&state.sync_confidence_achieved,
+			),
+			app_data: retained_range(
+				retained.app_data,
+				&state.data_verified,
+				&state.sync_data_verified,
+			),
+			verified_cells: retained_range(
+				retained.verified_cells,
+				&state.confidence_achieved,
+				&state.sync_confidence_achieved,
+			),
+		}
+	}
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Blocks {
 	pub latest: u32,
@@ -82,6 +135,8 @@ pub struct Blocks {
 	pub app_data: Option<BlockRange>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub historical_sync: Option<HistoricalSync>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub retained: Option<Retained>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -172,6 +227,9 @@ impl Status {
 			available: state.confidence_achieved.as_ref().map(From::from),
 			app_data: state.data_verified.as_ref().map(From::from),
 			historical_sync,
+			retained: RetentionConfig::from(config)
+				.is_enabled()
+				.then(|| Retained::new(state)),
 		};
 
 		let node = state.connected_node.clone();
@@ -221,6 +279,7 @@ pub enum Topic {
 	HeaderVerified,
 	ConfidenceAchieved,
 	DataVerified,
+	BlockUnavailable,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -251,6 +310,7 @@ pub enum BlockStatus {
 	VerifyingConfidence,
 	VerifyingData,
 	Finished,
+	Incomplete,
 }
 
 pub fn block_status(
@@ -263,7 +323,10 @@ pub fn block_status(
 	}
 
 	let first_block = state.header_verified.first().unwrap_or(state.latest);
-	let first_sync_block = sync_start_block.unwrap_or(first_block);
+	// Blocks processed before restart are kept in sync ranges, even if historical sync is not configured
+	let first_sync_block = sync_start_block
+		.or(state.sync_header_verified.first())
+		.unwrap_or(first_block);
 
 	if block_number < first_sync_block {
 		return Some(BlockStatus::Unavailable);
@@ -283,6 +346,9 @@ pub fn block_status(
 		if is_sync_latest.unwrap_or(false) {
 			return Some(BlockStatus::VerifyingHeader);
 		}
+		if sync_start_block.is_none() {
+			return Some(BlockStatus::Unavailable);
+		}
 	} else {
 		if state.data_verified.contains(block_number) {
 			return Some(BlockStatus::Finished);
@@ -305,11 +371,24 @@ pub fn block_status(
 pub struct Block {
 	pub status: BlockStatus,
 	pub confidence: Option<f64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub confidence_model: Option<ConfidenceModel>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub unavailability: Option<Unavailability>,
 }
 
 impl Block {
-	pub fn new(status: BlockStatus, confidence: Option<f64>) -> Self {
-		Self { status, confidence }
+	pub fn new(
+		status: BlockStatus,
+		confidence: Option<(f64, ConfidenceModel)>,
+		unavailability: Option<Unavailability>,
+	) -> Self {
+		Self {
+			status,
+			confidence: confidence.map(|(confidence, _)| confidence),
+			confidence_model: confidence.map(|(_, model)| model),
+			unavailability,
+		}
 	}
 }
 
@@ -453,10 +532,35 @@ pub struct ConfidenceMessage {
 	confidence: Option<f64>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnavailableMessage {
+	pub block_number: u32,
+	pub block_hash: H256,
+	#[serde(flatten)]
+	pub unavailability: Unavailability,
+}
+
+impl UnavailableMessage {
+	/// Creates unavailability message if the verified block is unavailable.
+	pub fn from_block(block: &BlockVerified) -> Option<Self> {
+		block
+			.unavailability
+			.map(|unavailability| UnavailableMessage {
+				block_number: block.block_num,
+				block_hash: block.header_hash,
+				unavailability,
+			})
+	}
+}
+
 impl TryFrom<BlockVerified> for PublishMessage {
 	type Error = Report;
 
 	fn try_from(value: BlockVerified) -> Result<Self, Self::Error> {
+		if let Some(message) = UnavailableMessage::from_block(&value) {
+			return Ok(PublishMessage::BlockUnavailable(message));
+		}
+
 		Ok(PublishMessage::ConfidenceAchieved(ConfidenceMessage {
 			block_number: value.block_num,
 			confidence: value.confidence,
@@ -486,6 +590,180 @@ pub struct DataQuery {
 	pub fields: Option<FieldsQueryParameter>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ColumnFamilyStats {
+	pub name: String,
+	pub estimated_keys: u64,
+	pub live_data_size: u64,
+	pub sst_files_size: u64,
+	pub pending_compaction_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatabaseStats {
+	pub column_families: Vec<ColumnFamilyStats>,
+}
+
+impl DatabaseStats {
+	pub fn new(db: &impl Database) -> Result<Self> {
+		let column_families = COLUMN_FAMILIES
+			.into_iter()
+			.map(|column_family| {
+				let stats = db.column_family_stats(column_family)?;
+				Ok(ColumnFamilyStats {
+					name: column_family.to_string(),
+					estimated_keys: stats.estimated_keys,
+					live_data_size: stats.live_data_size,
+					sst_files_size: stats.sst_files_size,
+					pending_compaction_bytes: stats.pending_compaction_bytes,
+				})
+			})
+			.collect::<Result<_>>()?;
+		Ok(DatabaseStats { column_families })
+	}
+}
+
+impl Reply for DatabaseStats {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PeerScores {
+	pub peers: Vec<PeerScore>,
+}
+
+impl Reply for PeerScores {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CellPosition {
+	pub row: u32,
+	pub col: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Cell {
+	pub position: CellPosition,
+	pub proof: String,
+	pub data: String,
+}
+
+impl TryFrom<VerifiedCell> for Cell {
+	type Error = Report;
+
+	fn try_from(cell: VerifiedCell) -> Result<Self> {
+		if cell.content.len() != config::COMMITMENT_SIZE + config::CHUNK_SIZE {
+			return Err(eyre!("Invalid cell content length {}", cell.content.len()));
+		}
+		let (proof, data) = cell.content.split_at(config::COMMITMENT_SIZE);
+		Ok(Cell {
+			position: CellPosition {
+				row: cell.row,
+				col: cell.col,
+			},
+			proof: format!("0x{}", hex::encode(proof)),
+			data: format!("0x{}", hex::encode(data)),
+		})
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CellsResponse {
+	pub block_number: u32,
+	pub cells: Vec<Cell>,
+}
+
+impl Reply for CellsResponse {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FinalityProof {
+	pub block_number: u32,
+	pub justification_block_number: u32,
+	pub set_id: u64,
+	pub validator_set: Vec<String>,
+	pub justification: String,
+}
+
+impl FinalityProof {
+	pub fn new(block_number: u32, proof: data::FinalityProof) -> Self {
+		FinalityProof {
+			block_number,
+			justification_block_number: proof.justification_block_number,
+			set_id: proof.set_id,
+			validator_set: proof
+				.validator_set
+				.iter()
+				.map(|validator| format!("0x{}", hex::encode(validator.0)))
+				.collect(),
+			justification: format!("0x{}", hex::encode(proof.justification)),
+		}
+	}
+}
+
+impl Reply for FinalityProof {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SampledCell {
+	pub position: CellPosition,
+	pub source: Option<data::CellSource>,
+	pub verified: bool,
+	pub round: u32,
+}
+
+impl From<data::SampledCell> for SampledCell {
+	fn from(cell: data::SampledCell) -> Self {
+		SampledCell {
+			position: CellPosition {
+				row: cell.row,
+				col: cell.col,
+			},
+			source: cell.source,
+			verified: cell.verified,
+			round: cell.round,
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SamplingAudit {
+	pub block_number: u32,
+	pub seed: String,
+	pub rounds: u32,
+	pub duration_ms: u64,
+	pub cells: Vec<SampledCell>,
+}
+
+impl SamplingAudit {
+	pub fn new(block_number: u32, audit: data::SamplingAudit) -> Self {
+		SamplingAudit {
+			block_number,
+			seed: format!("0x{}", hex::encode(audit.seed)),
+			rounds: audit.rounds,
+			duration_ms: audit.duration_ms,
+			cells: audit.cells.into_iter().map(From::from).collect(),
+		}
+	}
+}
+
+impl Reply for SamplingAudit {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataResponse {
 	pub block_number: u32,
@@ -557,6 +835,7 @@ pub enum PublishMessage {
 	HeaderVerified(Box<HeaderMessage>),
 	ConfidenceAchieved(ConfidenceMessage),
 	DataVerified(DataMessage),
+	BlockUnavailable(UnavailableMessage),
 }
 
 impl PublishMessage {
@@ -567,6 +846,16 @@ impl PublishMessage {
 			PublishMessage::DataVerified(data) => {
 				filter_fields(&mut data.data_transactions, fields)
 			},
+			PublishMessage::BlockUnavailable(_) => (),
+		}
+	}
+
+	pub fn topic(&self) -> Topic {
+		match self {
+			PublishMessage::HeaderVerified(_) => Topic::HeaderVerified,
+			PublishMessage::ConfidenceAchieved(_) => Topic::ConfidenceAchieved,
+			PublishMessage::DataVerified(_) => Topic::DataVerified,
+			PublishMessage::BlockUnavailable(_) => Topic::BlockUnavailable,
 		}
 	}
 }
@@ -708,6 +997,7 @@ impl TryFrom<ws::Message> for Request {
 pub enum ErrorCode {
 	NotFound,
 	BadRequest,
+	Conflict,
 	InternalServerError,
 }
 
@@ -757,10 +1047,15 @@ impl Error {
 		Self::new(Some(request_id), None, ErrorCode::BadRequest, message)
 	}
 
+	pub fn conflict(message: &str) -> Self {
+		Self::new(None, None, ErrorCode::Conflict, message)
+	}
+
 	fn status(&self) -> StatusCode {
 		match self.error_code {
 			ErrorCode::NotFound => StatusCode::NOT_FOUND,
 			ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+			ErrorCode::Conflict => StatusCode::CONFLICT,
 			ErrorCode::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
 		}
 	}
@@ -800,13 +1095,16 @@ pub enum WsError {
 mod tests {
 	use std::time::Duration;
 
+	use avail_core::DataLookup;
 	use avail_subxt::api::runtime_types::avail_core::data_lookup::compact::CompactDataLookup;
+	use kate_recovery::matrix::Dimensions;
 	use sp_core::H256;
 	use tokio::sync::mpsc;
 
 	use crate::{
 		api::v2::types::{BlockStatus, Header, HeaderMessage, PublishMessage},
-		types::{OptionBlockRange, State},
+		data::{Unavailability, UnavailabilityReason},
+		types::{BlockVerified, OptionBlockRange, State},
 	};
 
 	use super::{
@@ -931,6 +1229,31 @@ mod tests {
 		};
 	}
 
+	#[test]
+	fn publish_message_block_unavailable() {
+		let block_verified = BlockVerified {
+			header_hash: H256::default(),
+			block_num: 1,
+			dimensions: Dimensions::new(1, 1).unwrap(),
+			lookup: DataLookup::from_id_and_len_iter([(0, 1)].into_iter()).unwrap(),
+			commitments: vec![],
+			confidence: None,
+			unavailability: Some(Unavailability {
+				reason: UnavailabilityReason::CellsNotFetched,
+				missing_cells: 4,
+			}),
+		};
+		let message: PublishMessage = block_verified.try_into().unwrap();
+		assert!(matches!(message.topic(), Topic::BlockUnavailable));
+		assert_eq!(
+			serde_json::to_string(&message).unwrap(),
+			format!(
+				r#"{{"topic":"block-unavailable","message":{{"block_number":1,"block_hash":"{:?}","reason":"cells-not-fetched","missing_cells":4}}}}"#,
+				H256::default()
+			)
+		);
+	}
+
 	#[test]
 	fn block_status_none() {
 		let mut state = State::default();
@@ -1076,4 +1399,38 @@ mod tests {
 		assert_eq!(block_status(&Some(1), &state, 5), finished);
 		assert_ne!(block_status(&Some(1), &state, 6), finished);
 	}
+
+	#[test]
+	fn block_status_restored() {
+		let mut state = State::default();
+		state.header_verified.set(2);
+		state.header_verified.set(5);
+		state.confidence_achieved.set(2);
+		state.confidence_achieved.set(5);
+		state.data_verified.set(2);
+		state.data_verified.set(4);
+
+		state.detach_restored_ranges(state.header_verified.last(), 10);
+		state.latest = 10;
+		state.header_verified.set(10);
+
+		assert_eq!(
+			block_status(&None, &state, 1),
+			Some(BlockStatus::Unavailable)
+		);
+		assert_eq!(block_status(&None, &state, 2), Some(BlockStatus::Finished));
+		assert_eq!(
+			block_status(&None, &state, 5),
+			Some(BlockStatus::VerifyingData)
+		);
+		assert_eq!(
+			block_status(&None, &state, 7),
+			Some(BlockStatus::Unavailable)
+		);
+		assert_eq!(
+			block_status(&None, &state, 10),
+			Some(BlockStatus::VerifyingConfidence)
+		);
+		assert_eq!(state.synced, Some(true));
+	}
 }
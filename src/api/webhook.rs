@@ -0,0 +1,86 @@
+//! Unavailability alerting
+//!
+//! Block unavailability events are posted as JSON to the configured HTTP endpoint,
+//! so operators can be alerted when sampling of a block fails.
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request, Uri};
+use std::time::Duration;
+use tokio::{
+	sync::broadcast::{self, error::RecvError},
+	time::timeout,
+};
+use tracing::{error, info, warn};
+
+use super::v2::types::UnavailableMessage;
+use crate::types::BlockVerified;
+
+/// Maximum duration of the webhook request, so unresponsive endpoint doesn't stall the alerting
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts unavailability event as JSON to the webhook
+async fn post(
+	client: &Client<HttpConnector>,
+	uri: &Uri,
+	message: &UnavailableMessage,
+) -> Result<()> {
+	let body = serde_json::to_vec(message).wrap_err("Cannot serialize unavailability event")?;
+	let request = Request::builder()
+		.method(Method::POST)
+		.uri(uri)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(body))
+		.wrap_err("Cannot create webhook request")?;
+
+	let response = timeout(WEBHOOK_TIMEOUT, client.request(request))
+		.await
+		.map_err(|_| eyre!("Webhook request timed out after {WEBHOOK_TIMEOUT:?}"))?
+		.wrap_err("Webhook request failed")?;
+
+	if !response.status().is_success() {
+		return Err(eyre!("Webhook responded with {}", response.status()));
+	}
+	Ok(())
+}
+
+/// Posts unavailability events of the received blocks to the webhook.
+/// Failed requests are logged and not retried.
+pub async fn run(webhook: String, mut block_receiver: broadcast::Receiver<BlockVerified>) {
+	info!("Starting unavailability webhook...");
+
+	let uri: Uri = match webhook.parse() {
+		Ok(uri) => uri,
+		Err(error) => {
+			error!("Invalid unavailability webhook URL: {error}");
+			return;
+		},
+	};
+	let client = Client::new();
+
+	loop {
+		let block = match block_receiver.recv().await {
+			Ok(block) => block,
+			Err(RecvError::Lagged(skipped)) => {
+				warn!("Unavailability webhook lagged behind, {skipped} blocks skipped");
+				continue;
+			},
+			Err(error) => {
+				error!("Cannot receive message: {error}");
+				return;
+			},
+		};
+
+		let Some(message) = UnavailableMessage::from_block(&block) else {
+			continue;
+		};
+
+		let block_number = message.block_number;
+		match post(&client, &uri, &message).await {
+			Ok(()) => info!(block_number, "Unavailability event posted to webhook"),
+			Err(error) => warn!(block_number, "Cannot post unavailability event: {error:#}"),
+		}
+	}
+}
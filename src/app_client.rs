@@ -6,6 +6,7 @@
 //!
 //! Get app data rows from node
 //! Verify commitment equality for each row
+//! Insert rows fetched from node into the DHT and announce them to other clients
 //! Decode app data and store it into local database under the `app_id:block_number` key
 //!
 //! # Notes
@@ -27,22 +28,25 @@ use kate_recovery::{
 	commitments,
 	config::{self, CHUNK_SIZE},
 	data::{Cell, DataCell},
-	matrix::{Dimensions, Position},
+	matrix::{Dimensions, Position, RowIndex},
 };
 use mockall::automock;
 use rand::SeedableRng as _;
 use rand_chacha::ChaChaRng;
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{HashMap, HashSet, VecDeque},
 	ops::Range,
 	sync::{Arc, Mutex},
 };
-use tokio::sync::broadcast;
-use tracing::{debug, error, info, instrument};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, instrument, warn};
 
 use crate::{
-	data::{Database, Key},
-	network::{p2p::Client as P2pClient, rpc::Client as RpcClient},
+	data::{self, Database, Key},
+	network::{
+		p2p::{Client as P2pClient, Holding},
+		rpc::Client as RpcClient,
+	},
 	proof,
 	shutdown::Controller,
 	types::{AppClientConfig, BlockVerified, OptionBlockRange, State},
@@ -62,8 +66,10 @@ trait Client {
 
 	async fn fetch_rows_from_dht(
 		&self,
+		pp: Arc<PublicParameters>,
 		block_number: u32,
 		dimensions: Dimensions,
+		commitments: &[[u8; config::COMMITMENT_SIZE]],
 		row_indexes: &[u32],
 	) -> Vec<Option<Vec<u8>>>;
 
@@ -73,6 +79,10 @@ trait Client {
 		dimensions: Dimensions,
 		block_hash: H256,
 	) -> Result<Vec<Option<Vec<u8>>>>;
+
+	async fn insert_rows_into_dht(&self, block: u32, rows: Vec<(RowIndex, Vec<u8>)>) -> Result<()>;
+
+	async fn announce_rows(&self, block: u32, rows: Vec<u32>) -> Result<()>;
 }
 
 #[derive(Clone)]
@@ -180,12 +190,14 @@ impl Client for AppClient {
 
 	async fn fetch_rows_from_dht(
 		&self,
+		pp: Arc<PublicParameters>,
 		block_number: u32,
 		dimensions: Dimensions,
+		commitments: &[[u8; config::COMMITMENT_SIZE]],
 		row_indexes: &[u32],
 	) -> Vec<Option<Vec<u8>>> {
 		self.p2p_client
-			.fetch_rows_from_dht(block_number, dimensions, row_indexes)
+			.fetch_rows_from_dht(&pp, block_number, dimensions, commitments, row_indexes)
 			.await
 	}
 
@@ -205,6 +217,14 @@ impl Client for AppClient {
 		}
 		Ok(result)
 	}
+
+	async fn insert_rows_into_dht(&self, block: u32, rows: Vec<(RowIndex, Vec<u8>)>) -> Result<()> {
+		self.p2p_client.insert_rows_into_dht(block, rows).await
+	}
+
+	async fn announce_rows(&self, block: u32, rows: Vec<u32>) -> Result<()> {
+		self.p2p_client.announce(block, Holding::Rows(rows)).await
+	}
 }
 
 fn new_data_cell(row: usize, col: usize, data: &[u8]) -> Result<DataCell> {
@@ -259,7 +279,7 @@ async fn fetch_verified(
 	positions: &[Position],
 ) -> Result<(Vec<Cell>, Vec<Position>)> {
 	let (mut fetched, mut unfetched) = p2p_client
-		.fetch_cells_from_dht(block_number, positions)
+		.fetch_cells_from_dht(block_number, dimensions, positions)
 		.await;
 
 	let (verified, mut unverified) =
@@ -297,7 +317,7 @@ async fn process_block(
 	);
 
 	let dht_rows = client
-		.fetch_rows_from_dht(block_number, dimensions, &app_rows)
+		.fetch_rows_from_dht(pp.clone(), block_number, dimensions, commitments, &app_rows)
 		.await;
 
 	let dht_rows_count = dht_rows.iter().flatten().count();
@@ -336,6 +356,24 @@ async fn process_block(
 		missing_rows.len()
 	);
 
+	// Rows fetched from RPC are inserted into the DHT and held in the local store, so they are announced to other clients
+	let rpc_fetched_rows = rpc_verified_rows
+		.iter()
+		.filter_map(|&row| Some((RowIndex(row), rpc_rows.get(row as usize)?.clone()?)))
+		.collect::<Vec<_>>();
+	if !rpc_fetched_rows.is_empty() {
+		let row_indexes = rpc_fetched_rows.iter().map(|(row, _)| row.0).collect();
+		if let Err(error) = client
+			.insert_rows_into_dht(block_number, rpc_fetched_rows)
+			.await
+		{
+			debug!(block_number, "Error inserting rows into DHT: {error:#}");
+		}
+		if let Err(error) = client.announce_rows(block_number, row_indexes).await {
+			debug!(block_number, "Error announcing rows: {error:#}");
+		}
+	}
+
 	let verified_rows_iter = dht_verified_rows
 		.into_iter()
 		.chain(rpc_verified_rows.into_iter());
@@ -402,6 +440,72 @@ async fn process_block(
 	Ok(data)
 }
 
+/// Tracks the last received blocks of the sync and the live blocks streams,
+/// to find the blocks skipped while lagging behind the verified blocks stream.
+struct ReceivedBlocks {
+	sync_range: Range<u32>,
+	last_sync: Option<u32>,
+	last: Option<u32>,
+	sync_lagged: bool,
+	lagged: bool,
+}
+
+impl ReceivedBlocks {
+	fn new(sync_range: Range<u32>) -> Self {
+		ReceivedBlocks {
+			sync_range,
+			last_sync: None,
+			last: None,
+			sync_lagged: false,
+			lagged: false,
+		}
+	}
+
+	/// Marks both streams as lagged, since skipped messages can belong to any of them.
+	fn lagged(&mut self) {
+		self.sync_lagged = true;
+		self.lagged = true;
+	}
+
+	/// Marks the block as received, and returns the blocks of its stream
+	/// skipped since the last received block, if the stream lagged behind.
+	fn receive(&mut self, block_number: u32) -> Range<u32> {
+		let (last, lagged, first) = match self.sync_range.contains(&block_number) {
+			true => (
+				&mut self.last_sync,
+				&mut self.sync_lagged,
+				self.sync_range.start,
+			),
+			false => (&mut self.last, &mut self.lagged, self.sync_range.end),
+		};
+		let next = last.map(|last| last + 1).unwrap_or(first);
+		let skipped = match std::mem::take(lagged) {
+			true => next..block_number,
+			false => next..next,
+		};
+		*last = (*last).max(Some(block_number));
+		skipped
+	}
+}
+
+/// Creates verified block message for the block skipped by the lagging receiver.
+/// Block header is loaded from the database, or from RPC if it is not stored.
+async fn skipped_block(
+	db: &impl Database,
+	rpc_client: &RpcClient,
+	block_number: u32,
+) -> Result<BlockVerified> {
+	let header = match db
+		.get(Key::BlockHeader(block_number))
+		.wrap_err("App Client failed to get Block Header from the storage")?
+	{
+		Some(header) => header,
+		None => rpc_client.get_header_by_block_number(block_number).await?.0,
+	};
+	let confidence = data::get_confidence(db, block_number)?.map(|(confidence, _)| confidence);
+	BlockVerified::try_from((header, confidence))
+}
+
 /// Runs application client.
 ///
 /// # Arguments
@@ -444,17 +548,67 @@ pub async fn run(
 		};
 	}
 
+	fn is_data_verified(
+		state: &Arc<Mutex<State>>,
+		sync_range: &Range<u32>,
+		block_number: u32,
+	) -> bool {
+		let state = state.lock().expect("State lock can be acquired");
+		match sync_range.contains(&block_number) {
+			true => state.sync_data_verified.contains(block_number),
+			false => state.data_verified.contains(block_number),
+		}
+	}
+
+	let mut received_blocks = ReceivedBlocks::new(sync_range.clone());
+	// Blocks skipped while lagging behind the verified blocks stream, and the block received after them
+	let mut skipped_blocks = VecDeque::new();
+	let mut received_block = None;
+
 	loop {
-		let block = match block_receive.recv().await {
-			Ok(block) => block,
-			Err(error) => {
-				error!("Cannot receive message: {error}");
-				let _ = shutdown.trigger_shutdown(format!("Cannot receive message: {error:#}"));
-				return;
-			},
+		let block = if let Some(block_number) = skipped_blocks.pop_front() {
+			if is_data_verified(&state, &sync_range, block_number) {
+				continue;
+			}
+			match skipped_block(&db, &rpc_client, block_number).await {
+				Ok(block) => block,
+				Err(error) => {
+					error!(block_number, "Cannot backfill block: {error:#}");
+					continue;
+				},
+			}
+		} else if let Some(block) = received_block.take() {
+			block
+		} else {
+			match block_receive.recv().await {
+				Ok(block) => {
+					let skipped = received_blocks.receive(block.block_num);
+					if !skipped.is_empty() {
+						warn!("Backfilling skipped blocks {skipped:?}");
+						skipped_blocks.extend(skipped);
+						received_block = Some(block);
+						continue;
+					}
+					block
+				},
+				Err(RecvError::Lagged(skipped)) => {
+					warn!("App client lagged behind, {skipped} blocks skipped");
+					received_blocks.lagged();
+					continue;
+				},
+				Err(error) => {
+					error!("Cannot receive message: {error}");
+					let _ = shutdown.trigger_shutdown(format!("Cannot receive message: {error:#}"));
+					return;
+				},
+			}
 		};
 
 		let block_number = block.block_num;
+		if is_data_verified(&state, &sync_range, block_number) {
+			debug!(block_number, "Skipping already processed block");
+			continue;
+		}
 		let dimensions = &block.dimensions;
 
 		info!(block_number, "Block available: {dimensions:?}");
@@ -503,6 +657,26 @@ mod tests {
 	use hex_literal::hex;
 	use kate_recovery::{matrix::Dimensions, testnet};
 
+	#[test]
+	fn test_received_blocks() {
+		let mut received_blocks = ReceivedBlocks::new(1..10);
+		assert!(received_blocks.receive(1).is_empty());
+		assert!(received_blocks.receive(10).is_empty());
+		// Gaps are backfilled only if the stream lagged behind
+		assert!(received_blocks.receive(12).is_empty());
+
+		received_blocks.lagged();
+		assert_eq!(received_blocks.receive(15), 13..15);
+		assert_eq!(received_blocks.receive(5), 2..5);
+		assert!(received_blocks.receive(16).is_empty());
+
+		// Blocks skipped before the first received block are backfilled from the start of the stream
+		let mut received_blocks = ReceivedBlocks::new(1..10);
+		received_blocks.lagged();
+		assert_eq!(received_blocks.receive(12), 10..12);
+		assert_eq!(received_blocks.receive(3), 1..3);
+	}
+
 	#[tokio::test]
 	async fn test_process_blocks_without_rpc() {
 		let mut cfg = AppClientConfig::from(&RuntimeConfig::default());
@@ -539,16 +713,19 @@ mod tests {
 			]
 			.to_vec(),
 			confidence: None,
+			unavailability: None,
 		};
 		mock_client
 			.expect_fetch_rows_from_dht()
-			.returning(move |_, _, _| {
+			.returning(move |_, _, _, _, _| {
 				let dht_rows_clone = dht_fetched_rows.clone();
 				Box::pin(async move { dht_rows_clone })
 			});
 		if cfg.disable_rpc {
 			mock_client.expect_get_kate_rows().never();
 		}
+		mock_client.expect_insert_rows_into_dht().never();
+		mock_client.expect_announce_rows().never();
 		mock_client
 			.expect_reconstruct_rows_from_dht()
 			.returning(|_, _, _, _, _| Box::pin(async move { Ok(vec![]) }));
@@ -594,10 +771,11 @@ mod tests {
 			]
 			.to_vec(),
 			confidence: None,
+			unavailability: None,
 		};
 		mock_client
 			.expect_fetch_rows_from_dht()
-			.returning(move |_, _, _| {
+			.returning(move |_, _, _, _, _| {
 				let dht_rows_clone = dht_rows.clone();
 				Box::pin(async move { dht_rows_clone })
 			});
@@ -611,6 +789,16 @@ mod tests {
 					Box::pin(async move { Ok(kate_rows_clone) })
 				});
 		}
+		mock_client
+			.expect_insert_rows_into_dht()
+			.withf(|&block, rows| block == 288 && matches!(rows.as_slice(), [(RowIndex(0), _)]))
+			.times(1)
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
+		mock_client
+			.expect_announce_rows()
+			.withf(|&block, rows| block == 288 && *rows == [0])
+			.times(1)
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
 		mock_client
 			.expect_reconstruct_rows_from_dht()
 			.returning(|_, _, _, _, _| Box::pin(async move { Ok(vec![]) }));
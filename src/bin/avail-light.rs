@@ -4,27 +4,39 @@ use avail_core::AppId;
 use avail_light::{
 	api,
 	consts::EXPECTED_SYSTEM_VERSION,
-	data::rocks_db::RocksDB,
+	data::{
+		self,
+		mem_db::{MemoryDB, MEMORY_DB_PATH},
+		rocks_db::RocksDB,
+		Database,
+	},
+	encryption::EncryptionSecret,
 	maintenance::StaticConfigParams,
 	network::{self, p2p, rpc},
+	proof,
 	shutdown::Controller,
 	sync_client::SyncClient,
 	sync_finality::SyncFinality,
 	telemetry::{self, otlp::MetricAttributes},
-	types::{CliOpts, IdentityConfig, LibP2PConfig, RuntimeConfig, State},
+	types::{
+		CliOpts, Command, IdentityConfig, LibP2PConfig, RetentionConfig, RuntimeConfig, State,
+	},
 };
 use clap::Parser;
 use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Result,
 };
+use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
 use kate_recovery::com::AppData;
-use libp2p::{multiaddr::Protocol, Multiaddr};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 use std::{
+	collections::HashSet,
 	fs,
 	net::Ipv4Addr,
 	path::Path,
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 use tokio::sync::{broadcast, mpsc, RwLock};
 use tracing::{error, info, metadata::ParseLevelError, trace, warn, Level, Subscriber};
@@ -70,12 +82,7 @@ fn parse_log_level(log_level: &str, default: Level) -> (Level, Option<ParseLevel
 		.unwrap_or_else(|parse_err| (default, Some(parse_err)))
 }
 
-async fn run(shutdown: Controller<String>) -> Result<()> {
-	let opts = CliOpts::parse();
-
-	let mut cfg: RuntimeConfig = RuntimeConfig::default();
-	cfg.load_runtime_config(&opts)?;
-
+fn init_tracing(cfg: &RuntimeConfig) -> Option<ParseLevelError> {
 	let (log_level, parse_error) = parse_log_level(&cfg.log_level, Level::INFO);
 
 	if cfg.log_format_json {
@@ -86,8 +93,98 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			.expect("global default subscriber is set")
 	}
 
-	let identity_cfg =
-		IdentityConfig::load_or_init(&opts.identity, opts.avail_passphrase.as_deref())?;
+	parse_error
+}
+
+/// Loads configured public parameters, or the default ones if not configured.
+fn load_public_params(cfg: &RuntimeConfig) -> Result<PublicParameters> {
+	match (&cfg.public_params_file, &cfg.public_params_hash) {
+		(Some(path), Some(hash)) => {
+			let cache_path = proof::public_params_cache_path(path);
+			proof::load_public_params(path, hash, Some(&cache_path))
+				.wrap_err("Failed to load public parameters")
+		},
+		_ => Ok(kate_recovery::couscous::public_params()),
+	}
+}
+
+/// Runs a database command and exits, without starting the light client.
+async fn run_command(opts: &CliOpts, command: &Command) -> Result<()> {
+	let mut cfg: RuntimeConfig = RuntimeConfig::default();
+	cfg.load_runtime_config(opts)?;
+
+	if let Some(error) = init_tracing(&cfg) {
+		warn!("Using default log level: {}", error);
+	}
+
+	if cfg.avail_path == MEMORY_DB_PATH {
+		return Err(eyre!(
+			"Snapshot commands are not supported with in-memory database"
+		));
+	}
+
+	let encryption = EncryptionSecret::new(
+		opts.encryption_passphrase.as_deref(),
+		cfg.encryption_key_file.as_deref(),
+	)?;
+
+	match command {
+		Command::Export { from, to, file } => {
+			let db = RocksDB::open_with_encryption(&cfg.avail_path, encryption.as_ref())
+				.wrap_err("Avail Light could not initialize database")?;
+			data::snapshot::export(db, *from, *to, Path::new(file), encryption.as_ref())
+				.wrap_err("Failed to export snapshot")
+		},
+		Command::Import { file } => {
+			if opts.clean && Path::new(&cfg.avail_path).exists() {
+				info!("Cleaning up local state directory");
+				fs::remove_dir_all(&cfg.avail_path)
+					.wrap_err("Failed to remove local state directory")?;
+			}
+			let db = RocksDB::open_with_encryption(&cfg.avail_path, encryption.as_ref())
+				.wrap_err("Avail Light could not initialize database")?;
+			// Snapshot headers are verified against the blocks known by the node
+			let rpc_client = rpc::Client::new(
+				Arc::new(Mutex::new(State::default())),
+				rpc::Nodes::new(&cfg.full_node_ws),
+				&cfg.genesis_hash,
+				cfg.retry_config.clone(),
+			)
+			.await
+			.wrap_err("Failed to connect to the node")?;
+			// Imported verified cells are checked against the header commitments
+			let pp = Arc::new(load_public_params(&cfg)?);
+			data::snapshot::import(
+				&db,
+				Path::new(file),
+				encryption.as_ref(),
+				pp,
+				|block_number| rpc_client.get_block_hash(block_number),
+				|block_hash| rpc_client.fetch_set_id_at(block_hash),
+			)
+			.await
+			.map(|_| ())
+			.wrap_err("Failed to import snapshot")
+		},
+	}
+}
+
+async fn run(opts: CliOpts, shutdown: Controller<String>) -> Result<()> {
+	let mut cfg: RuntimeConfig = RuntimeConfig::default();
+	cfg.load_runtime_config(&opts)?;
+
+	let parse_error = init_tracing(&cfg);
+
+	let encryption = EncryptionSecret::new(
+		opts.encryption_passphrase.as_deref(),
+		cfg.encryption_key_file.as_deref(),
+	)?;
+
+	let identity_cfg = IdentityConfig::load_or_init(
+		&opts.identity,
+		opts.avail_passphrase.as_deref(),
+		encryption.as_ref(),
+	)?;
 	info!("Identity loaded from {}", &opts.identity);
 
 	let client_role = if cfg.is_fat_client() {
@@ -115,9 +212,35 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		Err(eyre!("Bootstrap node list must not be empty. Either use a '--network' flag or add a list of bootstrap nodes in the configuration file"))?
 	}
 
-	let db =
-		RocksDB::open(&cfg.avail_path).wrap_err("Avail Light could not initialize database")?;
+	if cfg.avail_path == MEMORY_DB_PATH {
+		info!("Using in-memory database, state will not be persisted");
+		let db = MemoryDB::default();
+		return start(cfg, identity_cfg, client_role, db, None, shutdown).await;
+	}
+
+	let db = RocksDB::open_with_encryption(&cfg.avail_path, encryption.as_ref())
+		.wrap_err("Avail Light could not initialize database")?;
+	start(
+		cfg,
+		identity_cfg,
+		client_role,
+		db.clone(),
+		Some(db),
+		shutdown,
+	)
+	.await
+}
 
+/// Starts the light client services using the given database backend.
+/// RocksDB instance is used by the Kademlia record store, if configured.
+async fn start(
+	cfg: RuntimeConfig,
+	identity_cfg: IdentityConfig,
+	client_role: &str,
+	db: impl Database + Clone + Send + Sync + 'static,
+	rocks_db: Option<RocksDB>,
+	shutdown: Controller<String>,
+) -> Result<()> {
 	let cfg_libp2p: LibP2PConfig = (&cfg).into();
 	let (id_keys, peer_id) = p2p::keypair(&cfg_libp2p)?;
 
@@ -153,11 +276,36 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	// Create sender channel for P2P event loop commands
 	let (p2p_event_loop_sender, p2p_event_loop_receiver) = mpsc::unbounded_channel();
 
+	let kad_store = p2p::Store::new(
+		&cfg_libp2p,
+		id_keys.public().to_peer_id(),
+		rocks_db.as_ref(),
+	)
+	.wrap_err("Unable to initialize Kademlia record store")?;
+
+	// Bootstrap peers are never penalised, so the client cannot be isolated from the network
+	let bootstrap_peers = cfg
+		.bootstraps
+		.iter()
+		.map(|bootstrap| <(PeerId, Multiaddr)>::from(bootstrap).0)
+		.collect::<HashSet<_>>();
+	let peer_reputation = p2p::PeerReputation::new(
+		cfg_libp2p.peer_block_threshold,
+		cfg_libp2p.peer_block_duration,
+		bootstrap_peers,
+	);
+
+	// Create queue of inbound Kademlia records waiting for validation
+	let (record_sender, record_receiver) = p2p::record_validation::channel();
+
 	let p2p_event_loop = p2p::EventLoop::new(
 		cfg_libp2p,
 		&id_keys,
+		kad_store,
 		cfg.is_fat_client(),
 		cfg.ws_transport_enable,
+		peer_reputation.clone(),
+		record_sender,
 		shutdown.clone(),
 	);
 
@@ -173,6 +321,7 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		p2p_event_loop_sender,
 		cfg.dht_parallelization_limit,
 		cfg.kad_record_ttl,
+		cfg.provider_partition_fraction,
 	);
 
 	// Start listening on provided port
@@ -202,13 +351,27 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	#[cfg(feature = "network-analysis")]
 	tokio::task::spawn(shutdown.with_cancel(analyzer::start_traffic_analyzer(cfg.port, 10)));
 
-	let pp = Arc::new(kate_recovery::couscous::public_params());
+	let pp = Arc::new(load_public_params(&cfg)?);
 	let raw_pp = pp.to_raw_var_bytes();
 	let public_params_hash = hex::encode(sp_core::blake2_128(&raw_pp));
 	let public_params_len = hex::encode(raw_pp).len();
 	trace!("Public params ({public_params_len}): hash: {public_params_hash}");
 
-	let state = Arc::new(Mutex::new(State::default()));
+	let state = data::load_state(db.clone()).wrap_err("Failed to restore state")?;
+	let restored_last = state.header_verified.as_ref().map(|range| range.last);
+	let restored_confidence = state.confidence_achieved.clone();
+	if let Some(last) = restored_last {
+		info!("State restored, last verified header is {last}");
+	}
+	let state = Arc::new(Mutex::new(state));
+
+	tokio::task::spawn(shutdown.with_delay(data::run_state_snapshots(
+		db.clone(),
+		state.clone(),
+		Duration::from_secs(cfg.state_snapshot_interval),
+		shutdown.clone(),
+	))?);
+
 	let (rpc_client, rpc_events, rpc_subscriptions) = rpc::init(
 		db.clone(),
 		state.clone(),
@@ -225,6 +388,15 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	#[cfg(feature = "crawl")]
 	let crawler_rpc_event_receiver = rpc_events.subscribe();
 
+	tokio::task::spawn(shutdown.with_cancel(p2p::record_validation::run(
+		p2p_client.clone(),
+		db.clone(),
+		pp.clone(),
+		ot_metrics.clone(),
+		rpc_events.subscribe(),
+		record_receiver,
+	)));
+
 	// spawn the RPC Network task for Event Loop to run in the background
 	// and shut it down, without delays
 	let rpc_subscriptions_handle = tokio::spawn(shutdown.with_cancel(shutdown.with_trigger(
@@ -267,7 +439,11 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		},
 	};
 
-	state.lock().unwrap().latest = block_header.number;
+	{
+		let mut state = state.lock().unwrap();
+		state.latest = block_header.number;
+		state.detach_restored_ranges(restored_last, block_header.number);
+	}
 	let sync_range = cfg.sync_range(block_header.number);
 
 	let ws_clients = api::v2::types::WsClients::default();
@@ -282,6 +458,7 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		network_version: EXPECTED_SYSTEM_VERSION[0].to_string(),
 		node_client: rpc_client.clone(),
 		ws_clients: ws_clients.clone(),
+		peer_reputation,
 		shutdown: shutdown.clone(),
 	};
 	tokio::task::spawn(shutdown.with_cancel(server.bind()));
@@ -318,6 +495,10 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		ws_clients.clone(),
 	)));
 
+	if let Some(webhook) = cfg.unavailability_webhook.clone() {
+		tokio::task::spawn(shutdown.with_cancel(api::webhook::run(webhook, block_tx.subscribe())));
+	}
+
 	if let Some(data_rx) = data_rx {
 		tokio::task::spawn(shutdown.with_cancel(api::v2::publish(
 			api::v2::types::Topic::DataVerified,
@@ -332,6 +513,7 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		tokio::task::spawn(shutdown.with_cancel(avail_light::crawl_client::run(
 			crawler_rpc_event_receiver,
 			p2p_client.clone(),
+			pp.clone(),
 			cfg.crawl.crawl_block_delay,
 			ot_metrics.clone(),
 			cfg.crawl.crawl_block_mode,
@@ -376,6 +558,17 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		s.finality_synced = true;
 	}
 
+	let retention_cfg: RetentionConfig = (&cfg).into();
+	if retention_cfg.is_enabled() {
+		tokio::task::spawn(shutdown.with_cancel(data::retention::run(
+			db.clone(),
+			retention_cfg,
+			state.clone(),
+			block_tx.subscribe(),
+			shutdown.clone(),
+		)));
+	}
+
 	let static_config_params = StaticConfigParams {
 		block_confidence_treshold: cfg.confidence,
 		replication_factor: cfg.replication_factor,
@@ -384,6 +577,7 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	};
 
 	tokio::task::spawn(shutdown.with_cancel(avail_light::maintenance::run(
+		db.clone(),
 		p2p_client.clone(),
 		ot_metrics.clone(),
 		block_rx,
@@ -409,12 +603,27 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			shutdown.clone(),
 		)));
 	} else {
+		if let Some(blocks) = restored_confidence {
+			let (db, p2p_client) = (db.clone(), p2p_client.clone());
+			let ttl = cfg.kad_record_ttl;
+			tokio::task::spawn(shutdown.with_cancel(async move {
+				let result = avail_light::light_client::reseed_dht(db, p2p_client, blocks, ttl);
+				if let Err(error) = result.await {
+					warn!("Cannot reseed verified cells into the DHT: {error:#}");
+				}
+			}));
+		}
+
+		let backfill_sync_client = SyncClient::new(db.clone(), rpc_client.clone());
 		let light_network_client = network::new(p2p_client, rpc_client, pp, cfg.disable_rpc);
 
 		tokio::task::spawn(shutdown.with_cancel(avail_light::light_client::run(
 			db.clone(),
 			light_network_client,
+			backfill_sync_client,
 			(&cfg).into(),
+			(&cfg).into(),
+			block_header.number,
 			ot_metrics,
 			state.clone(),
 			channels,
@@ -511,6 +720,11 @@ async fn user_signal() {
 
 #[tokio::main]
 pub async fn main() -> Result<()> {
+	let opts = CliOpts::parse();
+	if let Some(command) = &opts.command {
+		return run_command(&opts, command).await;
+	}
+
 	let shutdown = Controller::new();
 
 	// install custom panic hooks
@@ -519,7 +733,7 @@ pub async fn main() -> Result<()> {
 	// spawn a task to watch for ctrl-c signals from user to trigger the shutdown
 	tokio::spawn(shutdown.with_trigger("user signaled shutdown".to_string(), user_signal()));
 
-	if let Err(error) = run(shutdown.clone()).await {
+	if let Err(error) = run(opts, shutdown.clone()).await {
 		error!("{error:#}");
 		return Err(error.wrap_err("Starting Light Client failed"));
 	};
@@ -0,0 +1,163 @@
+//! Statistical models used to calculate the block confidence from the number of verified cells.
+//!
+//! * `Naive` - each sampled cell is available with probability of 0.5 if the block is unavailable,
+//!   regardless of the block matrix dimensions (confidence is `1 - 0.5^n`)
+//! * `ErasureCoded` - cells are sampled without replacement from the extended matrix with `2 * rows * cols` cells,
+//!   and the block is considered unavailable if at least `rows + 1` cells of a single column are withheld
+//!   (minimum needed to prevent reconstruction of the column from the 2x row extension)
+
+use crate::{network::rpc, utils::calculate_confidence};
+use codec::{Decode, Encode};
+use color_eyre::eyre::eyre;
+use kate_recovery::matrix::Dimensions;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Confidence model, selected with the `confidence_model` configuration parameter.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
+#[serde(try_from = "String", into = "String")]
+pub enum ConfidenceModel {
+	#[default]
+	Naive,
+	ErasureCoded,
+}
+
+impl Display for ConfidenceModel {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			ConfidenceModel::Naive => write!(f, "naive"),
+			ConfidenceModel::ErasureCoded => write!(f, "erasure-coded"),
+		}
+	}
+}
+
+impl TryFrom<String> for ConfidenceModel {
+	type Error = color_eyre::Report;
+
+	fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+		match value.to_lowercase().as_str() {
+			"naive" => Ok(ConfidenceModel::Naive),
+			"erasure-coded" => Ok(ConfidenceModel::ErasureCoded),
+			_ => Err(eyre!(
+				"Wrong confidence model. Expecting 'naive' or 'erasure-coded'."
+			)),
+		}
+	}
+}
+
+impl From<ConfidenceModel> for String {
+	fn from(model: ConfidenceModel) -> Self {
+		model.to_string()
+	}
+}
+
+impl ConfidenceModel {
+	/// Calculates number of cells required to achieve given confidence.
+	pub fn cell_count(&self, confidence: f64, dimensions: Dimensions) -> u32 {
+		match self {
+			ConfidenceModel::Naive => rpc::cell_count_for_confidence(confidence),
+			ConfidenceModel::ErasureCoded => {
+				let mut cell_count = 1;
+				while cell_count <= available_cells(dimensions)
+					&& erasure_coded_confidence(cell_count, dimensions) < confidence
+				{
+					cell_count += 1;
+				}
+				cell_count
+			},
+		}
+	}
+
+	/// Calculates confidence from given number of verified cells.
+	pub fn confidence(&self, cell_count: u32, dimensions: Dimensions) -> f64 {
+		match self {
+			ConfidenceModel::Naive => calculate_confidence(cell_count),
+			ConfidenceModel::ErasureCoded => erasure_coded_confidence(cell_count, dimensions),
+		}
+	}
+}
+
+/// Maximum number of available cells in the extended matrix of the unavailable block.
+/// Column of the extended matrix can be reconstructed from any `rows` of its `2 * rows` cells,
+/// so withholding `rows + 1` cells of a single column is enough to make the block unavailable.
+fn available_cells(dimensions: Dimensions) -> u32 {
+	let withheld = u32::from(dimensions.rows().get()) + 1;
+	dimensions.extended_size().saturating_sub(withheld)
+}
+
+/// Calculates probability that the block is available, given that all sampled cells are available.
+/// Probability of sampling only available cells from the unavailable block follows hypergeometric distribution.
+fn erasure_coded_confidence(cell_count: u32, dimensions: Dimensions) -> f64 {
+	let total = dimensions.extended_size();
+	let available = available_cells(dimensions);
+	if cell_count > available {
+		return 100f64;
+	}
+
+	let probability = (0..cell_count)
+		.map(|i| (available - i) as f64 / (total - i) as f64)
+		.product::<f64>();
+	100f64 * (1f64 - probability)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_case::test_case;
+
+	#[test_case(ConfidenceModel::Naive, 1, 4, 99.9 => 10 ; "Naive model ignores dimensions")]
+	#[test_case(ConfidenceModel::Naive, 256, 256, 99.9 => 10 ; "Naive model")]
+	#[test_case(ConfidenceModel::ErasureCoded, 1, 4, 99.9 => 7 ; "Small matrix")]
+	#[test_case(ConfidenceModel::ErasureCoded, 1, 4, 50.0 => 3 ; "Small matrix with low confidence")]
+	#[test_case(ConfidenceModel::ErasureCoded, 16, 64, 99.9 => 682 ; "Medium matrix")]
+	#[test_case(ConfidenceModel::ErasureCoded, 256, 256, 99.9 => 3473 ; "Large matrix")]
+	#[test_case(ConfidenceModel::ErasureCoded, 256, 256, 99.9999 => 6854 ; "Confidence is not clamped")]
+	fn cell_count(model: ConfidenceModel, rows: u16, cols: u16, confidence: f64) -> u32 {
+		model.cell_count(confidence, Dimensions::new(rows, cols).unwrap())
+	}
+
+	#[test]
+	fn erasure_coded_confidence() {
+		let confidence = |cell_count, rows, cols| {
+			let dimensions = Dimensions::new(rows, cols).unwrap();
+			ConfidenceModel::ErasureCoded.confidence(cell_count, dimensions)
+		};
+		let assert_close = |actual: f64, expected: f64| assert!((actual - expected).abs() < 1e-9);
+
+		// Unavailable block with 8 extended cells has 2 cells of a single column withheld, so at most 6 cells are available
+		assert_close(confidence(1, 1, 4), 100.0 * (1.0 - 6.0 / 8.0));
+		assert_close(
+			confidence(2, 1, 4),
+			100.0 * (1.0 - (6.0 * 5.0) / (8.0 * 7.0)),
+		);
+		assert_close(
+			confidence(4, 1, 4),
+			100.0 * (1.0 - (6.0 * 5.0 * 4.0 * 3.0) / (8.0 * 7.0 * 6.0 * 5.0)),
+		);
+		assert_close(confidence(6, 1, 4), 100.0 * (1.0 - 1.0 / 28.0));
+		assert_eq!(confidence(7, 1, 4), 100.0);
+
+		// Unavailable block with 2048 extended cells has 17 cells of a single column withheld
+		assert_close(confidence(1, 16, 64), 100.0 * 17.0 / 2048.0);
+		assert_close(
+			confidence(2, 16, 64),
+			100.0 * (1.0 - (2031.0 * 2030.0) / (2048.0 * 2047.0)),
+		);
+
+		// Single cell detects the withheld column with much lower probability than the naive model assumes
+		let dimensions = Dimensions::new(256, 256).unwrap();
+		assert_close(
+			ConfidenceModel::ErasureCoded.confidence(1, dimensions),
+			100.0 * 257.0 / 131072.0,
+		);
+		assert_eq!(ConfidenceModel::Naive.confidence(1, dimensions), 50.0);
+	}
+
+	#[test]
+	fn confidence_model_config() {
+		let model: ConfidenceModel = serde_json::from_str(r#""Erasure-Coded""#).unwrap();
+		assert_eq!(model, ConfidenceModel::ErasureCoded);
+		assert_eq!(serde_json::to_string(&model).unwrap(), r#""erasure-coded""#);
+		assert!(serde_json::from_str::<ConfidenceModel>(r#""binomial""#).is_err());
+	}
+}
@@ -6,6 +6,7 @@ use crate::{
 	telemetry::{MetricValue, Metrics},
 	types::{self, block_matrix_partition_format, Delay},
 };
+use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
 use kate_recovery::matrix::Partition;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -56,6 +57,7 @@ impl Default for CrawlConfig {
 pub async fn run(
 	mut message_rx: broadcast::Receiver<Event>,
 	network_client: Client,
+	pp: Arc<PublicParameters>,
 	delay: u64,
 	metrics: Arc<impl Metrics>,
 	mode: CrawlMode,
@@ -98,7 +100,7 @@ pub async fn run(
 
 			let total = positions.len();
 			let fetched = network_client
-				.fetch_cells_from_dht(block_number, &positions)
+				.fetch_cells_from_dht(block_number, block.dimensions, &positions)
 				.await
 				.0
 				.len();
@@ -119,7 +121,7 @@ pub async fn run(
 			let rows: Vec<u32> = (0..dimensions.extended_rows()).step_by(2).collect();
 			let total = rows.len();
 			let fetched = network_client
-				.fetch_rows_from_dht(block_number, dimensions, &rows)
+				.fetch_rows_from_dht(&pp, block_number, dimensions, &block.commitments, &rows)
 				.await
 				.iter()
 				.step_by(2)
@@ -1,17 +1,50 @@
+use crate::{
+	confidence::ConfidenceModel,
+	shutdown::Controller,
+	types::{BlockRange, State, StateSnapshot},
+	utils::calculate_confidence,
+};
+use avail_subxt::primitives::Header as DaHeader;
 use codec::{Decode, Encode};
-use color_eyre::eyre::Result;
+use color_eyre::{
+	eyre::{eyre, Result, WrapErr},
+	Report,
+};
+use kate_recovery::{
+	data::Cell,
+	matrix::{Dimensions, Position},
+};
 use serde::{Deserialize, Serialize};
 use sp_core::ed25519;
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tracing::{error, info};
 
+pub mod mem_db;
+pub mod retention;
 pub mod rocks_db;
+pub mod snapshot;
 
-#[cfg(test)]
-pub mod mem_db;
+/// Batch of write operations, which are applied to the database atomically.
+pub trait Batch {
+	/// Puts value for given key into the batch.
+	fn put<T>(&mut self, key: Key, value: T) -> Result<()>
+	where
+		T: Serialize + Encode;
+
+	/// Deletes value for the given key in the batch.
+	fn delete(&mut self, key: Key) -> Result<()>;
+}
 
 pub trait Database {
 	/// Type of the database key which we can get from the custom key.
 	type Key;
 
+	/// Type of the write batch supported by database.
+	type Batch: Batch;
+
 	/// Puts value for given key into database.
 	/// Key is serialized into database key, value is serialized into type supported by database.
 	fn put<T>(&self, key: Key, value: T) -> Result<()>
@@ -26,6 +59,49 @@ pub trait Database {
 
 	/// Deletes value from the database for the given key.
 	fn delete(&self, key: Key) -> Result<()>;
+
+	/// Gets all keys and values within the given inclusive range, ordered by key.
+	/// Both keys have to be of the same kind, e.g. `Key::BlockHeader(a)` and `Key::BlockHeader(b)`.
+	/// All app data of the application can be scanned with `Key::AppData(app_id, 0)` and `Key::AppData(app_id, u32::MAX)`.
+	fn get_range<T>(&self, from: Key, to: Key) -> Result<Vec<(Key, T)>>
+	where
+		for<'a> T: Deserialize<'a> + Decode;
+
+	/// Gets all keys within the given inclusive range, ordered by key.
+	/// Both keys have to be of the same kind (see [`Database::get_range`]).
+	fn get_keys(&self, from: Key, to: Key) -> Result<Vec<Key>>;
+
+	/// Creates new empty write batch.
+	fn new_batch(&self) -> Self::Batch;
+
+	/// Applies all operations from the write batch atomically.
+	fn write_batch(&self, batch: Self::Batch) -> Result<()>;
+
+	/// Returns estimated size of the data stored in the given column family, in bytes.
+	fn column_family_size(&self, column_family: &str) -> Result<u64>;
+
+	/// Returns estimated statistics of the given column family.
+	fn column_family_stats(&self, column_family: &str) -> Result<ColumnFamilyStats>;
+
+	/// Compacts the given column family, reclaiming disk space of the deleted and overwritten data.
+	fn compact(&self, column_family: &str) -> Result<()>;
+
+	/// Compacts the given inclusive range of keys, so the size estimation reflects the deleted data.
+	/// Both keys have to be of the same kind (see [`Database::get_range`]).
+	fn compact_range(&self, from: Key, to: Key) -> Result<()>;
+}
+
+/// Statistics of the column family, as estimated by the database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnFamilyStats {
+	/// Estimated number of keys
+	pub estimated_keys: u64,
+	/// Estimated size of the live data, in bytes
+	pub live_data_size: u64,
+	/// Total size of the SST files, in bytes
+	pub sst_files_size: u64,
+	/// Estimated number of bytes compaction needs to rewrite
+	pub pending_compaction_bytes: u64,
 }
 
 /// Column family for confidence factor
@@ -40,20 +116,551 @@ pub const APP_DATA_CF: &str = "avail_light_app_data_cf";
 /// Column family for state
 pub const STATE_CF: &str = "avail_light_state_cf";
 
+/// Column family for Kademlia records
+pub const KADEMLIA_STORE_CF: &str = "avail_light_kademlia_store_cf";
+
+/// Column family for verified cells
+pub const VERIFIED_CELLS_CF: &str = "avail_light_verified_cells_cf";
+
+/// Column family for finality proofs
+pub const FINALITY_PROOF_CF: &str = "avail_light_finality_proof_cf";
+
+/// Column family for confidence parameters
+pub const CONFIDENCE_PARAMS_CF: &str = "avail_light_confidence_params_cf";
+
+/// Column family for sampling audit records
+pub const SAMPLING_AUDIT_CF: &str = "avail_light_sampling_audit_cf";
+
+/// Column family for unavailability outcomes of the sampled blocks
+pub const UNAVAILABILITY_CF: &str = "avail_light_unavailability_cf";
+
+/// All column families of the database
+pub const COLUMN_FAMILIES: [&str; 10] = [
+	CONFIDENCE_FACTOR_CF,
+	BLOCK_HEADER_CF,
+	APP_DATA_CF,
+	STATE_CF,
+	KADEMLIA_STORE_CF,
+	VERIFIED_CELLS_CF,
+	FINALITY_PROOF_CF,
+	CONFIDENCE_PARAMS_CF,
+	SAMPLING_AUDIT_CF,
+	UNAVAILABILITY_CF,
+];
+
 /// Sync finality checkpoint key name
 const FINALITY_SYNC_CHECKPOINT_KEY: &str = "finality_sync_checkpoint";
 
-#[derive(Clone)]
+/// State snapshot key name
+const STATE_KEY: &str = "state";
+
+/// Database schema version key name
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// App data encryption parameters key name
+const ENCRYPTION_PARAMS_KEY: &str = "encryption_params";
+
+/// Client sampling secret key name
+const SAMPLING_SECRET_KEY: &str = "sampling_secret";
+
+/// Light client backlog key name
+const LIGHT_CLIENT_BACKLOG_KEY: &str = "light_client_backlog";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Key {
 	AppData(u32, u32),
 	BlockHeader(u32),
 	VerifiedCellCount(u32),
+	ConfidenceParams(u32),
+	VerifiedCells(u32),
+	SamplingAudit(u32),
+	Unavailability(u32),
+	FinalityProof(u32),
 	FinalitySyncCheckpoint,
+	State,
+	SchemaVersion,
+	EncryptionParams,
+	SamplingSecret,
+	/// Range of the blocks queued by the light client and not yet processed
+	LightClientBacklog,
+}
+
+impl Key {
+	/// Checks if range keys are of the same kind.
+	fn check_range(from: &Key, to: &Key) -> Result<()> {
+		if std::mem::discriminant(from) != std::mem::discriminant(to) {
+			return Err(eyre!(
+				"Range keys {from:?} and {to:?} are not of the same kind"
+			));
+		}
+		Ok(())
+	}
 }
 
-#[derive(Serialize, Deserialize, Debug, Decode, Encode)]
+#[derive(Serialize, Deserialize, Debug, Clone, Decode, Encode)]
 pub struct FinalitySyncCheckpoint {
 	pub number: u32,
 	pub set_id: u64,
 	pub validator_set: Vec<ed25519::Public>,
 }
+
+/// Confidence model and block matrix dimensions, used to calculate confidence from the verified cell count.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Decode, Encode)]
+pub struct ConfidenceParams {
+	pub model: ConfidenceModel,
+	pub rows: u16,
+	pub cols: u16,
+}
+
+impl ConfidenceParams {
+	pub fn new(model: ConfidenceModel, dimensions: Dimensions) -> Self {
+		ConfidenceParams {
+			model,
+			rows: dimensions.rows().get(),
+			cols: dimensions.cols().get(),
+		}
+	}
+}
+
+/// Gets confidence of the block, together with the model used to calculate it.
+/// Confidence of the blocks verified before confidence parameters were stored is calculated with the naive model.
+pub fn get_confidence(
+	db: &impl Database,
+	block_number: u32,
+) -> Result<Option<(f64, ConfidenceModel)>> {
+	let Some(cell_count) = db.get::<u32>(Key::VerifiedCellCount(block_number))? else {
+		return Ok(None);
+	};
+
+	let params = db.get::<ConfidenceParams>(Key::ConfidenceParams(block_number))?;
+	let confidence = params
+		.and_then(|params| Some((params.model, Dimensions::new(params.rows, params.cols)?)))
+		.map(|(model, dimensions)| (model.confidence(cell_count, dimensions), model))
+		.unwrap_or((calculate_confidence(cell_count), ConfidenceModel::Naive));
+
+	Ok(Some(confidence))
+}
+
+/// GRANDPA justification which finalized the block header, with the validator set it was verified against.
+/// Blocks without their own justification are finalized by the justification of the descendant block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct FinalityProof {
+	/// Number of the block finalized by the justification
+	pub justification_block_number: u32,
+	pub set_id: u64,
+	pub validator_set: Vec<ed25519::Public>,
+	/// SCALE encoded GRANDPA justification
+	pub justification: Vec<u8>,
+}
+
+/// Cell sampled and verified by the client, stored with its proof.
+/// Content is the cell proof (commitment size) followed by the cell data (chunk size).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct VerifiedCell {
+	pub row: u32,
+	pub col: u16,
+	pub content: Vec<u8>,
+}
+
+impl From<&Cell> for VerifiedCell {
+	fn from(cell: &Cell) -> Self {
+		VerifiedCell {
+			row: cell.position.row,
+			col: cell.position.col,
+			content: cell.content.to_vec(),
+		}
+	}
+}
+
+impl TryFrom<VerifiedCell> for Cell {
+	type Error = Report;
+
+	fn try_from(cell: VerifiedCell) -> Result<Self> {
+		let content = cell
+			.content
+			.try_into()
+			.map_err(|content: Vec<u8>| eyre!("Invalid cell content length {}", content.len()))?;
+		Ok(Cell {
+			position: Position {
+				row: cell.row,
+				col: cell.col,
+			},
+			content,
+		})
+	}
+}
+
+/// Source from which the sampled cell was fetched.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+#[serde(rename_all = "lowercase")]
+pub enum CellSource {
+	Dht,
+	Rpc,
+}
+
+/// Fetch attempt of the sampled cell.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct SampledCell {
+	pub row: u32,
+	pub col: u16,
+	/// Source from which the cell was fetched, if it was fetched at all
+	pub source: Option<CellSource>,
+	pub verified: bool,
+	/// Sampling round in which the cell was fetched
+	pub round: u32,
+}
+
+impl SampledCell {
+	pub fn new(position: Position, source: Option<CellSource>, verified: bool) -> Self {
+		SampledCell {
+			row: position.row,
+			col: position.col,
+			source,
+			verified,
+			round: 1,
+		}
+	}
+}
+
+/// Audit record of the block sampling.
+/// Sampled positions can be reproduced by seeding `ChaCha20Rng` with the seed (see [`crate::network::rpc::generate_cells`]),
+/// replacement positions of the failed cells are drawn from the same generator in the later rounds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct SamplingAudit {
+	/// Seed derived from the client sampling secret and the block hash
+	pub seed: [u8; 32],
+	/// Fetch attempts of all sampled cells, in sampling order
+	pub cells: Vec<SampledCell>,
+	pub rounds: u32,
+	/// Duration of the sampling, in milliseconds
+	pub duration_ms: u64,
+}
+
+/// Reason why the sampled block is considered unavailable.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnavailabilityReason {
+	/// Sampled cells could not be fetched within the sampling time budget
+	CellsNotFetched,
+	/// Some of the fetched cells failed the proof verification
+	ProofVerificationFailed,
+}
+
+/// Unavailability outcome of the block sampling.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+pub struct Unavailability {
+	pub reason: UnavailabilityReason,
+	/// Number of sampled cells which failed to fetch or verify
+	pub missing_cells: u32,
+}
+
+impl Unavailability {
+	/// Creates unavailability outcome from the sampled cells.
+	/// Failed verification in any round is reported, even if the cell failed to fetch in the later rounds.
+	pub fn new(samples: &[SampledCell], missing_cells: u32) -> Self {
+		let not_verified = samples
+			.iter()
+			.any(|sample| sample.source.is_some() && !sample.verified);
+		Unavailability {
+			reason: match not_verified {
+				true => UnavailabilityReason::ProofVerificationFailed,
+				false => UnavailabilityReason::CellsNotFetched,
+			},
+			missing_cells,
+		}
+	}
+}
+
+/// Loads the client sampling secret from the database, or generates and stores a new one.
+pub fn load_or_init_sampling_secret(db: &impl Database) -> Result<[u8; 32]> {
+	if let Some(secret) = db
+		.get::<[u8; 32]>(Key::SamplingSecret)
+		.wrap_err("Failed to get sampling secret")?
+	{
+		return Ok(secret);
+	}
+
+	let secret: [u8; 32] = rand::random();
+	db.put(Key::SamplingSecret, secret)
+		.wrap_err("Failed to store sampling secret")?;
+	info!("Generated new sampling secret");
+	Ok(secret)
+}
+
+fn is_stored<T>(db: &impl Database, key: Key) -> Result<bool>
+where
+	for<'a> T: Deserialize<'a> + Decode,
+{
+	db.get::<T>(key).map(|value| value.is_some())
+}
+
+/// Shrinks range to the blocks which are stored in the database at its ends,
+/// and extends it with the blocks stored after the range end.
+fn reconcile_range(
+	range: Option<BlockRange>,
+	is_stored: impl Fn(u32) -> Result<bool>,
+) -> Result<Option<BlockRange>> {
+	let Some(BlockRange {
+		mut first,
+		mut last,
+	}) = range
+	else {
+		return Ok(None);
+	};
+
+	while first <= last && !is_stored(first)? {
+		first += 1;
+	}
+	while first <= last && !is_stored(last)? {
+		if last == 0 {
+			return Ok(None);
+		}
+		last -= 1;
+	}
+	if first > last {
+		return Ok(None);
+	}
+	while last < u32::MAX && is_stored(last + 1)? {
+		last += 1;
+	}
+
+	Ok(Some(BlockRange { first, last }))
+}
+
+/// Removes blocks before the first retained block from the range.
+pub(crate) fn retain_range(range: Option<BlockRange>, first: Option<u32>) -> Option<BlockRange> {
+	let Some(first) = first else {
+		return range;
+	};
+	let range = range?;
+	(first <= range.last).then_some(BlockRange {
+		first: range.first.max(first),
+		last: range.last,
+	})
+}
+
+fn intersect_range(range: Option<BlockRange>, other: &Option<BlockRange>) -> Option<BlockRange> {
+	let (range, other) = (range?, other.as_ref()?);
+	let first = range.first.max(other.first);
+	let last = range.last.min(other.last);
+	(first <= last).then_some(BlockRange { first, last })
+}
+
+/// Loads state snapshot from the database, and reconciles its block ranges with stored data.
+/// Range is considered valid if headers of its boundary blocks are stored (and confidence, for confidence ranges).
+/// Data verified ranges are bounded by confidence ranges, since blocks without app data are not stored.
+/// Default state is returned if there is no snapshot in the database.
+pub fn load_state(db: impl Database) -> Result<State> {
+	let Some(snapshot) = db
+		.get::<StateSnapshot>(Key::State)
+		.wrap_err("Failed to get state snapshot")?
+	else {
+		return Ok(State::default());
+	};
+
+	let mut state = State::from(snapshot);
+
+	let has_header = |block_number| is_stored::<DaHeader>(&db, Key::BlockHeader(block_number));
+	let has_confidence = |block_number| is_stored::<u32>(&db, Key::VerifiedCellCount(block_number));
+
+	let retained = state.retained.clone();
+
+	state.header_verified = reconcile_range(
+		retain_range(state.header_verified.take(), retained.block_header),
+		has_header,
+	)?;
+	state.confidence_achieved = intersect_range(
+		reconcile_range(
+			retain_range(state.confidence_achieved.take(), retained.confidence),
+			has_confidence,
+		)?,
+		&state.header_verified,
+	);
+	state.data_verified = intersect_range(
+		retain_range(state.data_verified.take(), retained.app_data),
+		&state.confidence_achieved,
+	);
+
+	state.sync_header_verified = reconcile_range(
+		retain_range(state.sync_header_verified.take(), retained.block_header),
+		has_header,
+	)?;
+	state.sync_confidence_achieved = intersect_range(
+		reconcile_range(
+			retain_range(state.sync_confidence_achieved.take(), retained.confidence),
+			has_confidence,
+		)?,
+		&state.sync_header_verified,
+	);
+	state.sync_data_verified = intersect_range(
+		retain_range(state.sync_data_verified.take(), retained.app_data),
+		&state.sync_confidence_achieved,
+	);
+
+	if let Some(last) = state.header_verified.as_ref().map(|range| range.last) {
+		state.latest = state.latest.max(last);
+	}
+
+	Ok(state)
+}
+
+/// Stores snapshot of the state into the database.
+pub fn store_state(db: impl Database, state: &Mutex<State>) -> Result<()> {
+	let snapshot = {
+		let state = state
+			.lock()
+			.map_err(|error| eyre!("State mutex is poisoned: {error:#}"))?;
+		StateSnapshot::from(&*state)
+	};
+	db.put(Key::State, snapshot)
+		.wrap_err("Failed to store state snapshot")
+}
+
+/// Periodically stores state snapshot into the database.
+/// Last snapshot is stored once the shutdown is triggered.
+///
+/// # Arguments
+///
+/// * `db` - Database to store snapshot into
+/// * `state` - Shared state
+/// * `interval` - Interval between snapshots
+/// * `shutdown` - Shutdown controller
+pub async fn run_state_snapshots(
+	db: impl Database + Clone,
+	state: Arc<Mutex<State>>,
+	interval: Duration,
+	shutdown: Controller<String>,
+) {
+	info!("Starting state snapshots...");
+
+	let mut interval = tokio::time::interval(interval);
+	loop {
+		tokio::select! {
+			_ = interval.tick() => {
+				if let Err(error) = store_state(db.clone(), &state) {
+					error!("Cannot store state snapshot: {error:#}");
+				}
+			},
+			_ = shutdown.triggered_shutdown() => {
+				match store_state(db, &state) {
+					Ok(()) => info!("State snapshot stored"),
+					Err(error) => error!("Cannot store state snapshot: {error:#}"),
+				}
+				return;
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::OptionBlockRange;
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		utils::H256,
+	};
+	use std::ops::RangeInclusive;
+	use subxt::config::substrate::Digest;
+
+	fn header(number: u32) -> DaHeader {
+		DaHeader {
+			parent_hash: H256::default(),
+			number,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment::default(),
+				app_lookup: CompactDataLookup {
+					size: 0,
+					index: vec![],
+				},
+			}),
+			digest: Digest { logs: vec![] },
+		}
+	}
+
+	fn store_blocks(db: &mem_db::MemoryDB, blocks: RangeInclusive<u32>, count: bool) {
+		for block_number in blocks {
+			db.put(Key::BlockHeader(block_number), header(block_number))
+				.unwrap();
+			if count {
+				db.put(Key::VerifiedCellCount(block_number), 10u32).unwrap();
+			}
+		}
+	}
+
+	#[test]
+	fn verified_cell_conversion() {
+		let cell = Cell {
+			position: Position { row: 1, col: 2 },
+			content: [3u8; 80],
+		};
+		let verified = VerifiedCell::from(&cell);
+		let restored = Cell::try_from(verified.clone()).unwrap();
+		assert_eq!(restored.position.row, 1);
+		assert_eq!(restored.position.col, 2);
+		assert_eq!(restored.content, cell.content);
+
+		let invalid = VerifiedCell {
+			content: vec![3u8; 79],
+			..verified
+		};
+		assert!(Cell::try_from(invalid).is_err());
+	}
+
+	#[test]
+	fn load_state_without_snapshot() {
+		let db = mem_db::MemoryDB::default();
+		let state = load_state(db).unwrap();
+		assert_eq!(state.latest, 0);
+		assert!(state.header_verified.is_none());
+	}
+
+	#[test]
+	fn load_state_reconciles_ranges() {
+		let db = mem_db::MemoryDB::default();
+		store_blocks(&db, 3..=8, true);
+		store_blocks(&db, 9..=10, false);
+
+		let mut state = State {
+			latest: 9,
+			..Default::default()
+		};
+		state.header_verified = Some(BlockRange { first: 1, last: 9 });
+		state.confidence_achieved = Some(BlockRange { first: 1, last: 7 });
+		state.data_verified = Some(BlockRange { first: 1, last: 9 });
+		state.finality_synced = true;
+		store_state(db.clone(), &Mutex::new(state)).unwrap();
+
+		let state = load_state(db).unwrap();
+		assert_eq!(
+			state.header_verified,
+			Some(BlockRange { first: 3, last: 10 })
+		);
+		assert_eq!(
+			state.confidence_achieved,
+			Some(BlockRange { first: 3, last: 8 })
+		);
+		assert_eq!(state.data_verified, Some(BlockRange { first: 3, last: 8 }));
+		assert_eq!(state.latest, 10);
+		assert!(!state.finality_synced);
+	}
+
+	#[test]
+	fn load_state_drops_missing_ranges() {
+		let db = mem_db::MemoryDB::default();
+		let mut state = State::default();
+		state.sync_header_verified.set(5);
+		state.sync_confidence_achieved.set(5);
+		store_state(db.clone(), &Mutex::new(state)).unwrap();
+
+		let state = load_state(db).unwrap();
+		assert!(state.sync_header_verified.is_none());
+		assert!(state.sync_confidence_achieved.is_none());
+	}
+}
@@ -1,48 +1,87 @@
-use crate::data::{
-	Database, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, FINALITY_SYNC_CHECKPOINT_KEY,
-};
-use color_eyre::eyre::{eyre, Result};
+use crate::data::{Batch, ColumnFamilyStats, Database, Key};
+use codec::{Decode, Encode};
+use color_eyre::eyre::{Result, WrapErr};
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::HashMap,
+	collections::BTreeMap,
 	sync::{Arc, RwLock},
 };
 
-#[derive(Eq, Hash, PartialEq)]
-pub struct HashMapKey(pub String);
+/// Value of the `avail_path` configuration parameter which selects in-memory database.
+pub const MEMORY_DB_PATH: &str = ":memory:";
+
+/// Column family and binary key, same as used by RocksDB, so keys are ordered in the same way.
+#[derive(Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct HashMapKey(pub Option<&'static str>, pub Vec<u8>);
 
+/// In-memory database, for the ephemeral runs. Values are SCALE encoded, same as in RocksDB.
 #[derive(Clone)]
 pub struct MemoryDB {
-	map: Arc<RwLock<HashMap<HashMapKey, String>>>,
+	map: Arc<RwLock<BTreeMap<HashMapKey, Vec<u8>>>>,
 }
 
 impl Default for MemoryDB {
 	fn default() -> Self {
 		MemoryDB {
-			map: Arc::new(RwLock::new(HashMap::new())),
+			map: Arc::new(RwLock::new(BTreeMap::new())),
 		}
 	}
 }
 
+#[derive(Default)]
+pub struct MemoryBatch {
+	operations: Vec<(HashMapKey, Option<Vec<u8>>)>,
+}
+
+impl Batch for MemoryBatch {
+	fn put<T>(&mut self, key: Key, value: T) -> Result<()>
+	where
+		T: Serialize + Encode,
+	{
+		self.operations.push((key.into(), Some(value.encode())));
+		Ok(())
+	}
+
+	fn delete(&mut self, key: Key) -> Result<()> {
+		self.operations.push((key.into(), None));
+		Ok(())
+	}
+}
+
+impl MemoryDB {
+	fn scan(&self, from: Key, to: Key) -> Result<Vec<(Key, Vec<u8>)>> {
+		Key::check_range(&from, &to)?;
+		let map = self.map.read().expect("Lock acquired");
+		map.range(HashMapKey::from(from)..=HashMapKey::from(to))
+			.map(|(HashMapKey(column_family, key), value)| {
+				let column_family = column_family.unwrap_or_default();
+				Ok((Key::try_from((column_family, &key[..]))?, value.clone()))
+			})
+			.collect()
+	}
+}
+
 impl Database for MemoryDB {
 	type Key = HashMapKey;
+	type Batch = MemoryBatch;
+
 	fn put<T>(&self, key: Key, value: T) -> Result<()>
 	where
-		T: Serialize,
+		T: Serialize + Encode,
 	{
 		let mut map = self.map.write().expect("Lock acquired");
 
-		map.insert(key.into(), serde_json::to_string(&value)?);
+		map.insert(key.into(), value.encode());
 		Ok(())
 	}
 
 	fn get<T>(&self, key: Key) -> Result<Option<T>>
 	where
-		T: for<'a> Deserialize<'a>,
+		T: for<'a> Deserialize<'a> + Decode,
 	{
 		let map = self.map.read().expect("Lock acquired");
 		map.get(&key.into())
-			.map(|value| serde_json::from_str(value).map_err(|error| eyre!("{error}")))
+			.map(|value| <T>::decode(&mut &value[..]).wrap_err("Failed decoding the value."))
 			.transpose()
 	}
 
@@ -51,21 +90,150 @@ impl Database for MemoryDB {
 		map.remove(&key.into());
 		Ok(())
 	}
+
+	fn get_range<T>(&self, from: Key, to: Key) -> Result<Vec<(Key, T)>>
+	where
+		T: for<'a> Deserialize<'a> + Decode,
+	{
+		self.scan(from, to)?
+			.into_iter()
+			.map(|(key, value)| {
+				let value = <T>::decode(&mut &value[..]).wrap_err("Failed decoding the value.")?;
+				Ok((key, value))
+			})
+			.collect()
+	}
+
+	fn get_keys(&self, from: Key, to: Key) -> Result<Vec<Key>> {
+		Ok(self
+			.scan(from, to)?
+			.into_iter()
+			.map(|(key, _)| key)
+			.collect())
+	}
+
+	fn new_batch(&self) -> MemoryBatch {
+		MemoryBatch::default()
+	}
+
+	fn write_batch(&self, batch: MemoryBatch) -> Result<()> {
+		let mut map = self.map.write().expect("Lock acquired");
+		for (key, value) in batch.operations {
+			match value {
+				Some(value) => map.insert(key, value),
+				None => map.remove(&key),
+			};
+		}
+		Ok(())
+	}
+
+	fn column_family_size(&self, column_family: &str) -> Result<u64> {
+		let map = self.map.read().expect("Lock acquired");
+		let size = map
+			.iter()
+			.filter(|(HashMapKey(cf, _), _)| *cf == Some(column_family))
+			.map(|(HashMapKey(_, key), value)| (key.len() + value.len()) as u64)
+			.sum();
+		Ok(size)
+	}
+
+	fn column_family_stats(&self, column_family: &str) -> Result<ColumnFamilyStats> {
+		let map = self.map.read().expect("Lock acquired");
+		let (estimated_keys, live_data_size) = map
+			.iter()
+			.filter(|(HashMapKey(cf, _), _)| *cf == Some(column_family))
+			.fold((0, 0), |(keys, size), (HashMapKey(_, key), value)| {
+				(keys + 1, size + (key.len() + value.len()) as u64)
+			});
+		Ok(ColumnFamilyStats {
+			estimated_keys,
+			live_data_size,
+			..Default::default()
+		})
+	}
+
+	fn compact(&self, _: &str) -> Result<()> {
+		Ok(())
+	}
+
+	fn compact_range(&self, _: Key, _: Key) -> Result<()> {
+		Ok(())
+	}
 }
 
 impl From<Key> for HashMapKey {
 	fn from(key: Key) -> Self {
-		match key {
-			Key::AppData(app_id, block_number) => {
-				HashMapKey(format!("{APP_DATA_CF}:{app_id}:{block_number}"))
-			},
-			Key::BlockHeader(block_number) => {
-				HashMapKey(format!("{BLOCK_HEADER_CF}:{block_number}"))
-			},
-			Key::VerifiedCellCount(block_number) => {
-				HashMapKey(format!("{CONFIDENCE_FACTOR_CF}:{block_number}"))
-			},
-			Key::FinalitySyncCheckpoint => HashMapKey(FINALITY_SYNC_CHECKPOINT_KEY.to_string()),
+		let (column_family, key): (Option<&'static str>, Vec<u8>) = key.into();
+		HashMapKey(column_family, key)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn get_range() {
+		let db = MemoryDB::default();
+		for block_number in [1, 2, 256, 1000] {
+			db.put(Key::VerifiedCellCount(block_number), block_number)
+				.unwrap();
+			db.put(Key::AppData(1, block_number), vec![vec![1u8]])
+				.unwrap();
+			db.put(Key::AppData(2, block_number), vec![vec![2u8]])
+				.unwrap();
 		}
+
+		let counts = db
+			.get_range::<u32>(Key::VerifiedCellCount(2), Key::VerifiedCellCount(999))
+			.unwrap();
+		assert_eq!(
+			counts,
+			vec![
+				(Key::VerifiedCellCount(2), 2),
+				(Key::VerifiedCellCount(256), 256)
+			]
+		);
+
+		let keys = db
+			.get_keys(Key::AppData(2, 0), Key::AppData(2, u32::MAX))
+			.unwrap();
+		assert_eq!(
+			keys,
+			[1, 2, 256, 1000]
+				.into_iter()
+				.map(|block_number| Key::AppData(2, block_number))
+				.collect::<Vec<_>>()
+		);
+
+		assert!(db
+			.get_keys(Key::BlockHeader(0), Key::VerifiedCellCount(10))
+			.is_err());
+	}
+
+	#[test]
+	fn values_are_scale_encoded() {
+		let db = MemoryDB::default();
+		db.put(Key::VerifiedCellCount(1), 10u32).unwrap();
+		let map = db.map.read().unwrap();
+		assert_eq!(
+			map.get(&Key::VerifiedCellCount(1).into()),
+			Some(&10u32.encode())
+		);
+	}
+
+	#[test]
+	fn write_batch() {
+		let db = MemoryDB::default();
+		db.put(Key::VerifiedCellCount(1), 1u32).unwrap();
+
+		let mut batch = db.new_batch();
+		batch.put(Key::VerifiedCellCount(2), 2u32).unwrap();
+		batch.delete(Key::VerifiedCellCount(1)).unwrap();
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(2)).unwrap(), None);
+
+		db.write_batch(batch).unwrap();
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(1)).unwrap(), None);
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(2)).unwrap(), Some(2));
 	}
 }
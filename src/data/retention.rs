@@ -0,0 +1,385 @@
+//! Pruning of the column families according to the configured retention policies.
+
+use super::{
+	retain_range, Batch, Database, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF,
+	CONFIDENCE_PARAMS_CF, FINALITY_PROOF_CF, SAMPLING_AUDIT_CF, UNAVAILABILITY_CF,
+	VERIFIED_CELLS_CF,
+};
+use crate::{
+	shutdown::Controller,
+	types::{BlockVerified, RetentionConfig, RetentionPolicy, State},
+};
+use color_eyre::{eyre::WrapErr, Result};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+
+/// Expected block time in seconds, used to estimate the age of the blocks.
+/// Block timestamps are not stored, so the retained age drifts if the actual block time differs.
+pub(crate) const BLOCK_TIME: u64 = 20;
+
+/// Maximum number of blocks pruned from a column family in a single run.
+const MAX_PRUNED_BLOCKS: u32 = 10_000;
+
+/// While column family exceeds its size limit, one in `SIZE_LIMIT_PRUNE_FRACTION` retained blocks is pruned in a single run.
+const SIZE_LIMIT_PRUNE_FRACTION: u32 = 10;
+
+/// Returns the first block to retain, according to the retention policy.
+/// Latest block is always retained.
+fn first_retained(policy: &RetentionPolicy, first: u32, latest: u32, size: u64) -> u32 {
+	let mut until = first;
+
+	if let Some(max_blocks) = policy.max_blocks {
+		until = until.max(latest.saturating_sub(max_blocks).saturating_add(1));
+	}
+
+	// Age is approximated with the number of blocks produced at the expected block time
+	if let Some(max_age) = policy.max_age {
+		let max_blocks = u32::try_from(max_age / BLOCK_TIME).unwrap_or(u32::MAX);
+		until = until.max(latest.saturating_sub(max_blocks).saturating_add(1));
+	}
+
+	if policy
+		.max_size
+		.map(|max_size| size > max_size)
+		.unwrap_or(false)
+	{
+		let retained = latest.saturating_sub(until).saturating_add(1);
+		until = until.saturating_add((retained / SIZE_LIMIT_PRUNE_FRACTION).max(1));
+	}
+
+	until
+		.min(latest)
+		.min(first.saturating_add(MAX_PRUNED_BLOCKS))
+}
+
+/// Prunes blocks from the column family and returns the first retained block.
+fn prune(
+	db: &impl Database,
+	column_family: &str,
+	policy: &RetentionPolicy,
+	first: u32,
+	latest: u32,
+	key: impl Fn(u32) -> Key,
+) -> Result<u32> {
+	let size = match policy.max_size {
+		Some(_) => db.column_family_size(column_family)?,
+		None => 0,
+	};
+
+	let until = first_retained(policy, first, latest, size);
+	if until <= first {
+		return Ok(first);
+	}
+
+	delete_blocks(db, column_family, first, until, &key)?;
+	// Size estimation doesn't reflect deleted data until it is compacted,
+	// so pruned range is compacted to avoid pruning again on the next run
+	if policy.max_size.is_some() {
+		db.compact_range(key(first), key(until - 1))
+			.wrap_err_with(|| format!("Failed to compact pruned blocks of {column_family}"))?;
+	}
+	Ok(until)
+}
+
+/// Deletes blocks before the `until` block from the column family.
+fn delete_blocks(
+	db: &impl Database,
+	column_family: &str,
+	first: u32,
+	until: u32,
+	key: impl Fn(u32) -> Key,
+) -> Result<()> {
+	if until <= first {
+		return Ok(());
+	}
+
+	let keys = db.get_keys(key(first), key(until - 1))?;
+	let pruned = keys.len();
+	let mut batch = db.new_batch();
+	for key in keys {
+		batch.delete(key)?;
+	}
+	db.write_batch(batch)
+		.wrap_err_with(|| format!("Failed to prune blocks from {column_family}"))?;
+
+	info!(column_family, first, until, pruned, "Pruned blocks");
+	Ok(())
+}
+
+fn first_block(retained: Option<u32>, ranges: [Option<u32>; 2]) -> Option<u32> {
+	retained.or(ranges.into_iter().flatten().min())
+}
+
+/// Applies retention policies to the column families and updates the state with the retained ranges.
+pub fn apply(db: &impl Database, cfg: &RetentionConfig, state: &Mutex<State>) -> Result<()> {
+	let (latest, mut retained, headers_first, confidence_first, app_data_first, cells_first) = {
+		let state = state.lock().expect("State lock can be acquired");
+		let retained = state.retained.clone();
+		(
+			state.latest,
+			retained.clone(),
+			first_block(
+				retained.block_header,
+				[
+					state.header_verified.as_ref().map(|range| range.first),
+					state.sync_header_verified.as_ref().map(|range| range.first),
+				],
+			),
+			first_block(
+				retained.confidence,
+				[
+					state.confidence_achieved.as_ref().map(|range| range.first),
+					state
+						.sync_confidence_achieved
+						.as_ref()
+						.map(|range| range.first),
+				],
+			),
+			first_block(
+				retained.app_data,
+				[
+					state.data_verified.as_ref().map(|range| range.first),
+					state.sync_data_verified.as_ref().map(|range| range.first),
+				],
+			),
+			// Verified cells are stored together with the verified cell counts
+			first_block(
+				retained.verified_cells,
+				[
+					state.confidence_achieved.as_ref().map(|range| range.first),
+					state
+						.sync_confidence_achieved
+						.as_ref()
+						.map(|range| range.first),
+				],
+			),
+		)
+	};
+
+	if let (Some(policy), Some(first)) = (&cfg.block_header, headers_first) {
+		let until = prune(db, BLOCK_HEADER_CF, policy, first, latest, Key::BlockHeader)?;
+		// Finality proofs are retained together with the block headers
+		delete_blocks(db, FINALITY_PROOF_CF, first, until, Key::FinalityProof)?;
+		retained.block_header = Some(until);
+	}
+
+	if let (Some(policy), Some(first)) = (&cfg.confidence, confidence_first) {
+		let until = prune(
+			db,
+			CONFIDENCE_FACTOR_CF,
+			policy,
+			first,
+			latest,
+			Key::VerifiedCellCount,
+		)?;
+		// Confidence parameters are retained together with the verified cell counts
+		delete_blocks(
+			db,
+			CONFIDENCE_PARAMS_CF,
+			first,
+			until,
+			Key::ConfidenceParams,
+		)?;
+		// Unavailability outcomes are retained together with the verified cell counts
+		delete_blocks(db, UNAVAILABILITY_CF, first, until, Key::Unavailability)?;
+		retained.confidence = Some(until);
+	}
+
+	if let (Some(policy), Some(first), Some(app_id)) = (&cfg.app_data, app_data_first, cfg.app_id) {
+		let until = prune(db, APP_DATA_CF, policy, first, latest, |block_number| {
+			Key::AppData(app_id, block_number)
+		})?;
+		retained.app_data = Some(until);
+	}
+
+	if let (Some(policy), Some(first)) = (&cfg.verified_cells, cells_first) {
+		let until = prune(
+			db,
+			VERIFIED_CELLS_CF,
+			policy,
+			first,
+			latest,
+			Key::VerifiedCells,
+		)?;
+		// Sampling audit records are retained together with the verified cells
+		delete_blocks(db, SAMPLING_AUDIT_CF, first, until, Key::SamplingAudit)?;
+		retained.verified_cells = Some(until);
+	}
+
+	let mut state = state.lock().expect("State lock can be acquired");
+	state.header_verified = retain_range(state.header_verified.take(), retained.block_header);
+	state.sync_header_verified =
+		retain_range(state.sync_header_verified.take(), retained.block_header);
+	state.confidence_achieved = retain_range(state.confidence_achieved.take(), retained.confidence);
+	state.sync_confidence_achieved =
+		retain_range(state.sync_confidence_achieved.take(), retained.confidence);
+	state.data_verified = retain_range(state.data_verified.take(), retained.app_data);
+	state.sync_data_verified = retain_range(state.sync_data_verified.take(), retained.app_data);
+	state.retained = retained;
+
+	Ok(())
+}
+
+/// Runs retention policies every `pruning_interval` blocks.
+///
+/// # Arguments
+///
+/// * `db` - Database to prune
+/// * `cfg` - Retention configuration
+/// * `state` - Shared state, updated with retained ranges
+/// * `block_receiver` - Channel to receive verified blocks
+/// * `shutdown` - Shutdown controller
+pub async fn run(
+	db: impl Database,
+	cfg: RetentionConfig,
+	state: Arc<Mutex<State>>,
+	mut block_receiver: broadcast::Receiver<BlockVerified>,
+	shutdown: Controller<String>,
+) {
+	info!("Starting retention...");
+
+	loop {
+		let block_number = match block_receiver.recv().await {
+			Ok(block) => block.block_num,
+			// Retention policies are applied periodically, so skipped blocks don't need to be processed
+			Err(RecvError::Lagged(skipped)) => {
+				warn!("Retention lagged behind, {skipped} blocks skipped");
+				continue;
+			},
+			Err(error) => {
+				let _ = shutdown.trigger_shutdown(format!("{error:#}"));
+				break;
+			},
+		};
+
+		if block_number % cfg.pruning_interval != 0 {
+			continue;
+		}
+
+		info!(block_number, "Applying retention policies...");
+		if let Err(error) = apply(&db, &cfg, &state) {
+			error!(
+				block_number,
+				"Applying retention policies failed: {error:#}"
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		data::{mem_db::MemoryDB, VerifiedCell},
+		types::{BlockRange, OptionBlockRange},
+	};
+	use test_case::test_case;
+
+	fn policy(
+		max_blocks: Option<u32>,
+		max_age: Option<u64>,
+		max_size: Option<u64>,
+	) -> RetentionPolicy {
+		RetentionPolicy {
+			max_blocks,
+			max_age,
+			max_size,
+		}
+	}
+
+	#[test_case(policy(None, None, None), 1, 100, 0 => 1 ; "No limits")]
+	#[test_case(policy(Some(10), None, None), 1, 100, 0 => 91 ; "Max blocks")]
+	#[test_case(policy(Some(0), None, None), 1, 100, 0 => 100 ; "Latest is retained")]
+	#[test_case(policy(None, Some(200), None), 1, 100, 0 => 91 ; "Max age")]
+	#[test_case(policy(Some(10), Some(400), None), 1, 100, 0 => 91 ; "Stricter limit wins")]
+	#[test_case(policy(None, None, Some(10)), 1, 100, 20 => 11 ; "Size limit exceeded")]
+	#[test_case(policy(None, None, Some(10)), 1, 100, 5 => 1 ; "Size limit not exceeded")]
+	#[test_case(policy(Some(10), None, None), 1, 20_000, 0 => 10_001 ; "Pruned blocks are limited")]
+	fn first_retained_block(policy: RetentionPolicy, first: u32, latest: u32, size: u64) -> u32 {
+		first_retained(&policy, first, latest, size)
+	}
+
+	#[test]
+	fn apply_retention() {
+		let db = MemoryDB::default();
+		for block_number in 1..=10 {
+			db.put(Key::VerifiedCellCount(block_number), 10u32).unwrap();
+			db.put(Key::ConfidenceParams(block_number), 10u32).unwrap();
+			db.put(Key::Unavailability(block_number), 10u32).unwrap();
+			db.put(Key::AppData(1, block_number), vec![vec![0u8]])
+				.unwrap();
+			db.put(Key::SamplingAudit(block_number), block_number)
+				.unwrap();
+			db.put(Key::VerifiedCells(block_number), Vec::<VerifiedCell>::new())
+				.unwrap();
+			db.put(Key::BlockHeader(block_number), block_number)
+				.unwrap();
+			db.put(Key::FinalityProof(block_number), block_number)
+				.unwrap();
+		}
+
+		let mut state = State {
+			latest: 10,
+			..Default::default()
+		};
+		state.header_verified.set(1);
+		state.header_verified.set(10);
+		state.confidence_achieved.set(1);
+		state.confidence_achieved.set(10);
+		state.data_verified.set(1);
+		state.data_verified.set(10);
+		let state = Mutex::new(state);
+
+		let cfg = RetentionConfig {
+			block_header: Some(policy(Some(9), None, None)),
+			confidence: Some(policy(Some(5), None, None)),
+			app_data: Some(policy(Some(8), None, None)),
+			verified_cells: Some(policy(Some(2), None, None)),
+			app_id: Some(1),
+			pruning_interval: 1,
+		};
+		apply(&db, &cfg, &state).unwrap();
+
+		assert!(db.get::<u32>(Key::VerifiedCellCount(5)).unwrap().is_none());
+		assert!(db.get::<u32>(Key::VerifiedCellCount(6)).unwrap().is_some());
+		assert!(db.get::<u32>(Key::ConfidenceParams(5)).unwrap().is_none());
+		assert!(db.get::<u32>(Key::ConfidenceParams(6)).unwrap().is_some());
+		assert!(db.get::<u32>(Key::Unavailability(5)).unwrap().is_none());
+		assert!(db.get::<u32>(Key::Unavailability(6)).unwrap().is_some());
+		assert!(db
+			.get::<Vec<Vec<u8>>>(Key::AppData(1, 2))
+			.unwrap()
+			.is_none());
+		assert!(db
+			.get::<Vec<Vec<u8>>>(Key::AppData(1, 3))
+			.unwrap()
+			.is_some());
+		assert!(db
+			.get::<Vec<VerifiedCell>>(Key::VerifiedCells(8))
+			.unwrap()
+			.is_none());
+		assert!(db
+			.get::<Vec<VerifiedCell>>(Key::VerifiedCells(9))
+			.unwrap()
+			.is_some());
+		assert!(db.get::<u32>(Key::SamplingAudit(8)).unwrap().is_none());
+		assert!(db.get::<u32>(Key::SamplingAudit(9)).unwrap().is_some());
+
+		assert!(db.get::<u32>(Key::BlockHeader(1)).unwrap().is_none());
+		assert!(db.get::<u32>(Key::FinalityProof(1)).unwrap().is_none());
+		assert!(db.get::<u32>(Key::BlockHeader(2)).unwrap().is_some());
+		assert!(db.get::<u32>(Key::FinalityProof(2)).unwrap().is_some());
+
+		let state = state.lock().unwrap();
+		assert_eq!(state.retained.block_header, Some(2));
+		assert_eq!(state.retained.confidence, Some(6));
+		assert_eq!(state.retained.app_data, Some(3));
+		assert_eq!(state.retained.verified_cells, Some(9));
+		assert_eq!(
+			state.confidence_achieved,
+			Some(BlockRange { first: 6, last: 10 })
+		);
+		assert_eq!(state.data_verified, Some(BlockRange { first: 3, last: 10 }));
+	}
+}
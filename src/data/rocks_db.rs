@@ -1,32 +1,63 @@
-use crate::data::{self, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, STATE_CF};
+use crate::data::{
+	self, ColumnFamilyStats, Key, APP_DATA_CF, BLOCK_HEADER_CF, COLUMN_FAMILIES,
+	CONFIDENCE_FACTOR_CF, CONFIDENCE_PARAMS_CF, FINALITY_PROOF_CF, SAMPLING_AUDIT_CF, STATE_CF,
+	UNAVAILABILITY_CF, VERIFIED_CELLS_CF,
+};
 use codec::{Decode, Encode};
-use color_eyre::eyre::{eyre, Context, Result};
-use rocksdb::{ColumnFamilyDescriptor, Options};
+use color_eyre::{
+	eyre::{eyre, Context, Result},
+	Report,
+};
+use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use super::FINALITY_SYNC_CHECKPOINT_KEY;
+use super::{
+	ENCRYPTION_PARAMS_KEY, FINALITY_SYNC_CHECKPOINT_KEY, LIGHT_CLIENT_BACKLOG_KEY,
+	SAMPLING_SECRET_KEY, SCHEMA_VERSION_KEY, STATE_KEY,
+};
+use crate::encryption::{EncryptionKey, EncryptionSecret};
+
+mod encryption;
+mod migrations;
+
+pub use migrations::SCHEMA_VERSION;
 
 #[derive(Clone)]
 pub struct RocksDB {
 	db: Arc<rocksdb::DB>,
+	/// Key used to encrypt app data, if encryption is enabled
+	encryption: Option<Arc<EncryptionKey>>,
 }
 
 impl RocksDB {
 	pub fn open(path: &str) -> Result<RocksDB> {
-		let cf_opts = vec![
-			ColumnFamilyDescriptor::new(CONFIDENCE_FACTOR_CF, Options::default()),
-			ColumnFamilyDescriptor::new(BLOCK_HEADER_CF, Options::default()),
-			ColumnFamilyDescriptor::new(APP_DATA_CF, Options::default()),
-			ColumnFamilyDescriptor::new(STATE_CF, Options::default()),
-		];
+		Self::open_with_encryption(path, None)
+	}
+
+	/// Opens the database, encrypting app data with the key obtained from the given secret.
+	/// Existing app data is encrypted when the database is opened with encryption for the first time.
+	pub fn open_with_encryption(path: &str, secret: Option<&EncryptionSecret>) -> Result<RocksDB> {
+		let cf_opts = COLUMN_FAMILIES
+			.map(|column_family| ColumnFamilyDescriptor::new(column_family, Options::default()));
 
 		let mut db_opts = Options::default();
 		db_opts.create_if_missing(true);
 		db_opts.create_missing_column_families(true);
 
 		let db = rocksdb::DB::open_cf_descriptors(&db_opts, path, cf_opts)?;
-		Ok(RocksDB { db: Arc::new(db) })
+		let mut db = RocksDB {
+			db: Arc::new(db),
+			encryption: None,
+		};
+		migrations::run(&db)?;
+		db.encryption = encryption::setup(&db, secret)?.map(Arc::new);
+		Ok(db)
+	}
+
+	/// Returns the underlying RocksDB instance, shared with the Kademlia record store.
+	pub(crate) fn inner(&self) -> Arc<rocksdb::DB> {
+		self.db.clone()
 	}
 }
 
@@ -37,7 +68,7 @@ impl From<Key> for (Option<&'static str>, Vec<u8>) {
 		match key {
 			Key::AppData(app_id, block_number) => (
 				Some(APP_DATA_CF),
-				format!("{app_id}:{block_number}").into_bytes(),
+				[app_id.to_be_bytes(), block_number.to_be_bytes()].concat(),
 			),
 			Key::BlockHeader(block_number) => {
 				(Some(BLOCK_HEADER_CF), block_number.to_be_bytes().to_vec())
@@ -46,16 +77,170 @@ impl From<Key> for (Option<&'static str>, Vec<u8>) {
 				Some(CONFIDENCE_FACTOR_CF),
 				block_number.to_be_bytes().to_vec(),
 			),
+			Key::ConfidenceParams(block_number) => (
+				Some(CONFIDENCE_PARAMS_CF),
+				block_number.to_be_bytes().to_vec(),
+			),
+			Key::VerifiedCells(block_number) => {
+				(Some(VERIFIED_CELLS_CF), block_number.to_be_bytes().to_vec())
+			},
+			Key::SamplingAudit(block_number) => {
+				(Some(SAMPLING_AUDIT_CF), block_number.to_be_bytes().to_vec())
+			},
+			Key::Unavailability(block_number) => {
+				(Some(UNAVAILABILITY_CF), block_number.to_be_bytes().to_vec())
+			},
+			Key::FinalityProof(block_number) => {
+				(Some(FINALITY_PROOF_CF), block_number.to_be_bytes().to_vec())
+			},
 			Key::FinalitySyncCheckpoint => (
 				Some(STATE_CF),
 				FINALITY_SYNC_CHECKPOINT_KEY.as_bytes().to_vec(),
 			),
+			Key::State => (Some(STATE_CF), STATE_KEY.as_bytes().to_vec()),
+			Key::SchemaVersion => (Some(STATE_CF), SCHEMA_VERSION_KEY.as_bytes().to_vec()),
+			Key::EncryptionParams => (Some(STATE_CF), ENCRYPTION_PARAMS_KEY.as_bytes().to_vec()),
+			Key::SamplingSecret => (Some(STATE_CF), SAMPLING_SECRET_KEY.as_bytes().to_vec()),
+			Key::LightClientBacklog => {
+				(Some(STATE_CF), LIGHT_CLIENT_BACKLOG_KEY.as_bytes().to_vec())
+			},
 		}
 	}
 }
 
+impl TryFrom<(&str, &[u8])> for Key {
+	type Error = Report;
+
+	fn try_from((column_family, key): (&str, &[u8])) -> Result<Self> {
+		fn decode_u32(bytes: &[u8]) -> Result<u32> {
+			Ok(u32::from_be_bytes(bytes.try_into()?))
+		}
+
+		match column_family {
+			APP_DATA_CF if key.len() == 8 => {
+				Ok(Key::AppData(decode_u32(&key[..4])?, decode_u32(&key[4..])?))
+			},
+			BLOCK_HEADER_CF => Ok(Key::BlockHeader(decode_u32(key)?)),
+			CONFIDENCE_FACTOR_CF => Ok(Key::VerifiedCellCount(decode_u32(key)?)),
+			CONFIDENCE_PARAMS_CF => Ok(Key::ConfidenceParams(decode_u32(key)?)),
+			VERIFIED_CELLS_CF => Ok(Key::VerifiedCells(decode_u32(key)?)),
+			FINALITY_PROOF_CF => Ok(Key::FinalityProof(decode_u32(key)?)),
+			SAMPLING_AUDIT_CF => Ok(Key::SamplingAudit(decode_u32(key)?)),
+			UNAVAILABILITY_CF => Ok(Key::Unavailability(decode_u32(key)?)),
+			STATE_CF if key == FINALITY_SYNC_CHECKPOINT_KEY.as_bytes() => {
+				Ok(Key::FinalitySyncCheckpoint)
+			},
+			STATE_CF if key == STATE_KEY.as_bytes() => Ok(Key::State),
+			STATE_CF if key == SCHEMA_VERSION_KEY.as_bytes() => Ok(Key::SchemaVersion),
+			STATE_CF if key == ENCRYPTION_PARAMS_KEY.as_bytes() => Ok(Key::EncryptionParams),
+			STATE_CF if key == SAMPLING_SECRET_KEY.as_bytes() => Ok(Key::SamplingSecret),
+			STATE_CF if key == LIGHT_CLIENT_BACKLOG_KEY.as_bytes() => Ok(Key::LightClientBacklog),
+			_ => Err(eyre!("Unknown key in column family {column_family}")),
+		}
+	}
+}
+
+impl RocksDB {
+	fn scan(&self, from: Key, to: Key) -> Result<Vec<(Key, Box<[u8]>)>> {
+		Key::check_range(&from, &to)?;
+		let ((Some(cf), from), (_, to)): (RocksKey, RocksKey) = (from.into(), to.into()) else {
+			return Err(eyre!("Range scan is supported only on Column Families"));
+		};
+
+		let cf_handle = self
+			.db
+			.cf_handle(cf)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+
+		let mut result = vec![];
+		let iterator = self
+			.db
+			.iterator_cf(&cf_handle, IteratorMode::From(&from, Direction::Forward));
+		for item in iterator {
+			let (key, value) = item.wrap_err("Iteration with Column Family failed on RocksDB")?;
+			if *key > *to {
+				break;
+			}
+			result.push((Key::try_from((cf, &key[..]))?, value));
+		}
+		Ok(result)
+	}
+}
+
+/// Encodes the value, and encrypts it if it belongs to the encrypted column family.
+fn encode_value<T: Encode>(
+	encryption: &Option<Arc<EncryptionKey>>,
+	column_family: &str,
+	value: &T,
+) -> Result<Vec<u8>> {
+	match encryption {
+		Some(key) if column_family == APP_DATA_CF => key.encrypt(&value.encode()),
+		_ => Ok(value.encode()),
+	}
+}
+
+/// Decrypts the value if it belongs to the encrypted column family, and decodes it.
+fn decode_value<T: Decode>(
+	encryption: &Option<Arc<EncryptionKey>>,
+	column_family: &str,
+	value: &[u8],
+) -> Result<T> {
+	let decrypted;
+	let value = match encryption {
+		Some(key) if column_family == APP_DATA_CF => {
+			decrypted = key.decrypt(value)?;
+			&decrypted[..]
+		},
+		_ => value,
+	};
+	<T>::decode(&mut &value[..]).wrap_err("Failed decoding the value.")
+}
+
+pub struct RocksBatch {
+	db: Arc<rocksdb::DB>,
+	encryption: Option<Arc<EncryptionKey>>,
+	batch: rocksdb::WriteBatch,
+}
+
+impl data::Batch for RocksBatch {
+	fn put<T>(&mut self, key: Key, value: T) -> Result<()>
+	where
+		T: Serialize + Encode,
+	{
+		let (column_family, key) = key.into();
+		let Some(cf) = column_family else {
+			self.batch.put(key, <T>::encode(&value));
+			return Ok(());
+		};
+
+		let cf_handle = self
+			.db
+			.cf_handle(cf)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		self.batch
+			.put_cf(&cf_handle, key, encode_value(&self.encryption, cf, &value)?);
+		Ok(())
+	}
+
+	fn delete(&mut self, key: Key) -> Result<()> {
+		let (column_family, key) = key.into();
+		let Some(cf) = column_family else {
+			self.batch.delete(key);
+			return Ok(());
+		};
+
+		let cf_handle = self
+			.db
+			.cf_handle(cf)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		self.batch.delete_cf(&cf_handle, key);
+		Ok(())
+	}
+}
+
 impl data::Database for RocksDB {
 	type Key = RocksKey;
+	type Batch = RocksBatch;
 
 	fn put<T>(&self, key: Key, value: T) -> Result<()>
 	where
@@ -76,7 +261,7 @@ impl data::Database for RocksDB {
 			.cf_handle(cf)
 			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
 		self.db
-			.put_cf(&cf_handle, key, <T>::encode(&value))
+			.put_cf(&cf_handle, key, encode_value(&self.encryption, cf, &value)?)
 			.wrap_err("Put operation with Column Family failed on RocksDB")
 	}
 
@@ -103,7 +288,7 @@ impl data::Database for RocksDB {
 
 		self.db
 			.get_cf(&cf_handle, key)?
-			.map(|value| <T>::decode(&mut &value[..]).wrap_err("Failed decoding the app data."))
+			.map(|value| decode_value(&self.encryption, cf, &value))
 			.transpose()
 			.wrap_err("Get operation with Column Family failed on RocksDB")
 	}
@@ -126,4 +311,98 @@ impl data::Database for RocksDB {
 			.delete_cf(&cf_handle, key)
 			.wrap_err("Delete operation with Column Family failed on RocksDB")
 	}
+
+	fn get_range<T>(&self, from: Key, to: Key) -> Result<Vec<(Key, T)>>
+	where
+		T: for<'a> Deserialize<'a> + Decode,
+	{
+		let (column_family, _): RocksKey = from.clone().into();
+		let column_family = column_family.unwrap_or_default();
+		self.scan(from, to)?
+			.into_iter()
+			.map(|(key, value)| {
+				let value = decode_value(&self.encryption, column_family, &value)?;
+				Ok((key, value))
+			})
+			.collect()
+	}
+
+	fn get_keys(&self, from: Key, to: Key) -> Result<Vec<Key>> {
+		Ok(self
+			.scan(from, to)?
+			.into_iter()
+			.map(|(key, _)| key)
+			.collect())
+	}
+
+	fn new_batch(&self) -> RocksBatch {
+		RocksBatch {
+			db: self.db.clone(),
+			encryption: self.encryption.clone(),
+			batch: rocksdb::WriteBatch::default(),
+		}
+	}
+
+	fn write_batch(&self, batch: RocksBatch) -> Result<()> {
+		self.db
+			.write(batch.batch)
+			.wrap_err("Write batch operation failed on RocksDB")
+	}
+
+	fn column_family_size(&self, column_family: &str) -> Result<u64> {
+		let cf_handle = self
+			.db
+			.cf_handle(column_family)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		self.db
+			.property_int_value_cf(&cf_handle, "rocksdb.estimate-live-data-size")
+			.map(|size| size.unwrap_or(0))
+			.wrap_err("Size estimation of Column Family failed on RocksDB")
+	}
+
+	fn column_family_stats(&self, column_family: &str) -> Result<ColumnFamilyStats> {
+		let cf_handle = self
+			.db
+			.cf_handle(column_family)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		let property = |name: &str| {
+			self.db
+				.property_int_value_cf(&cf_handle, name)
+				.map(|value| value.unwrap_or(0))
+				.wrap_err_with(|| {
+					format!("Getting property {name} of Column Family failed on RocksDB")
+				})
+		};
+		Ok(ColumnFamilyStats {
+			estimated_keys: property("rocksdb.estimate-num-keys")?,
+			live_data_size: property("rocksdb.estimate-live-data-size")?,
+			sst_files_size: property("rocksdb.total-sst-files-size")?,
+			pending_compaction_bytes: property("rocksdb.estimate-pending-compaction-bytes")?,
+		})
+	}
+
+	fn compact(&self, column_family: &str) -> Result<()> {
+		let cf_handle = self
+			.db
+			.cf_handle(column_family)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		self.db
+			.compact_range_cf(&cf_handle, None::<&[u8]>, None::<&[u8]>);
+		Ok(())
+	}
+
+	fn compact_range(&self, from: Key, to: Key) -> Result<()> {
+		Key::check_range(&from, &to)?;
+		let ((Some(cf), from), (_, to)): (RocksKey, RocksKey) = (from.into(), to.into()) else {
+			return Err(eyre!(
+				"Range compaction is supported only on Column Families"
+			));
+		};
+		let cf_handle = self
+			.db
+			.cf_handle(cf)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		self.db.compact_range_cf(&cf_handle, Some(from), Some(to));
+		Ok(())
+	}
 }
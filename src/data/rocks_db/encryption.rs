@@ -0,0 +1,151 @@
+//! Encryption of the app data column family.
+//!
+//! Encryption parameters are stored in the state column family. Check value encrypted with the
+//! configured key is used to detect invalid passphrase or key file before any data is read.
+//! App data stored before encryption was enabled is encrypted in place, and encryption is
+//! resumed on the next startup if it was interrupted.
+
+use super::RocksDB;
+use crate::{
+	data::{Database, Key, APP_DATA_CF},
+	encryption::{self, EncryptionKey, EncryptionSecret},
+};
+use codec::{Decode, Encode};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use rocksdb::{IteratorMode, WriteBatch};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Plaintext of the check value.
+const CHECK_VALUE: &[u8] = b"avail-light-encryption-check";
+
+/// Number of app data records encrypted in a single write batch.
+const ENCRYPTION_BATCH_SIZE: usize = 1000;
+
+/// Encryption parameters stored in the database.
+#[derive(Serialize, Deserialize, Decode, Encode)]
+struct EncryptionParams {
+	/// Salt used for the key derivation from the passphrase
+	salt: Vec<u8>,
+	/// Check value encrypted with the key
+	check: Vec<u8>,
+	/// Set once the app data stored before encryption was enabled is encrypted
+	app_data_encrypted: bool,
+}
+
+/// Returns the app data encryption key, if encryption is configured.
+/// Database with encrypted app data is refused if encryption secret is missing or invalid.
+pub fn setup(db: &RocksDB, secret: Option<&EncryptionSecret>) -> Result<Option<EncryptionKey>> {
+	let params = db
+		.get::<EncryptionParams>(Key::EncryptionParams)
+		.wrap_err("Failed to get encryption parameters")?;
+
+	let (params, secret) = match (params, secret) {
+		(None, None) => return Ok(None),
+		(Some(_), None) => {
+			return Err(eyre!(
+				"App data is encrypted. Provide encryption passphrase or key file, or remove the database using --clean flag"
+			))
+		},
+		(params, Some(secret)) => (params, secret),
+	};
+
+	let mut params = match params {
+		Some(params) => params,
+		None => {
+			let salt = encryption::generate_salt().to_vec();
+			let check = secret.key(&salt).encrypt(CHECK_VALUE)?;
+			let params = EncryptionParams {
+				salt,
+				check,
+				app_data_encrypted: false,
+			};
+			db.put(Key::EncryptionParams, &params)
+				.wrap_err("Failed to store encryption parameters")?;
+			params
+		},
+	};
+
+	let key = secret.key(&params.salt);
+	if key.decrypt(&params.check).ok().as_deref() != Some(CHECK_VALUE) {
+		return Err(eyre!(
+			"Encryption passphrase or key file does not match the one used to encrypt app data"
+		));
+	}
+
+	if !params.app_data_encrypted {
+		encrypt_app_data(&db.db, &key)?;
+		params.app_data_encrypted = true;
+		db.put(Key::EncryptionParams, &params)
+			.wrap_err("Failed to store encryption parameters")?;
+	}
+
+	Ok(Some(key))
+}
+
+/// Encrypts the app data which is not encrypted with the given key.
+fn encrypt_app_data(db: &rocksdb::DB, key: &EncryptionKey) -> Result<()> {
+	let cf_handle = db
+		.cf_handle(APP_DATA_CF)
+		.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+
+	let mut encrypted = 0;
+	let mut batch = WriteBatch::default();
+	for item in db.iterator_cf(&cf_handle, IteratorMode::Start) {
+		let (app_data_key, value) =
+			item.wrap_err("Iteration with Column Family failed on RocksDB")?;
+		// Skip records encrypted before the interruption
+		if key.decrypt(&value).is_ok() {
+			continue;
+		}
+		batch.put_cf(&cf_handle, app_data_key, key.encrypt(&value)?);
+		encrypted += 1;
+
+		if batch.len() >= ENCRYPTION_BATCH_SIZE {
+			db.write(std::mem::take(&mut batch))
+				.wrap_err("Write batch operation failed on RocksDB")?;
+			info!(encrypted, "Encrypting app data...");
+		}
+	}
+	db.write(batch)
+		.wrap_err("Write batch operation failed on RocksDB")?;
+
+	info!(encrypted, "App data encrypted");
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::test_utils::TempDir;
+
+	#[test]
+	fn app_data_is_encrypted() {
+		let path = TempDir::new();
+		let secret = EncryptionSecret::Key([1u8; 32]);
+		{
+			let db = RocksDB::open(&path.0).unwrap();
+			db.put(Key::AppData(1, 1), vec![vec![1u8]]).unwrap();
+		}
+		{
+			let db = RocksDB::open_with_encryption(&path.0, Some(&secret)).unwrap();
+			db.put(Key::AppData(1, 2), vec![vec![2u8]]).unwrap();
+			for (block_number, data) in [(1, 1u8), (2, 2u8)] {
+				assert_eq!(
+					db.get::<Vec<Vec<u8>>>(Key::AppData(1, block_number))
+						.unwrap(),
+					Some(vec![vec![data]])
+				);
+			}
+
+			let (_, key): (Option<&'static str>, Vec<u8>) = Key::AppData(1, 2).into();
+			let cf_handle = db.db.cf_handle(APP_DATA_CF).unwrap();
+			let stored = db.db.get_cf(&cf_handle, key).unwrap().unwrap();
+			assert_ne!(stored, vec![vec![2u8]].encode());
+		}
+
+		assert!(RocksDB::open(&path.0).is_err());
+		let other_secret = EncryptionSecret::Key([2u8; 32]);
+		assert!(RocksDB::open_with_encryption(&path.0, Some(&other_secret)).is_err());
+	}
+}
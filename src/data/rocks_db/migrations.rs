@@ -0,0 +1,257 @@
+//! Versioning of the on-disk database schema and migrations between schema versions.
+//!
+//! Schema version is stored in the state column family. Database without schema version is
+//! either freshly created, or created before versioning was introduced (version 0).
+//! Migrations are applied in order at startup, and schema version is stored after each migration,
+//! so interrupted upgrade continues from the last successfully applied migration.
+
+use super::RocksDB;
+use crate::data::{Database, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, STATE_CF};
+use codec::Encode;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use rocksdb::{IteratorMode, WriteBatch};
+use tracing::info;
+
+/// Current version of the database schema.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Number of records migrated in a single write batch.
+const MIGRATION_BATCH_SIZE: usize = 1000;
+
+struct Migration {
+	/// Schema version after migration is applied
+	version: u32,
+	description: &'static str,
+	migrate: fn(&rocksdb::DB) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+	Migration {
+		version: 1,
+		description: "Use big-endian binary app data keys",
+		migrate: migrate_app_data_keys,
+	},
+	Migration {
+		version: 2,
+		description: "Add retained verified cells to the state snapshot",
+		migrate: migrate_state_retained_cells,
+	},
+];
+
+fn is_empty(db: &rocksdb::DB) -> Result<bool> {
+	for cf in [CONFIDENCE_FACTOR_CF, BLOCK_HEADER_CF, APP_DATA_CF, STATE_CF] {
+		let cf_handle = db
+			.cf_handle(cf)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		if db
+			.iterator_cf(&cf_handle, IteratorMode::Start)
+			.next()
+			.is_some()
+		{
+			return Ok(false);
+		}
+	}
+	Ok(true)
+}
+
+/// Checks database schema version and applies pending migrations.
+/// Database with schema version newer than supported is refused.
+pub fn run(db: &RocksDB) -> Result<()> {
+	let version = match db.get::<u32>(Key::SchemaVersion)? {
+		Some(version) => version,
+		None if is_empty(&db.db)? => {
+			return db
+				.put(Key::SchemaVersion, SCHEMA_VERSION)
+				.wrap_err("Failed to store database schema version");
+		},
+		None => 0,
+	};
+
+	if version > SCHEMA_VERSION {
+		return Err(eyre!(
+			"Database schema version {version} is not supported (supported version is {SCHEMA_VERSION}). Upgrade the light client or remove the database using --clean flag"
+		));
+	}
+
+	if version == SCHEMA_VERSION {
+		return Ok(());
+	}
+
+	info!("Migrating database schema from version {version} to {SCHEMA_VERSION}...");
+	for migration in MIGRATIONS
+		.iter()
+		.filter(|migration| migration.version > version)
+	{
+		info!(
+			version = migration.version,
+			"Applying migration: {}", migration.description
+		);
+		(migration.migrate)(&db.db).wrap_err_with(|| {
+			format!("Database migration to version {} failed", migration.version)
+		})?;
+		db.put(Key::SchemaVersion, migration.version)
+			.wrap_err("Failed to store database schema version")?;
+		info!(version = migration.version, "Migration applied");
+	}
+
+	Ok(())
+}
+
+fn parse_legacy_app_data_key(key: &[u8]) -> Option<(u32, u32)> {
+	let (app_id, block_number) = std::str::from_utf8(key).ok()?.split_once(':')?;
+	Some((app_id.parse().ok()?, block_number.parse().ok()?))
+}
+
+/// Migrates app data keys from `"{app_id}:{block_number}"` strings to big-endian binary keys.
+fn migrate_app_data_keys(db: &rocksdb::DB) -> Result<()> {
+	let cf_handle = db
+		.cf_handle(APP_DATA_CF)
+		.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+
+	let mut legacy_keys = vec![];
+	for item in db.iterator_cf(&cf_handle, IteratorMode::Start) {
+		let (key, _) = item.wrap_err("Iteration with Column Family failed on RocksDB")?;
+		if let Some(parsed) = parse_legacy_app_data_key(&key) {
+			legacy_keys.push((key, parsed));
+		}
+	}
+
+	let total = legacy_keys.len();
+	let mut migrated = 0;
+	for chunk in legacy_keys.chunks(MIGRATION_BATCH_SIZE) {
+		let mut batch = WriteBatch::default();
+		for (legacy_key, (app_id, block_number)) in chunk {
+			let Some(value) = db.get_cf(&cf_handle, legacy_key)? else {
+				continue;
+			};
+			let (_, key): (Option<&'static str>, Vec<u8>) =
+				Key::AppData(*app_id, *block_number).into();
+			batch.put_cf(&cf_handle, key, value);
+			batch.delete_cf(&cf_handle, legacy_key);
+		}
+		db.write(batch)
+			.wrap_err("Write batch operation failed on RocksDB")?;
+
+		migrated += chunk.len();
+		info!(migrated, total, "Migrated app data keys");
+	}
+
+	Ok(())
+}
+
+/// Appends the first retained verified cells block (`None`) to the stored state snapshot.
+/// Retained blocks are the last field of the snapshot, so the new optional field is encoded at the end.
+fn migrate_state_retained_cells(db: &rocksdb::DB) -> Result<()> {
+	let cf_handle = db
+		.cf_handle(STATE_CF)
+		.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+
+	let (_, key): (Option<&'static str>, Vec<u8>) = Key::State.into();
+	let Some(mut value) = db.get_cf(&cf_handle, &key)? else {
+		return Ok(());
+	};
+	value.extend(None::<u32>.encode());
+	db.put_cf(&cf_handle, key, value)
+		.wrap_err("Put operation with Column Family failed on RocksDB")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		types::{Retained, StateSnapshot},
+		utils::test_utils::TempDir,
+	};
+
+	#[test]
+	fn new_database_has_current_version() {
+		let path = TempDir::new();
+		let db = RocksDB::open(&path.0).unwrap();
+		assert_eq!(
+			db.get::<u32>(Key::SchemaVersion).unwrap(),
+			Some(SCHEMA_VERSION)
+		);
+	}
+
+	#[test]
+	fn newer_version_is_refused() {
+		let path = TempDir::new();
+		{
+			let db = RocksDB::open(&path.0).unwrap();
+			db.put(Key::SchemaVersion, SCHEMA_VERSION + 1).unwrap();
+		}
+		assert!(RocksDB::open(&path.0).is_err());
+	}
+
+	#[test]
+	fn legacy_app_data_keys_are_migrated() {
+		let path = TempDir::new();
+		{
+			let db = RocksDB::open(&path.0).unwrap();
+			db.delete(Key::SchemaVersion).unwrap();
+			let cf_handle = db.db.cf_handle(APP_DATA_CF).unwrap();
+			db.db
+				.put_cf(&cf_handle, "1:10", codec::Encode::encode(&vec![vec![1u8]]))
+				.unwrap();
+		}
+
+		let db = RocksDB::open(&path.0).unwrap();
+		assert_eq!(
+			db.get::<u32>(Key::SchemaVersion).unwrap(),
+			Some(SCHEMA_VERSION)
+		);
+		assert_eq!(
+			db.get::<Vec<Vec<u8>>>(Key::AppData(1, 10)).unwrap(),
+			Some(vec![vec![1u8]])
+		);
+		let cf_handle = db.db.cf_handle(APP_DATA_CF).unwrap();
+		assert!(db.db.get_cf(&cf_handle, "1:10").unwrap().is_none());
+	}
+
+	#[test]
+	fn state_snapshot_is_migrated() {
+		#[derive(Encode)]
+		struct LegacyRetained {
+			block_header: Option<u32>,
+			confidence: Option<u32>,
+			app_data: Option<u32>,
+		}
+
+		let path = TempDir::new();
+		{
+			let db = RocksDB::open(&path.0).unwrap();
+			db.put(Key::SchemaVersion, 1u32).unwrap();
+			let snapshot = StateSnapshot {
+				latest: 10,
+				..Default::default()
+			};
+			let mut value = snapshot.encode();
+			let retained_len = Retained::default().encode().len();
+			value.truncate(value.len() - retained_len);
+			value.extend(
+				LegacyRetained {
+					block_header: Some(5),
+					confidence: None,
+					app_data: Some(7),
+				}
+				.encode(),
+			);
+			let (_, key): (Option<&'static str>, Vec<u8>) = Key::State.into();
+			let cf_handle = db.db.cf_handle(STATE_CF).unwrap();
+			db.db.put_cf(&cf_handle, key, value).unwrap();
+		}
+
+		let db = RocksDB::open(&path.0).unwrap();
+		let snapshot = db.get::<StateSnapshot>(Key::State).unwrap().unwrap();
+		assert_eq!(snapshot.latest, 10);
+		assert_eq!(
+			snapshot.retained,
+			Retained {
+				block_header: Some(5),
+				confidence: None,
+				app_data: Some(7),
+				verified_cells: None,
+			}
+		);
+	}
+}
@@ -0,0 +1,1110 @@
+//! Export and import of the block range as a portable snapshot file.
+//!
+//! Snapshot file consists of the magic bytes, snapshot format version (little-endian `u32`),
+//! SCALE encoded [`Payload`] and `blake2_256` checksum of all preceding bytes.
+//! If encryption at rest is enabled, snapshot is encrypted with the configured encryption secret.
+//! Imported snapshot is verified before anything is written to the database:
+//! header hashes are recomputed and checked against the parent hashes,
+//! hash of the latest header is checked against the block hash reported by the node,
+//! finality checkpoint is checked against the latest authority set change in the verified headers
+//! and against the set ID reported by the node, verified cell proofs are checked against the header commitments,
+//! and finality proofs are checked against the validator sets derived from the checkpoint.
+//! Finality proofs which cannot be verified (blocks before the checkpoint set change) are not imported.
+
+use super::{
+	intersect_range, load_state, Batch, ConfidenceParams, Database, FinalityProof,
+	FinalitySyncCheckpoint, Key, SamplingAudit, VerifiedCell,
+};
+use crate::{
+	encryption::{generate_salt, EncryptionSecret, SALT_SIZE},
+	finality::{check_finality, ValidatorSet},
+	proof,
+	types::{BlockRange, GrandpaJustification, OptionBlockRange, StateSnapshot},
+	utils::{extract_kate, filter_auth_set_changes},
+};
+use avail_subxt::{primitives::Header as DaHeader, utils::H256};
+use codec::{Decode, Encode};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
+use kate_recovery::{commitments, data::Cell, matrix::Dimensions};
+use serde::Deserialize;
+use sp_core::{blake2_256, ed25519};
+use std::{
+	collections::{HashMap, HashSet},
+	fs,
+	future::Future,
+	path::Path,
+	sync::Arc,
+};
+use tracing::{info, warn};
+
+/// Magic bytes at the beginning of the snapshot file.
+const MAGIC: &[u8; 8] = b"AVLSNAPS";
+
+/// Version of the snapshot file format.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+const CHECKSUM_LEN: usize = 32;
+
+#[derive(Debug, Encode, Decode)]
+struct Snapshot {
+	range: BlockRange,
+	/// Headers of all blocks in range, with their hashes
+	headers: Vec<(H256, DaHeader)>,
+	confidence_achieved: Option<BlockRange>,
+	/// Verified cell counts of the blocks in confidence range
+	cell_counts: Vec<(u32, u32)>,
+	/// Confidence parameters of the blocks in confidence range
+	confidence_params: Vec<(u32, ConfidenceParams)>,
+	/// Verified cells of the blocks in confidence range
+	verified_cells: Vec<(u32, Vec<VerifiedCell>)>,
+	/// Sampling audit records of the blocks in confidence range
+	sampling_audits: Vec<(u32, SamplingAudit)>,
+	data_verified: Option<BlockRange>,
+	/// App data as `(app_id, block_number, data)`
+	app_data: Vec<(u32, u32, Vec<Vec<u8>>)>,
+	/// Finality proofs of the blocks in range
+	finality_proofs: Vec<(u32, FinalityProof)>,
+	finality_checkpoint: Option<FinalitySyncCheckpoint>,
+}
+
+#[derive(Debug, Encode, Decode)]
+enum Payload {
+	Plain(Snapshot),
+	/// SCALE encoded snapshot, encrypted with the key derived using the salt
+	Encrypted {
+		salt: [u8; SALT_SIZE],
+		data: Vec<u8>,
+	},
+}
+
+fn header_hash(header: &DaHeader) -> H256 {
+	Encode::using_encoded(header, blake2_256).into()
+}
+
+fn encode(snapshot: Snapshot, encryption: Option<&EncryptionSecret>) -> Result<Vec<u8>> {
+	let payload = match encryption {
+		Some(secret) => {
+			let salt = generate_salt();
+			let data = secret.key(&salt).encrypt(&snapshot.encode())?;
+			Payload::Encrypted { salt, data }
+		},
+		None => Payload::Plain(snapshot),
+	};
+
+	let mut bytes = MAGIC.to_vec();
+	bytes.extend(SNAPSHOT_VERSION.to_le_bytes());
+	payload.encode_to(&mut bytes);
+	let checksum = blake2_256(&bytes);
+	bytes.extend(checksum);
+	Ok(bytes)
+}
+
+fn decode(bytes: &[u8], encryption: Option<&EncryptionSecret>) -> Result<Snapshot> {
+	let header_len = MAGIC.len() + 4;
+	if bytes.len() < header_len + CHECKSUM_LEN || !bytes.starts_with(MAGIC) {
+		return Err(eyre!("File is not a light client snapshot"));
+	}
+
+	let version = u32::from_le_bytes(bytes[MAGIC.len()..header_len].try_into()?);
+	if version != SNAPSHOT_VERSION {
+		return Err(eyre!(
+			"Snapshot version {version} is not supported (supported version is {SNAPSHOT_VERSION})"
+		));
+	}
+
+	let (content, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+	if blake2_256(content) != checksum {
+		return Err(eyre!("Snapshot checksum mismatch"));
+	}
+
+	let payload =
+		Payload::decode(&mut &content[header_len..]).wrap_err("Failed to decode snapshot")?;
+	match (payload, encryption) {
+		(Payload::Plain(snapshot), _) => Ok(snapshot),
+		(Payload::Encrypted { salt, data }, Some(secret)) => {
+			let data = secret
+				.key(&salt)
+				.decrypt(&data)
+				.wrap_err("Failed to decrypt snapshot")?;
+			Snapshot::decode(&mut &data[..]).wrap_err("Failed to decode snapshot")
+		},
+		(Payload::Encrypted { .. }, None) => Err(eyre!(
+			"Snapshot is encrypted, encryption passphrase or key file has to be provided"
+		)),
+	}
+}
+
+/// Gets values stored for the blocks in range, with their block numbers.
+fn get_blocks<T>(
+	db: &impl Database,
+	range: &Option<BlockRange>,
+	key: impl Fn(u32) -> Key,
+) -> Result<Vec<(u32, T)>>
+where
+	for<'a> T: Deserialize<'a> + Decode,
+{
+	let Some(BlockRange { first, last }) = range else {
+		return Ok(vec![]);
+	};
+	(*first..=*last)
+		.filter_map(|block_number| {
+			db.get::<T>(key(block_number))
+				.map(|value| value.map(|value| (block_number, value)))
+				.transpose()
+		})
+		.collect()
+}
+
+/// Returns the first block number which is not within the range.
+fn find_outside<T>(blocks: &[(u32, T)], range: &Option<BlockRange>) -> Option<u32> {
+	blocks
+		.iter()
+		.map(|(block_number, _)| *block_number)
+		.find(|block_number| !range.contains(*block_number))
+}
+
+fn is_within(range: &Option<BlockRange>, outer: &BlockRange) -> bool {
+	range
+		.as_ref()
+		.map(|range| range.first >= outer.first && range.last <= outer.last)
+		.unwrap_or(true)
+}
+
+/// Verifies that headers are a chain of the blocks in range, and that stored data belongs to them.
+fn verify(snapshot: &Snapshot) -> Result<()> {
+	let BlockRange { first, last } = snapshot.range;
+	if first > last || snapshot.headers.len() as u64 != u64::from(last - first) + 1 {
+		return Err(eyre!(
+			"Snapshot headers don't match block range {first}-{last}"
+		));
+	}
+
+	let mut parent_hash = None;
+	for (expected, (hash, header)) in (first..=last).zip(&snapshot.headers) {
+		if header.number != expected {
+			return Err(eyre!(
+				"Expected header {expected}, found header {}",
+				header.number
+			));
+		}
+		if header_hash(header) != *hash {
+			return Err(eyre!("Hash mismatch for header {expected}"));
+		}
+		if parent_hash.is_some_and(|parent_hash| parent_hash != header.parent_hash) {
+			return Err(eyre!("Parent hash mismatch for header {expected}"));
+		}
+		parent_hash = Some(*hash);
+	}
+
+	if !is_within(&snapshot.confidence_achieved, &snapshot.range) {
+		return Err(eyre!("Confidence range is outside of the block range"));
+	}
+	let confidence_blocks = snapshot
+		.confidence_achieved
+		.as_ref()
+		.map(|range| range.last - range.first + 1)
+		.unwrap_or(0);
+	let counts_match = snapshot.cell_counts.len() as u32 == confidence_blocks
+		&& snapshot
+			.cell_counts
+			.iter()
+			.all(|(block_number, _)| snapshot.confidence_achieved.contains(*block_number));
+	if !counts_match {
+		return Err(eyre!("Verified cell counts don't match confidence range"));
+	}
+	let confidence_achieved = &snapshot.confidence_achieved;
+	if let Some(block_number) = find_outside(&snapshot.confidence_params, confidence_achieved)
+		.or(find_outside(&snapshot.verified_cells, confidence_achieved))
+		.or(find_outside(&snapshot.sampling_audits, confidence_achieved))
+	{
+		return Err(eyre!(
+			"Sampling data of block {block_number} is outside of confidence range"
+		));
+	}
+
+	if !is_within(&snapshot.data_verified, &snapshot.range) {
+		return Err(eyre!("Data verified range is outside of the block range"));
+	}
+	if let Some((_, block_number, _)) = snapshot
+		.app_data
+		.iter()
+		.find(|(_, block_number, _)| !snapshot.data_verified.contains(*block_number))
+	{
+		return Err(eyre!(
+			"App data of block {block_number} is outside of data verified range"
+		));
+	}
+
+	if let Some(block_number) =
+		find_outside(&snapshot.finality_proofs, &Some(snapshot.range.clone()))
+	{
+		return Err(eyre!(
+			"Finality proof of block {block_number} is outside of the block range"
+		));
+	}
+
+	Ok(())
+}
+
+/// Checks that the latest snapshot header is the block known by the node.
+/// Since headers are linked by their parent hashes, this verifies all snapshot headers.
+fn verify_latest_hash(snapshot: &Snapshot, block_hash: H256) -> Result<()> {
+	let Some((hash, header)) = snapshot.headers.last() else {
+		return Err(eyre!("Snapshot doesn't contain any headers"));
+	};
+	if *hash != block_hash {
+		return Err(eyre!(
+			"Hash of header {} doesn't match the block hash {block_hash:?} reported by the node",
+			header.number
+		));
+	}
+	Ok(())
+}
+
+/// Returns the validator set scheduled by the authority set change in the header, if any.
+fn scheduled_validator_set(header: &DaHeader) -> Option<Vec<ed25519::Public>> {
+	filter_auth_set_changes(header).first().map(|authorities| {
+		authorities
+			.iter()
+			.map(|a| ed25519::Public::from_raw(a.0 .0 .0 .0))
+			.collect::<Vec<_>>()
+	})
+}
+
+/// Checks that checkpoint validator set is scheduled by the latest authority set change
+/// in the snapshot headers preceding the checkpoint, and that checkpoint set ID matches
+/// the set ID returned by `set_id_at` for that header.
+/// Returns number of the block which scheduled the checkpoint validator set.
+async fn verify_checkpoint<S, Fut>(
+	snapshot: &Snapshot,
+	checkpoint: &FinalitySyncCheckpoint,
+	set_id_at: S,
+) -> Result<u32>
+where
+	S: FnOnce(H256) -> Fut,
+	Fut: Future<Output = Result<u64>>,
+{
+	if checkpoint.validator_set.is_empty() {
+		return Err(eyre!(
+			"Finality checkpoint at block {} has empty validator set",
+			checkpoint.number
+		));
+	}
+
+	let preceding = checkpoint.number.saturating_sub(snapshot.range.first) as usize;
+	let Some((hash, header, validator_set)) = snapshot
+		.headers
+		.iter()
+		.take(preceding)
+		.rev()
+		.find_map(|(hash, header)| Some((hash, header, scheduled_validator_set(header)?)))
+	else {
+		return Err(eyre!(
+			"Finality checkpoint at block {} cannot be verified, no authority set change in snapshot headers preceding it",
+			checkpoint.number
+		));
+	};
+
+	if validator_set != checkpoint.validator_set {
+		return Err(eyre!(
+			"Finality checkpoint at block {} doesn't match authority set change in block {}",
+			checkpoint.number,
+			header.number
+		));
+	}
+
+	let set_id = set_id_at(*hash)
+		.await
+		.wrap_err("Failed to get set ID from the node")?;
+	if set_id != checkpoint.set_id {
+		return Err(eyre!(
+			"Finality checkpoint set ID {} doesn't match set ID {set_id} at block {} reported by the node",
+			checkpoint.set_id,
+			header.number
+		));
+	}
+	Ok(header.number)
+}
+
+/// Verifies proofs of the verified cells against the header commitments,
+/// and checks that verified cell counts and confidence parameters match the cells and the headers.
+async fn verify_cells(snapshot: &Snapshot, pp: Arc<PublicParameters>) -> Result<()> {
+	// Sampling data is within the confidence range, so headers are present (see `verify`)
+	let header =
+		|block_number: u32| &snapshot.headers[(block_number - snapshot.range.first) as usize].1;
+	let verified_cells = snapshot
+		.verified_cells
+		.iter()
+		.map(|(block_number, cells)| (*block_number, cells))
+		.collect::<HashMap<_, _>>();
+
+	for (block_number, count) in &snapshot.cell_counts {
+		let (rows, cols, _, commitment) = extract_kate(&header(*block_number).extension);
+		let dimensions = Dimensions::new(rows, cols)
+			.ok_or_else(|| eyre!("Invalid dimensions of block {block_number}"))?;
+
+		let cells = verified_cells
+			.get(block_number)
+			.map(|cells| {
+				cells
+					.iter()
+					.cloned()
+					.map(Cell::try_from)
+					.collect::<Result<Vec<_>>>()
+			})
+			.transpose()?
+			.unwrap_or_default();
+		if cells.len() != *count as usize {
+			return Err(eyre!(
+				"Verified cell count of block {block_number} doesn't match its verified cells"
+			));
+		}
+		let positions = cells
+			.iter()
+			.map(|cell| &cell.position)
+			.collect::<HashSet<_>>();
+		if positions.len() != cells.len() {
+			return Err(eyre!("Duplicate verified cells of block {block_number}"));
+		}
+		if positions.iter().any(|position| {
+			position.row >= dimensions.extended_rows() || position.col >= dimensions.cols().get()
+		}) {
+			return Err(eyre!(
+				"Verified cells of block {block_number} are outside of the block matrix"
+			));
+		}
+
+		let commitments = commitments::from_slice(&commitment)?;
+		let (_, unverified) =
+			proof::verify(*block_number, dimensions, &cells, &commitments, pp.clone()).await?;
+		if !unverified.is_empty() {
+			return Err(eyre!(
+				"{} verified cells of block {block_number} have invalid proofs",
+				unverified.len()
+			));
+		}
+	}
+
+	for (block_number, params) in &snapshot.confidence_params {
+		let (rows, cols, _, _) = extract_kate(&header(*block_number).extension);
+		if params.rows != rows || params.cols != cols {
+			return Err(eyre!(
+				"Confidence parameters of block {block_number} don't match its header"
+			));
+		}
+	}
+	Ok(())
+}
+
+fn verify_finality_proof(
+	validator_set: &ValidatorSet,
+	hash: H256,
+	block_number: u32,
+	proof: &FinalityProof,
+) -> Result<()> {
+	if proof.set_id != validator_set.set_id || proof.validator_set != validator_set.validator_set {
+		return Err(eyre!("Validator set doesn't match the finality checkpoint"));
+	}
+	let justification = GrandpaJustification::decode(&mut &proof.justification[..])
+		.wrap_err("Failed to decode justification")?;
+	if justification.commit.target_hash != hash
+		|| justification.commit.target_number != block_number
+	{
+		return Err(eyre!("Justification doesn't target the block"));
+	}
+	check_finality(validator_set, &justification)
+}
+
+/// Verifies finality proofs of the blocks following the authority set change which scheduled
+/// the checkpoint validator set. Validator sets of those blocks are derived from the checkpoint
+/// and the following authority set changes in the snapshot headers.
+/// Returns verified finality proofs, proofs of the other blocks cannot be verified.
+fn verify_finality_proofs(
+	snapshot: &Snapshot,
+	checkpoint: Option<(&FinalitySyncCheckpoint, u32)>,
+) -> Result<Vec<(u32, FinalityProof)>> {
+	let Some((checkpoint, scheduled_at)) = checkpoint else {
+		return Ok(vec![]);
+	};
+	let proofs = snapshot
+		.finality_proofs
+		.iter()
+		.map(|(block_number, proof)| (*block_number, proof))
+		.collect::<HashMap<_, _>>();
+
+	let mut validator_set = ValidatorSet {
+		set_id: checkpoint.set_id,
+		validator_set: checkpoint.validator_set.clone(),
+	};
+	let mut verified = vec![];
+	for (hash, header) in snapshot
+		.headers
+		.iter()
+		.filter(|(_, header)| header.number > scheduled_at)
+	{
+		if let Some(proof) = proofs.get(&header.number) {
+			verify_finality_proof(&validator_set, *hash, header.number, proof)
+				.wrap_err_with(|| format!("Invalid finality proof of block {}", header.number))?;
+			verified.push((header.number, (*proof).clone()));
+		}
+		// Changed validator set applies to the blocks after the block which scheduled it
+		if let Some(next) = scheduled_validator_set(header) {
+			validator_set = ValidatorSet {
+				set_id: validator_set.set_id + 1,
+				validator_set: next,
+			};
+		}
+	}
+	Ok(verified)
+}
+
+/// Exports blocks in the given inclusive range from the database into the snapshot file.
+/// Headers of all blocks in range have to be stored.
+/// Snapshot is encrypted if the encryption secret is provided, so decrypted app data is not written to disk.
+pub fn export(
+	db: impl Database + Clone,
+	from: u32,
+	to: u32,
+	path: &Path,
+	encryption: Option<&EncryptionSecret>,
+) -> Result<()> {
+	if from > to {
+		return Err(eyre!("Invalid block range {from}-{to}"));
+	}
+	let range = BlockRange {
+		first: from,
+		last: to,
+	};
+
+	let headers = (from..=to)
+		.map(|block_number| {
+			db.get::<DaHeader>(Key::BlockHeader(block_number))?
+				.map(|header| (header_hash(&header), header))
+				.ok_or_else(|| eyre!("Header of block {block_number} is not stored"))
+		})
+		.collect::<Result<Vec<_>>>()?;
+
+	let state = load_state(db.clone()).wrap_err("Failed to load state")?;
+	let within_range = |live: Option<BlockRange>, sync: Option<BlockRange>| {
+		let range = Some(range.clone());
+		intersect_range(live, &range).or(intersect_range(sync, &range))
+	};
+	let confidence_achieved =
+		within_range(state.confidence_achieved, state.sync_confidence_achieved);
+	let data_verified = within_range(state.data_verified, state.sync_data_verified);
+
+	let cell_counts = get_blocks(&db, &confidence_achieved, Key::VerifiedCellCount)?;
+	let confidence_params = get_blocks(&db, &confidence_achieved, Key::ConfidenceParams)?;
+	let verified_cells = get_blocks(&db, &confidence_achieved, Key::VerifiedCells)?;
+	let sampling_audits = get_blocks(&db, &confidence_achieved, Key::SamplingAudit)?;
+	let finality_proofs = get_blocks(&db, &Some(range.clone()), Key::FinalityProof)?;
+
+	let app_data = db
+		.get_range::<Vec<Vec<u8>>>(Key::AppData(0, 0), Key::AppData(u32::MAX, u32::MAX))?
+		.into_iter()
+		.filter_map(|(key, data)| match key {
+			Key::AppData(app_id, block_number) if data_verified.contains(block_number) => {
+				Some((app_id, block_number, data))
+			},
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+
+	let finality_checkpoint = db
+		.get::<FinalitySyncCheckpoint>(Key::FinalitySyncCheckpoint)
+		.wrap_err("Failed to get finality checkpoint")?;
+
+	let snapshot = Snapshot {
+		range,
+		headers,
+		confidence_achieved,
+		cell_counts,
+		confidence_params,
+		verified_cells,
+		sampling_audits,
+		data_verified,
+		app_data,
+		finality_proofs,
+		finality_checkpoint,
+	};
+	verify(&snapshot).wrap_err("Exported snapshot is not valid")?;
+
+	let (cell_counts, app_data) = (snapshot.cell_counts.len(), snapshot.app_data.len());
+	fs::write(path, encode(snapshot, encryption)?)
+		.wrap_err_with(|| format!("Failed to write snapshot to {}", path.display()))?;
+
+	info!(
+		from,
+		to,
+		cell_counts,
+		app_data,
+		encrypted = encryption.is_some(),
+		"Snapshot exported to {}",
+		path.display()
+	);
+	Ok(())
+}
+
+/// Imports snapshot file into the empty database, after verifying its content.
+/// Encrypted snapshot is decrypted with the given encryption secret.
+/// Hash of the latest snapshot header is checked against the hash returned by `block_hash` for its block number,
+/// finality checkpoint set ID is checked against the set ID returned by `set_id_at` for the block hash
+/// which scheduled the checkpoint validator set, and verified cells are checked with the public parameters.
+/// State snapshot is stored as well, so imported ranges are restored on startup.
+pub async fn import<F, Fut, S, SFut>(
+	db: &impl Database,
+	path: &Path,
+	encryption: Option<&EncryptionSecret>,
+	pp: Arc<PublicParameters>,
+	block_hash: F,
+	set_id_at: S,
+) -> Result<BlockRange>
+where
+	F: FnOnce(u32) -> Fut,
+	Fut: Future<Output = Result<H256>>,
+	S: FnOnce(H256) -> SFut,
+	SFut: Future<Output = Result<u64>>,
+{
+	if db
+		.get::<StateSnapshot>(Key::State)
+		.wrap_err("Failed to get state snapshot")?
+		.is_some()
+	{
+		return Err(eyre!(
+			"Snapshot can be imported only into an empty database"
+		));
+	}
+
+	let bytes = fs::read(path)
+		.wrap_err_with(|| format!("Failed to read snapshot from {}", path.display()))?;
+	let snapshot = decode(&bytes, encryption)?;
+	verify(&snapshot).wrap_err("Snapshot verification failed")?;
+
+	let latest_hash = block_hash(snapshot.range.last)
+		.await
+		.wrap_err("Failed to get the latest snapshot block hash from the node")?;
+	verify_latest_hash(&snapshot, latest_hash).wrap_err("Snapshot verification failed")?;
+
+	let checkpoint = match &snapshot.finality_checkpoint {
+		Some(checkpoint) => {
+			let scheduled_at = verify_checkpoint(&snapshot, checkpoint, set_id_at)
+				.await
+				.wrap_err("Snapshot verification failed")?;
+			Some((checkpoint, scheduled_at))
+		},
+		None => None,
+	};
+	verify_cells(&snapshot, pp)
+		.await
+		.wrap_err("Snapshot verification failed")?;
+	let finality_proofs =
+		verify_finality_proofs(&snapshot, checkpoint).wrap_err("Snapshot verification failed")?;
+	if finality_proofs.len() < snapshot.finality_proofs.len() {
+		warn!(
+			"Skipping {} finality proofs which cannot be verified against the finality checkpoint",
+			snapshot.finality_proofs.len() - finality_proofs.len()
+		);
+	}
+
+	let mut batch = db.new_batch();
+	for (_, header) in &snapshot.headers {
+		batch.put(Key::BlockHeader(header.number), header.clone())?;
+	}
+	for (block_number, count) in &snapshot.cell_counts {
+		batch.put(Key::VerifiedCellCount(*block_number), *count)?;
+	}
+	for (block_number, params) in &snapshot.confidence_params {
+		batch.put(Key::ConfidenceParams(*block_number), *params)?;
+	}
+	for (block_number, cells) in &snapshot.verified_cells {
+		batch.put(Key::VerifiedCells(*block_number), cells.clone())?;
+	}
+	for (block_number, audit) in &snapshot.sampling_audits {
+		batch.put(Key::SamplingAudit(*block_number), audit.clone())?;
+	}
+	for (app_id, block_number, data) in &snapshot.app_data {
+		batch.put(Key::AppData(*app_id, *block_number), data.clone())?;
+	}
+	for (block_number, proof) in finality_proofs {
+		batch.put(Key::FinalityProof(block_number), proof)?;
+	}
+
+	if let Some(checkpoint) = &snapshot.finality_checkpoint {
+		batch.put(Key::FinalitySyncCheckpoint, checkpoint.clone())?;
+	}
+
+	let state = StateSnapshot {
+		latest: snapshot.range.last,
+		header_verified: Some(snapshot.range.clone()),
+		confidence_achieved: snapshot.confidence_achieved.clone(),
+		data_verified: snapshot.data_verified.clone(),
+		..Default::default()
+	};
+	batch.put(Key::State, state)?;
+
+	db.write_batch(batch)
+		.wrap_err("Failed to write snapshot into the database")?;
+
+	info!(
+		from = snapshot.range.first,
+		to = snapshot.range.last,
+		cell_counts = snapshot.cell_counts.len(),
+		app_data = snapshot.app_data.len(),
+		"Snapshot imported from {}",
+		path.display()
+	);
+	Ok(snapshot.range)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		confidence::ConfidenceModel,
+		data::{get_confidence, mem_db::MemoryDB},
+		types::{Commit, Precommit, SignedPrecommit, SignerMessage},
+	};
+	use avail_subxt::api::runtime_types::avail_core::{
+		data_lookup::compact::CompactDataLookup,
+		header::extension::{v3, HeaderExtension},
+		kate_commitment::v3::KateCommitment,
+	};
+	use kate_recovery::testnet;
+	use sp_core::Pair;
+	use std::env;
+	use subxt::config::substrate::{Digest, DigestItem};
+	use uuid::Uuid;
+
+	/// Compressed BLS12-381 G1 identity, commitment to the zero polynomial
+	const IDENTITY: [u8; 48] = {
+		let mut bytes = [0u8; 48];
+		bytes[0] = 0xc0;
+		bytes
+	};
+
+	/// Header of the single row block with zero data
+	fn header(number: u32, parent_hash: H256, logs: Vec<DigestItem>) -> DaHeader {
+		DaHeader {
+			parent_hash,
+			number,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment {
+					rows: 1,
+					cols: 4,
+					data_root: H256::default(),
+					commitment: [IDENTITY, IDENTITY].concat(),
+				},
+				app_lookup: CompactDataLookup {
+					size: 0,
+					index: vec![],
+				},
+			}),
+			digest: Digest { logs },
+		}
+	}
+
+	/// Scheduled authority set change to the single validator, encoded as GRANDPA consensus log
+	fn set_change(public: ed25519::Public) -> DigestItem {
+		// `ConsensusLog::ScheduledChange` variant with authorities and delay
+		let log = (1u8, vec![(public, 1u64)], 0u32).encode();
+		DigestItem::Consensus(*b"FRNK", log)
+	}
+
+	fn finality_proof(pair: &ed25519::Pair, set_id: u64, hash: H256, number: u32) -> FinalityProof {
+		let precommit = Precommit {
+			target_hash: hash,
+			target_number: number,
+		};
+		let message = Encode::encode(&(
+			&SignerMessage::PrecommitMessage(precommit.clone()),
+			&1u64,
+			&set_id,
+		));
+		let justification = GrandpaJustification {
+			round: 1,
+			commit: Commit {
+				target_hash: hash,
+				target_number: number,
+				precommits: vec![SignedPrecommit {
+					precommit,
+					signature: pair.sign(&message),
+					id: pair.public(),
+				}],
+			},
+			votes_ancestries: vec![],
+		};
+		FinalityProof {
+			justification_block_number: number,
+			set_id,
+			validator_set: vec![pair.public()],
+			justification: justification.encode(),
+		}
+	}
+
+	fn store_chain(db: &MemoryDB, first: u32, last: u32) {
+		store_chain_with_logs(db, first, last, |_| vec![]);
+	}
+
+	fn store_chain_with_logs(
+		db: &MemoryDB,
+		first: u32,
+		last: u32,
+		logs: impl Fn(u32) -> Vec<DigestItem>,
+	) {
+		let mut parent_hash = H256::default();
+		for block_number in first..=last {
+			let header = header(block_number, parent_hash, logs(block_number));
+			parent_hash = header_hash(&header);
+			db.put(Key::BlockHeader(block_number), header).unwrap();
+			db.put(Key::VerifiedCellCount(block_number), 1u32).unwrap();
+			db.put(Key::AppData(1, block_number), vec![vec![1u8]])
+				.unwrap();
+			let params = ConfidenceParams {
+				model: ConfidenceModel::ErasureCoded,
+				rows: 1,
+				cols: 4,
+			};
+			db.put(Key::ConfidenceParams(block_number), params).unwrap();
+			let cells = vec![VerifiedCell {
+				row: 0,
+				col: 1,
+				content: [IDENTITY.to_vec(), vec![0u8; 32]].concat(),
+			}];
+			db.put(Key::VerifiedCells(block_number), cells).unwrap();
+			let audit = SamplingAudit {
+				seed: [3u8; 32],
+				cells: vec![],
+				rounds: 1,
+				duration_ms: 100,
+			};
+			db.put(Key::SamplingAudit(block_number), audit).unwrap();
+			let proof = FinalityProof {
+				justification_block_number: block_number,
+				set_id: 1,
+				validator_set: vec![],
+				justification: vec![4u8],
+			};
+			db.put(Key::FinalityProof(block_number), proof).unwrap();
+		}
+		let state = StateSnapshot {
+			latest: last,
+			header_verified: Some(BlockRange { first, last }),
+			confidence_achieved: Some(BlockRange { first, last }),
+			data_verified: Some(BlockRange { first, last }),
+			..Default::default()
+		};
+		db.put(Key::State, state).unwrap();
+	}
+
+	fn snapshot_path() -> std::path::PathBuf {
+		env::temp_dir().join(format!("avail_light_snapshot_{}", Uuid::new_v4()))
+	}
+
+	fn stored_hash(db: &MemoryDB, block_number: u32) -> H256 {
+		let header = db.get::<DaHeader>(Key::BlockHeader(block_number)).unwrap();
+		header_hash(&header.unwrap())
+	}
+
+	async fn import_snapshot(
+		db: &MemoryDB,
+		path: &Path,
+		encryption: Option<&EncryptionSecret>,
+		hash: H256,
+		set_id: u64,
+	) -> Result<BlockRange> {
+		let pp = Arc::new(testnet::public_params(16));
+		import(
+			db,
+			path,
+			encryption,
+			pp,
+			|_| async move { Ok(hash) },
+			|_| async move { Ok(set_id) },
+		)
+		.await
+	}
+
+	#[tokio::test]
+	async fn export_and_import() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 10);
+		let hash = stored_hash(&db, 7);
+		let path = snapshot_path();
+		export(db, 3, 7, &path, None).unwrap();
+
+		let db = MemoryDB::default();
+		let range = import_snapshot(&db, &path, None, hash, 1).await.unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(range, BlockRange { first: 3, last: 7 });
+
+		assert!(db.get::<DaHeader>(Key::BlockHeader(2)).unwrap().is_none());
+		assert!(db.get::<DaHeader>(Key::BlockHeader(3)).unwrap().is_some());
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(7)).unwrap(), Some(1));
+		assert!(db.get::<u32>(Key::VerifiedCellCount(8)).unwrap().is_none());
+		assert_eq!(
+			db.get::<Vec<Vec<u8>>>(Key::AppData(1, 5)).unwrap(),
+			Some(vec![vec![1u8]])
+		);
+		// Confidence is calculated with the imported confidence parameters
+		assert_eq!(
+			get_confidence(&db, 7).unwrap().map(|(_, model)| model),
+			Some(ConfidenceModel::ErasureCoded)
+		);
+		assert!(db
+			.get::<ConfidenceParams>(Key::ConfidenceParams(8))
+			.unwrap()
+			.is_none());
+		let cells = db.get::<Vec<VerifiedCell>>(Key::VerifiedCells(3)).unwrap();
+		assert_eq!(cells.map(|cells| cells.len()), Some(1));
+		let audit = db.get::<SamplingAudit>(Key::SamplingAudit(3)).unwrap();
+		assert_eq!(audit.map(|audit| audit.seed), Some([3u8; 32]));
+		// Finality proofs cannot be verified without finality checkpoint
+		assert!(db
+			.get::<FinalityProof>(Key::FinalityProof(7))
+			.unwrap()
+			.is_none());
+
+		let state = load_state(db.clone()).unwrap();
+		assert_eq!(
+			state.header_verified,
+			Some(BlockRange { first: 3, last: 7 })
+		);
+		assert_eq!(state.data_verified, Some(BlockRange { first: 3, last: 7 }));
+
+		let path = snapshot_path();
+		export(db.clone(), 3, 7, &path, None).unwrap();
+		assert!(import_snapshot(&db, &path, None, hash, 1).await.is_err());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn unknown_chain_is_rejected() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 5);
+		let path = snapshot_path();
+		export(db, 1, 5, &path, None).unwrap();
+
+		let db = MemoryDB::default();
+		let result = import_snapshot(&db, &path, None, H256::repeat_byte(1), 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+		assert!(db.get::<DaHeader>(Key::BlockHeader(5)).unwrap().is_none());
+		assert!(db.get::<StateSnapshot>(Key::State).unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn unverifiable_checkpoint_is_rejected() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 5);
+		let hash = stored_hash(&db, 5);
+		let checkpoint = FinalitySyncCheckpoint {
+			number: 10,
+			set_id: 1,
+			validator_set: vec![],
+		};
+		db.put(Key::FinalitySyncCheckpoint, checkpoint).unwrap();
+		let path = snapshot_path();
+		export(db, 1, 5, &path, None).unwrap();
+
+		let db = MemoryDB::default();
+		let result = import_snapshot(&db, &path, None, hash, 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+		assert!(db.get::<StateSnapshot>(Key::State).unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn checkpoint_and_finality_proofs_are_verified() {
+		let pair = ed25519::Pair::from_seed(&[1u8; 32]);
+		let other = ed25519::Pair::from_seed(&[2u8; 32]);
+		let db = MemoryDB::default();
+		store_chain_with_logs(&db, 1, 6, |block_number| match block_number {
+			2 => vec![set_change(pair.public())],
+			_ => vec![],
+		});
+		let hash = stored_hash(&db, 6);
+		// Proof of the block 2 is signed by the previous validator set
+		for block_number in 2..=6 {
+			let block_hash = stored_hash(&db, block_number);
+			let proof = finality_proof(&pair, 1, block_hash, block_number);
+			db.put(Key::FinalityProof(block_number), proof).unwrap();
+		}
+
+		let export_with = |checkpoint: FinalitySyncCheckpoint| {
+			let db = db.clone();
+			db.put(Key::FinalitySyncCheckpoint, checkpoint).unwrap();
+			let path = snapshot_path();
+			export(db, 1, 6, &path, None).unwrap();
+			path
+		};
+		let checkpoint = FinalitySyncCheckpoint {
+			number: 5,
+			set_id: 1,
+			validator_set: vec![pair.public()],
+		};
+
+		let path = export_with(checkpoint.clone());
+		let imported = MemoryDB::default();
+		assert!(import_snapshot(&imported, &path, None, hash, 2)
+			.await
+			.is_err());
+		let range = import_snapshot(&imported, &path, None, hash, 1)
+			.await
+			.unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(range, BlockRange { first: 1, last: 6 });
+		assert!(imported
+			.get::<FinalityProof>(Key::FinalityProof(2))
+			.unwrap()
+			.is_none());
+		for block_number in 3..=6 {
+			let proof = imported.get::<FinalityProof>(Key::FinalityProof(block_number));
+			assert!(proof.unwrap().is_some());
+		}
+
+		let path = export_with(FinalitySyncCheckpoint {
+			validator_set: vec![other.public()],
+			..checkpoint.clone()
+		});
+		let result = import_snapshot(&MemoryDB::default(), &path, None, hash, 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+
+		let path = export_with(FinalitySyncCheckpoint {
+			validator_set: vec![],
+			..checkpoint.clone()
+		});
+		let result = import_snapshot(&MemoryDB::default(), &path, None, hash, 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+
+		// Justification of the block 4 targets the block 3
+		let proof = finality_proof(&pair, 1, stored_hash(&db, 3), 3);
+		db.put(Key::FinalityProof(4), proof).unwrap();
+		let path = export_with(checkpoint);
+		let imported = MemoryDB::default();
+		let result = import_snapshot(&imported, &path, None, hash, 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+		assert!(imported.get::<StateSnapshot>(Key::State).unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn invalid_cells_are_rejected() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 5);
+		let hash = stored_hash(&db, 5);
+		let cells = vec![VerifiedCell {
+			row: 0,
+			col: 1,
+			content: [IDENTITY.to_vec(), vec![1u8; 32]].concat(),
+		}];
+		db.put(Key::VerifiedCells(3), cells).unwrap();
+		let path = snapshot_path();
+		export(db.clone(), 1, 5, &path, None).unwrap();
+
+		let imported = MemoryDB::default();
+		let result = import_snapshot(&imported, &path, None, hash, 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+		assert!(imported.get::<StateSnapshot>(Key::State).unwrap().is_none());
+
+		// Verified cell count has to match the verified cells
+		let cells = vec![VerifiedCell {
+			row: 0,
+			col: 1,
+			content: [IDENTITY.to_vec(), vec![0u8; 32]].concat(),
+		}];
+		db.put(Key::VerifiedCells(3), cells).unwrap();
+		db.put(Key::VerifiedCellCount(3), 10u32).unwrap();
+		let path = snapshot_path();
+		export(db, 1, 5, &path, None).unwrap();
+		let result = import_snapshot(&imported, &path, None, hash, 1).await;
+		fs::remove_file(&path).unwrap();
+		assert!(result.is_err());
+	}
+
+	#[test]
+	fn export_requires_headers() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 10);
+		assert!(export(db, 5, 11, &snapshot_path(), None).is_err());
+	}
+
+	#[test]
+	fn corrupted_snapshot_is_rejected() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 5);
+		let path = snapshot_path();
+		export(db, 1, 5, &path, None).unwrap();
+		let mut bytes = fs::read(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		let mut corrupted = bytes.clone();
+		corrupted[20] ^= 1;
+		assert!(decode(&corrupted, None).is_err());
+
+		bytes[8] = SNAPSHOT_VERSION as u8 + 1;
+		assert!(decode(&bytes, None).is_err());
+	}
+
+	#[tokio::test]
+	async fn encrypted_snapshot() {
+		let db = MemoryDB::default();
+		store_chain(&db, 1, 5);
+		let hash = stored_hash(&db, 5);
+		let secret = EncryptionSecret::Passphrase("passphrase".to_string());
+		let path = snapshot_path();
+		export(db, 1, 5, &path, Some(&secret)).unwrap();
+
+		let bytes = fs::read(&path).unwrap();
+		assert!(matches!(
+			Payload::decode(&mut &bytes[MAGIC.len() + 4..]),
+			Ok(Payload::Encrypted { .. })
+		));
+
+		let db = MemoryDB::default();
+		let other_secret = EncryptionSecret::Passphrase("other".to_string());
+		assert!(import_snapshot(&db, &path, None, hash, 1).await.is_err());
+		assert!(import_snapshot(&db, &path, Some(&other_secret), hash, 1)
+			.await
+			.is_err());
+
+		let range = import_snapshot(&db, &path, Some(&secret), hash, 1)
+			.await
+			.unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(range, BlockRange { first: 1, last: 5 });
+		assert_eq!(
+			db.get::<Vec<Vec<u8>>>(Key::AppData(1, 5)).unwrap(),
+			Some(vec![vec![1u8]])
+		);
+	}
+
+	#[test]
+	fn broken_chain_is_rejected() {
+		let headers = (1..=3)
+			.map(|block_number| {
+				let header = header(block_number, H256::default(), vec![]);
+				(header_hash(&header), header)
+			})
+			.collect();
+		let snapshot = Snapshot {
+			range: BlockRange { first: 1, last: 3 },
+			headers,
+			confidence_achieved: None,
+			cell_counts: vec![],
+			confidence_params: vec![],
+			verified_cells: vec![],
+			sampling_audits: vec![],
+			data_verified: None,
+			app_data: vec![],
+			finality_proofs: vec![],
+			finality_checkpoint: None,
+		};
+		assert!(verify(&snapshot).is_err());
+	}
+}
@@ -0,0 +1,154 @@
+//! Encryption of the sensitive data stored on disk.
+//!
+//! Data is encrypted with XChaCha20-Poly1305, using either a key read from the key file,
+//! or a key derived from the passphrase with PBKDF2-HMAC-SHA256.
+//! Random salt used for the key derivation is stored next to the encrypted data,
+//! and random nonce is prepended to each encrypted value.
+
+use chacha20poly1305::{aead::Aead, Key, KeyInit, XChaCha20Poly1305, XNonce};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use hmac::Hmac;
+use sha2::Sha256;
+use std::fs;
+
+const KEY_SIZE: usize = 32;
+
+const NONCE_SIZE: usize = 24;
+
+/// Size of the salt used for the key derivation from the passphrase.
+pub const SALT_SIZE: usize = 16;
+
+/// Number of PBKDF2 rounds used for the key derivation from the passphrase.
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// Secret from which the encryption key is obtained.
+#[derive(Clone)]
+pub enum EncryptionSecret {
+	/// Key is derived from the passphrase and the salt
+	Passphrase(String),
+	/// Key is read from the key file
+	Key([u8; KEY_SIZE]),
+}
+
+impl EncryptionSecret {
+	/// Creates encryption secret from the passphrase or the key file, if any of them is provided.
+	pub fn new(passphrase: Option<&str>, key_file: Option<&str>) -> Result<Option<Self>> {
+		match (passphrase, key_file) {
+			(Some(_), Some(_)) => Err(eyre!(
+				"Encryption passphrase and encryption key file cannot be used together"
+			)),
+			(Some(passphrase), None) => {
+				Ok(Some(EncryptionSecret::Passphrase(passphrase.to_string())))
+			},
+			(None, Some(path)) => Self::from_key_file(path).map(Some),
+			(None, None) => Ok(None),
+		}
+	}
+
+	/// Reads hex encoded 32 bytes key from the key file.
+	fn from_key_file(path: &str) -> Result<Self> {
+		let content = fs::read_to_string(path)
+			.wrap_err_with(|| format!("Failed to read encryption key file {path}"))?;
+		let mut key = [0u8; KEY_SIZE];
+		hex::decode_to_slice(content.trim(), &mut key)
+			.wrap_err("Encryption key has to be 32 bytes, hex encoded")?;
+		Ok(EncryptionSecret::Key(key))
+	}
+
+	/// Returns encryption key for the given salt.
+	/// Salt is used only if the key is derived from the passphrase.
+	pub fn key(&self, salt: &[u8]) -> EncryptionKey {
+		match self {
+			EncryptionSecret::Passphrase(passphrase) => {
+				let mut key = [0u8; KEY_SIZE];
+				pbkdf2::pbkdf2::<Hmac<Sha256>>(
+					passphrase.as_bytes(),
+					salt,
+					PBKDF2_ROUNDS,
+					&mut key,
+				);
+				EncryptionKey(key)
+			},
+			EncryptionSecret::Key(key) => EncryptionKey(*key),
+		}
+	}
+}
+
+/// Generates random salt for the key derivation.
+pub fn generate_salt() -> [u8; SALT_SIZE] {
+	rand::random()
+}
+
+/// Key used to encrypt and decrypt the data.
+pub struct EncryptionKey([u8; KEY_SIZE]);
+
+impl EncryptionKey {
+	fn cipher(&self) -> XChaCha20Poly1305 {
+		XChaCha20Poly1305::new(Key::from_slice(&self.0))
+	}
+
+	/// Encrypts the data and prepends the random nonce to the ciphertext.
+	pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+		let nonce: [u8; NONCE_SIZE] = rand::random();
+		let ciphertext = self
+			.cipher()
+			.encrypt(XNonce::from_slice(&nonce), plaintext)
+			.map_err(|_| eyre!("Failed to encrypt the data"))?;
+		Ok([nonce.to_vec(), ciphertext].concat())
+	}
+
+	/// Decrypts the data encrypted with [`EncryptionKey::encrypt`].
+	pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+		if data.len() < NONCE_SIZE {
+			return Err(eyre!("Encrypted data is too short"));
+		}
+		let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+		self.cipher()
+			.decrypt(XNonce::from_slice(nonce), ciphertext)
+			.map_err(|_| eyre!("Failed to decrypt the data, encryption key is not valid"))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use uuid::Uuid;
+
+	#[test]
+	fn encrypt_decrypt() {
+		let key = EncryptionSecret::Key([1u8; KEY_SIZE]).key(&[]);
+		let encrypted = key.encrypt(b"data").unwrap();
+		assert_ne!(&encrypted[NONCE_SIZE..], b"data");
+		assert_eq!(key.decrypt(&encrypted).unwrap(), b"data");
+
+		let other_key = EncryptionSecret::Key([2u8; KEY_SIZE]).key(&[]);
+		assert!(other_key.decrypt(&encrypted).is_err());
+	}
+
+	#[test]
+	fn passphrase_key_depends_on_salt() {
+		let secret = EncryptionSecret::Passphrase("passphrase".to_string());
+		let (salt, other_salt) = (generate_salt(), generate_salt());
+		let encrypted = secret.key(&salt).encrypt(b"data").unwrap();
+		assert_eq!(secret.key(&salt).decrypt(&encrypted).unwrap(), b"data");
+		assert!(secret.key(&other_salt).decrypt(&encrypted).is_err());
+	}
+
+	#[test]
+	fn key_file() {
+		let path = env::temp_dir().join(format!("avail_light_key_{}", Uuid::new_v4()));
+		let path = path.to_string_lossy().to_string();
+		fs::write(&path, format!("{}\n", hex::encode([3u8; KEY_SIZE]))).unwrap();
+		let secret = EncryptionSecret::new(None, Some(&path)).unwrap();
+		assert!(matches!(secret, Some(EncryptionSecret::Key(key)) if key == [3u8; KEY_SIZE]));
+
+		fs::write(&path, "0102").unwrap();
+		assert!(EncryptionSecret::new(None, Some(&path)).is_err());
+		assert!(EncryptionSecret::new(Some("passphrase"), Some(&path)).is_err());
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -22,12 +22,13 @@ use kate_recovery::{data::Cell, matrix::RowIndex};
 use mockall::automock;
 use sp_core::blake2_256;
 use std::{sync::Arc, time::Instant};
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{debug, error, info, warn};
 
 use crate::{
 	data::{Database, Key},
 	network::{
-		p2p::Client as P2pClient,
+		p2p::{Client as P2pClient, Holding},
 		rpc::{Client as RpcClient, Event},
 	},
 	shutdown::Controller,
@@ -42,6 +43,8 @@ pub trait Client {
 	async fn insert_cells_into_dht(&self, block: u32, cells: Vec<Cell>) -> Result<()>;
 	async fn insert_rows_into_dht(&self, block: u32, rows: Vec<(RowIndex, Vec<u8>)>) -> Result<()>;
 	async fn get_kate_proof(&self, hash: H256, positions: &[Position]) -> Result<Vec<Cell>>;
+	async fn start_providing(&self, block: u32, partition: Partition) -> Result<()>;
+	async fn announce_partition(&self, block: u32, partition: Partition) -> Result<()>;
 }
 
 #[derive(Clone)]
@@ -70,6 +73,16 @@ impl Client for FatClient {
 	async fn get_kate_proof(&self, hash: H256, positions: &[Position]) -> Result<Vec<Cell>> {
 		self.rpc_client.request_kate_proof(hash, positions).await
 	}
+
+	async fn start_providing(&self, block: u32, partition: Partition) -> Result<()> {
+		self.p2p_client.start_providing(block, partition).await
+	}
+
+	async fn announce_partition(&self, block: u32, partition: Partition) -> Result<()> {
+		let Partition { number, fraction } = partition;
+		let holding = Holding::Partition { number, fraction };
+		self.p2p_client.announce(block, holding).await
+	}
 }
 
 pub async fn process_block(
@@ -182,6 +195,16 @@ pub async fn process_block(
 		warn!("No rows has been inserted into DHT since partition size is less than one row.")
 	}
 
+	// Publish provider record, so clients can find and dial this client if the DHT lookups fail
+	if let Err(e) = client.start_providing(block_number, partition).await {
+		debug!("Error publishing partition provider record: {e}");
+	}
+
+	// Announce held partition, so other clients can query this client directly
+	if let Err(e) = client.announce_partition(block_number, partition).await {
+		debug!("Error announcing partition: {e}");
+	}
+
 	Ok(())
 }
 
@@ -214,6 +237,10 @@ pub async fn run(
 					received_at,
 				} => (header, received_at),
 			},
+			Err(RecvError::Lagged(skipped)) => {
+				warn!("Fat client lagged behind, {skipped} headers skipped");
+				continue;
+			},
 			Err(error) => {
 				error!("Cannot receive message: {error}");
 				return;
@@ -376,6 +403,14 @@ mod tests {
 		mock_client
 			.expect_insert_cells_into_dht()
 			.returning(|_, _| Box::pin(async move { Ok(()) }));
+		mock_client
+			.expect_start_providing()
+			.times(1)
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
+		mock_client
+			.expect_announce_partition()
+			.times(1)
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
 
 		let mut mock_metrics = telemetry::MockMetrics::new();
 		mock_metrics.expect_count().returning(|_| ());
@@ -1,9 +1,11 @@
 pub mod api;
 pub mod app_client;
+pub mod confidence;
 pub mod consts;
 #[cfg(feature = "crawl")]
 pub mod crawl_client;
 pub mod data;
+pub mod encryption;
 pub mod fat_client;
 pub mod finality;
 pub mod light_client;
@@ -5,41 +5,151 @@
 //! # Flow
 //!
 //! * Connect to the Avail node WebSocket stream and start listening to finalized headers
-//! * Generate random cells for random data sampling (8 cells currently)
+//! * Generate random cells for random data sampling, seeded from the client sampling secret and the block hash
 //! * Retrieve cell proofs from a) DHT and/or b) via RPC call from the node, in that order
 //! * Verify proof using the received cells
-//! * Calculate block confidence and store it in RocksDB
+//! * Retry cells which failed to fetch or verify from all sources, until sampling time budget is spent
+//! * Store sampling audit record (seed, sampled positions, sources and verification results)
+//! * Calculate block confidence and store it in RocksDB, together with verified cells
 //! * Insert cells to to DHT for remote fetch
 //! * Notify the consumer (app client) a new block has been verified
 //!
 //! # Notes
 //!
+//! Multiple blocks are processed concurrently (up to configured concurrency), while the state is updated
+//! and consumers are notified in block order.
 //! In case delay is configured, block processing is delayed for configured time.
 //! In case RPC is disabled, RPC calls will be skipped.
+//! In case the client lags behind the finalized headers stream, skipped blocks are queued for backfilling through the sync client,
+//! so headers are received while skipped blocks are processed. Skipped blocks are queued as ranges, and received blocks
+//! are collapsed into a range to backfill when the queue is full. Range of the queued blocks which are not processed yet
+//! is persisted after each completed block, so it is backfilled after restart.
 
 use avail_subxt::{primitives::Header, utils::H256};
 use codec::Encode;
 use color_eyre::{eyre::WrapErr, Result};
-use kate_recovery::{commitments, matrix::Dimensions};
+use futures::stream::{FuturesOrdered, StreamExt};
+use kate_recovery::{
+	commitments, config,
+	data::Cell,
+	matrix::{Dimensions, Position},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use sp_core::blake2_256;
 use std::{
+	collections::{HashSet, VecDeque},
+	ops::Range,
 	sync::{Arc, Mutex},
-	time::Instant,
+	time::{Duration, Instant},
 };
-use tracing::{error, info};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, warn};
 
 use crate::{
-	data::{Database, Key},
+	data::{
+		self, retention::BLOCK_TIME, Batch, CellSource, ConfidenceParams, Database, Key,
+		SamplingAudit, Unavailability, VerifiedCell,
+	},
 	network::{
-		self,
+		self, p2p,
 		rpc::{self, Event},
 	},
 	shutdown::Controller,
+	sync_client,
 	telemetry::{MetricCounter, MetricValue, Metrics},
-	types::{self, ClientChannels, LightClientConfig, OptionBlockRange, State},
-	utils::{calculate_confidence, extract_kate},
+	types::{
+		self, BlockRange, ClientChannels, LightClientConfig, OptionBlockRange, State,
+		SyncClientConfig,
+	},
+	utils::extract_kate,
 };
 
+/// Maximum number of received blocks waiting to be processed, blocks over the limit are backfilled later
+const MAX_PENDING_BLOCKS: usize = 1024;
+
+/// Delay between the sampling rounds, so failed cells are not requested again immediately.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Fetches and verifies cells, drawing replacement positions for the cells which failed to fetch or verify,
+/// until the required number of cells is verified or the sampling time budget is spent.
+/// Replacement positions are drawn with the sampling random number generator, excluding all positions sampled so far.
+/// Sampling stops if a cell fetched from the node fails the proof verification,
+/// since the block is considered unavailable in that case.
+/// Returns verified cells, positions which were not verified and not replaced, and fetch statistics of all rounds.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_verified_with_replacements(
+	network_client: &impl network::Client,
+	block_number: u32,
+	header_hash: H256,
+	dimensions: Dimensions,
+	commitments: &[[u8; config::COMMITMENT_SIZE]],
+	positions: &[Position],
+	rng: &mut impl Rng,
+	budget: Duration,
+) -> Result<(Vec<Cell>, Vec<Position>, network::FetchStats)> {
+	let begin = Instant::now();
+
+	let (mut fetched, mut unfetched, mut stats) = network_client
+		.fetch_verified(
+			block_number,
+			header_hash,
+			dimensions,
+			commitments,
+			positions,
+		)
+		.await?;
+
+	let failed_node_verification = |stats: &network::FetchStats| {
+		stats
+			.samples
+			.iter()
+			.any(|sample| sample.source == Some(CellSource::Rpc) && !sample.verified)
+	};
+
+	let mut sampled = positions.iter().cloned().collect::<HashSet<_>>();
+	while !unfetched.is_empty() && !failed_node_verification(&stats) {
+		let Some(remaining) = budget.checked_sub(begin.elapsed()) else {
+			break;
+		};
+		tokio::time::sleep(RETRY_DELAY.min(remaining)).await;
+		if begin.elapsed() >= budget {
+			break;
+		}
+
+		let replacements = rpc::generate_cells(dimensions, unfetched.len() as u32, &sampled, rng);
+		if replacements.is_empty() {
+			// All positions of the block matrix are already sampled
+			break;
+		}
+		sampled.extend(replacements.iter().cloned());
+
+		info!(
+			block_number,
+			round = stats.rounds + 1,
+			replacements = replacements.len(),
+			"Sampling replacement cells for cells which failed to fetch or verify"
+		);
+
+		let (round_fetched, round_unfetched, round_stats) = network_client
+			.fetch_verified(
+				block_number,
+				header_hash,
+				dimensions,
+				commitments,
+				&replacements,
+			)
+			.await?;
+
+		fetched.extend(round_fetched);
+		unfetched.drain(..replacements.len());
+		unfetched.extend(round_unfetched);
+		stats.add_round(round_stats, replacements.len());
+	}
+
+	Ok((fetched, unfetched, stats))
+}
+
 pub async fn process_block(
 	db: impl Database,
 	network_client: &impl network::Client,
@@ -47,8 +157,8 @@ pub async fn process_block(
 	cfg: &LightClientConfig,
 	header: Header,
 	received_at: Instant,
-	state: Arc<Mutex<State>>,
-) -> Result<Option<f64>> {
+	sampling_secret: &[u8; 32],
+) -> Result<(Option<f64>, Option<Unavailability>)> {
 	metrics.count(MetricCounter::SessionBlock).await;
 	metrics
 		.record(MetricValue::TotalBlockNumber(header.number))
@@ -68,17 +178,19 @@ pub async fn process_block(
 			block_number,
 			"Skipping block with invalid dimensions {rows}x{cols}",
 		);
-		return Ok(None);
+		return Ok((None, None));
 	};
 
 	if dimensions.cols().get() <= 2 {
 		error!(block_number, "more than 2 columns is required");
-		return Ok(None);
+		return Ok((None, None));
 	}
 
 	let commitments = commitments::from_slice(&commitment)?;
-	let cell_count = rpc::cell_count_for_confidence(cfg.confidence);
-	let positions = rpc::generate_random_cells(dimensions, cell_count);
+	let cell_count = cfg.confidence_model.cell_count(cfg.confidence, dimensions);
+	let seed = rpc::sampling_seed(sampling_secret, &header_hash);
+	let mut rng = ChaCha20Rng::from_seed(seed);
+	let positions = rpc::generate_cells(dimensions, cell_count, &HashSet::new(), &mut rng);
 	info!(
 		block_number,
 		"cells_requested" = positions.len(),
@@ -86,15 +198,27 @@ pub async fn process_block(
 		positions.len()
 	);
 
-	let (fetched, unfetched, fetch_stats) = network_client
-		.fetch_verified(
-			block_number,
-			header_hash,
-			dimensions,
-			&commitments,
-			&positions,
-		)
-		.await?;
+	let sampling_begin = Instant::now();
+	let (fetched, unfetched, fetch_stats) = fetch_verified_with_replacements(
+		network_client,
+		block_number,
+		header_hash,
+		dimensions,
+		&commitments,
+		&positions,
+		&mut rng,
+		cfg.sampling_time_budget,
+	)
+	.await?;
+
+	let audit = SamplingAudit {
+		seed,
+		cells: fetch_stats.samples.clone(),
+		rounds: fetch_stats.rounds as u32,
+		duration_ms: sampling_begin.elapsed().as_millis() as u64,
+	};
+	db.put(Key::SamplingAudit(block_number), audit)
+		.wrap_err("Light Client failed to store Sampling Audit")?;
 
 	metrics
 		.record(MetricValue::DHTFetched(fetch_stats.dht_fetched))
@@ -125,20 +249,58 @@ pub async fn process_block(
 	}
 
 	if positions.len() > fetched.len() {
-		error!(block_number, "Failed to fetch {} cells", unfetched.len());
-		return Ok(None);
+		let missing_cells = (positions.len() - fetched.len()) as u32;
+		let unavailability = Unavailability::new(&fetch_stats.samples, missing_cells);
+		error!(
+			block_number,
+			rounds = fetch_stats.rounds,
+			replacements = fetch_stats.replacements,
+			reason = ?unavailability.reason,
+			"Failed to fetch {} cells within sampling time budget, block is unavailable",
+			unfetched.len()
+		);
+		db.put(Key::Unavailability(block_number), unavailability)
+			.wrap_err("Light Client failed to store Unavailability")?;
+		return Ok((None, Some(unavailability)));
 	}
 
-	// write confidence factor into on-disk database
-	db.put(Key::VerifiedCellCount(block_number), fetched.len() as u32)
+	// write confidence factor, confidence parameters, verified cells and block header into on-disk database atomically
+	//
+	// block header is used later for verifying DHT stored data
+	//
+	// @note this same data store is also written to in
+	// another competing thread, which syncs all block headers
+	// in range [0, LATEST], where LATEST = latest block number
+	// when this process started
+	let mut batch = db.new_batch();
+	batch
+		.put(Key::VerifiedCellCount(block_number), fetched.len() as u32)
 		.wrap_err("Light Client failed to store Confidence Factor")?;
+	batch
+		.put(
+			Key::ConfidenceParams(block_number),
+			ConfidenceParams::new(cfg.confidence_model, dimensions),
+		)
+		.wrap_err("Light Client failed to store Confidence Parameters")?;
+	batch
+		.put(
+			Key::VerifiedCells(block_number),
+			fetched.iter().map(VerifiedCell::from).collect::<Vec<_>>(),
+		)
+		.wrap_err("Light Client failed to store Verified Cells")?;
+	batch
+		.put(Key::BlockHeader(block_number), header)
+		.wrap_err("Light Client failed to store Block Header")?;
+	db.write_batch(batch)
+		.wrap_err("Light Client failed to store processed block")?;
 
-	state.lock().unwrap().confidence_achieved.set(block_number);
-
-	let confidence = calculate_confidence(fetched.len() as u32);
+	let confidence = cfg
+		.confidence_model
+		.confidence(fetched.len() as u32, dimensions);
 	info!(
 		block_number,
 		"confidence" = confidence,
+		confidence_model = %cfg.confidence_model,
 		"Confidence factor: {}",
 		confidence
 	);
@@ -146,18 +308,130 @@ pub async fn process_block(
 		.record(MetricValue::BlockConfidence(confidence))
 		.await?;
 
-	// push latest mined block's header into column family specified
-	// for keeping block headers, to be used
-	// later for verifying DHT stored data
-	//
-	// @note this same data store is also written to in
-	// another competing thread, which syncs all block headers
-	// in range [0, LATEST], where LATEST = latest block number
-	// when this process started
-	db.put(Key::BlockHeader(block_number), header)
-		.wrap_err("Light Client failed to store Block Header")?;
+	Ok((Some(confidence), None))
+}
+
+/// Inserts verified cells of the blocks processed before the restart back into the DHT.
+/// Only blocks within the DHT record TTL are reseeded, since older records would already be expired.
+///
+/// # Arguments
+///
+/// * `db` - Database with stored verified cells
+/// * `p2p_client` - Peer to peer client
+/// * `blocks` - Range of blocks with achieved confidence, restored from the previous run
+/// * `ttl` - DHT record TTL in seconds
+pub async fn reseed_dht(
+	db: impl Database,
+	p2p_client: p2p::Client,
+	blocks: BlockRange,
+	ttl: u64,
+) -> Result<()> {
+	let max_blocks = u32::try_from(ttl / BLOCK_TIME).unwrap_or(u32::MAX);
+	let first = blocks
+		.first
+		.max(blocks.last.saturating_sub(max_blocks).saturating_add(1));
+
+	let stored = db
+		.get_range::<Vec<VerifiedCell>>(Key::VerifiedCells(first), Key::VerifiedCells(blocks.last))
+		.wrap_err("Light Client failed to get Verified Cells")?;
 
-	Ok(Some(confidence))
+	info!(
+		first,
+		last = blocks.last,
+		blocks = stored.len(),
+		"Reseeding verified cells into the DHT"
+	);
+
+	for (key, cells) in stored {
+		let Key::VerifiedCells(block_number) = key else {
+			continue;
+		};
+		let cells = cells
+			.into_iter()
+			.map(Cell::try_from)
+			.collect::<Result<Vec<_>>>()?;
+		if let Err(error) = p2p_client.insert_cells_into_dht(block_number, cells).await {
+			debug!(block_number, "Error inserting cells into DHT: {error}");
+		}
+	}
+
+	Ok(())
+}
+
+/// Block waiting to be processed by the light client
+enum PendingBlock {
+	/// Block received from the finalized headers stream
+	Received(Header, Instant),
+	/// Blocks skipped while lagging behind the finalized headers stream, or received while the queue was full
+	Skipped(Range<u32>),
+}
+
+impl PendingBlock {
+	fn first_block(&self) -> u32 {
+		match self {
+			PendingBlock::Received(header, _) => header.number,
+			PendingBlock::Skipped(blocks) => blocks.start,
+		}
+	}
+}
+
+/// Blocks waiting for a free processing slot, consecutive skipped blocks are kept as a single range
+#[derive(Default)]
+struct PendingBlocks(VecDeque<PendingBlock>);
+
+impl PendingBlocks {
+	fn push_skipped(&mut self, blocks: Range<u32>) {
+		if blocks.is_empty() {
+			return;
+		}
+		if let Some(PendingBlock::Skipped(last)) = self.0.back_mut() {
+			if last.end == blocks.start {
+				last.end = blocks.end;
+				return;
+			}
+		}
+		self.0.push_back(PendingBlock::Skipped(blocks));
+	}
+
+	/// Queues the received block, or only its block number for backfilling if the queue is full.
+	fn push_received(&mut self, header: Header, received_at: Instant) {
+		if self.0.len() >= MAX_PENDING_BLOCKS {
+			let block_number = header.number;
+			self.push_skipped(block_number..block_number + 1);
+			return;
+		}
+		self.0
+			.push_back(PendingBlock::Received(header, received_at));
+	}
+
+	/// Returns the next block to process, skipped blocks are returned one by one.
+	fn pop(&mut self) -> Option<PendingBlock> {
+		match self.0.front_mut()? {
+			PendingBlock::Skipped(blocks) if blocks.len() > 1 => {
+				let block_number = blocks.start;
+				blocks.start += 1;
+				Some(PendingBlock::Skipped(block_number..block_number + 1))
+			},
+			_ => self.0.pop_front(),
+		}
+	}
+
+	/// Returns range from the first block which is not processed yet to the last queued block.
+	fn backlog(&self, first_processing: Option<u32>, last: u32) -> Option<BlockRange> {
+		let first = first_processing.or(self.0.front().map(PendingBlock::first_block))?;
+		(first <= last).then_some(BlockRange { first, last })
+	}
+}
+
+/// Persists range of the queued blocks which are not processed yet, or removes it if there are none.
+fn store_backlog(db: &impl Database, backlog: Option<BlockRange>) {
+	let result = match backlog {
+		Some(backlog) => db.put(Key::LightClientBacklog, backlog),
+		None => db.delete(Key::LightClientBacklog),
+	};
+	if let Err(error) = result {
+		error!("Cannot store light client backlog: {error:#}");
+	}
 }
 
 /// Runs light client.
@@ -165,15 +439,22 @@ pub async fn process_block(
 /// # Arguments
 ///
 /// * `light_client` - Light client implementation
+/// * `sync_client` - Sync client, used to backfill skipped blocks
 /// * `cfg` - Light client configuration
+/// * `sync_cfg` - Sync client configuration, used to backfill skipped blocks
+/// * `first_block` - First block processed by the light client, previous blocks are processed by the sync client
 /// * `metrics` - Metrics registry
 /// * `state` - Processed blocks state
 /// * `channels` - Communication channels
 /// * `shutdown` - Shutdown controller
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
 	db: impl Database + Clone,
 	network_client: impl network::Client,
+	sync_client: impl sync_client::Client,
 	cfg: LightClientConfig,
+	sync_cfg: SyncClientConfig,
+	first_block: u32,
 	metrics: Arc<impl Metrics>,
 	state: Arc<Mutex<State>>,
 	mut channels: ClientChannels,
@@ -181,62 +462,216 @@ pub async fn run(
 ) {
 	info!("Starting light client...");
 
+	let sampling_secret = match data::load_or_init_sampling_secret(&db) {
+		Ok(secret) => secret,
+		Err(error) => {
+			error!("Cannot load sampling secret: {error:#}");
+			let _ = shutdown.trigger_shutdown(format!("Cannot load sampling secret: {error:#}"));
+			return;
+		},
+	};
+
+	let mut next_block = first_block;
+	// Received and skipped blocks, waiting for a free processing slot
+	let mut pending = PendingBlocks::default();
+	// Blocks are processed concurrently, but completed in the order they were queued
+	let mut processing = FuturesOrdered::new();
+	// First block numbers of the blocks being processed, in processing order
+	let mut processing_blocks = VecDeque::new();
+
+	// Blocks queued before the restart are backfilled, blocks from the first block on are received again
+	match db.get::<BlockRange>(Key::LightClientBacklog) {
+		Ok(Some(backlog)) => {
+			let blocks = backlog.first..backlog.last.saturating_add(1).min(first_block);
+			if !blocks.is_empty() {
+				warn!("Backfilling blocks {blocks:?} queued before restart");
+				pending.push_skipped(blocks);
+			}
+		},
+		Ok(None) => (),
+		Err(error) => error!("Cannot load light client backlog: {error:#}"),
+	}
+
 	loop {
-		let (header, received_at) = match channels.rpc_event_receiver.recv().await {
-			Ok(event) => match event {
-				Event::HeaderUpdate {
-					header,
-					received_at,
-				} => (header, received_at),
-			},
-			Err(error) => {
-				error!("Cannot receive message: {error}");
-				return;
+		while processing.len() < cfg.block_processing_concurrency {
+			let Some(block) = pending.pop() else {
+				break;
+			};
+			processing_blocks.push_back(block.first_block());
+			processing.push_back(process_pending_block(
+				db.clone(),
+				&network_client,
+				&sync_client,
+				&metrics,
+				&cfg,
+				&sync_cfg,
+				block,
+				&sampling_secret,
+			));
+		}
+
+		tokio::select! {
+			event = channels.rpc_event_receiver.recv() => {
+				let (header, received_at) = match event {
+					Ok(Event::HeaderUpdate {
+						header,
+						received_at,
+					}) => (header, received_at),
+					Err(RecvError::Lagged(skipped)) => {
+						warn!("Light client lagged behind, {skipped} headers skipped");
+						continue;
+					},
+					Err(error) => {
+						error!("Cannot receive message: {error}");
+						return;
+					},
+				};
+
+				// Skipped blocks are completed before the blocks received after them
+				let skipped = next_block..header.number;
+				let is_lagging = !skipped.is_empty();
+				if is_lagging {
+					warn!("Backfilling skipped blocks {skipped:?}");
+					pending.push_skipped(skipped);
+				}
+				next_block = next_block.max(header.number + 1);
+				pending.push_received(header, received_at);
+				if is_lagging {
+					let backlog = pending.backlog(processing_blocks.front().copied(), next_block - 1);
+					store_backlog(&db, backlog);
+				}
 			},
-		};
+			Some(completed) = processing.next(), if !processing.is_empty() => {
+				processing_blocks.pop_front();
+				// Blocks are completed in order, so the remaining blocks follow the completed one
+				let backlog = pending.backlog(processing_blocks.front().copied(), next_block - 1);
+				store_backlog(&db, backlog);
 
-		if let Some(seconds) = cfg.block_processing_delay.sleep_duration(received_at) {
-			if let Err(error) = metrics
-				.record(MetricValue::BlockProcessingDelay(seconds.as_secs_f64()))
-				.await
-			{
-				error!("Cannot record block processing delay: {}", error);
-			}
-			info!("Sleeping for {seconds:?} seconds");
-			tokio::time::sleep(seconds).await;
+				let Some((header, result)) = completed else {
+					continue;
+				};
+				if !complete_block(header, result, &state, &channels, &shutdown) {
+					return;
+				}
+			},
 		}
+	}
+}
 
-		let process_block_result = process_block(
-			db.clone(),
-			&network_client,
-			&metrics,
-			&cfg,
-			header.clone(),
+/// Processes the pending block. Received block is processed after the configured block processing delay,
+/// and skipped block is backfilled through the sync client.
+/// Returns `None` if skipped block was already processed or cannot be backfilled.
+#[allow(clippy::too_many_arguments)]
+async fn process_pending_block(
+	db: impl Database,
+	network_client: &impl network::Client,
+	sync_client: &impl sync_client::Client,
+	metrics: &Arc<impl Metrics>,
+	cfg: &LightClientConfig,
+	sync_cfg: &SyncClientConfig,
+	block: PendingBlock,
+	sampling_secret: &[u8; 32],
+) -> Option<(Header, Result<(Option<f64>, Option<Unavailability>)>)> {
+	let (header, received_at) = match block {
+		PendingBlock::Received(header, received_at) => (header, received_at),
+		PendingBlock::Skipped(blocks) => {
+			let block_number = blocks.start;
+			let result =
+				sync_client::backfill_block(sync_client, network_client, sync_cfg, block_number)
+					.await;
+			return match result {
+				Ok(Some((header, outcome))) => Some((header, Ok(outcome))),
+				Ok(None) => None,
+				Err(error) => {
+					error!(block_number, "Cannot backfill block: {error:#}");
+					None
+				},
+			};
+		},
+	};
+
+	Some(
+		delay_and_process_block(
+			db,
+			network_client,
+			metrics,
+			cfg,
+			header,
 			received_at,
-			state.clone(),
+			sampling_secret,
 		)
-		.await;
-		let confidence = match process_block_result {
-			Ok(confidence) => confidence,
-			Err(error) => {
-				error!("Cannot process block: {error}");
-				let _ = shutdown.trigger_shutdown(format!("Cannot process block: {error:#}"));
-				return;
-			},
-		};
-
-		let Ok(client_msg) = types::BlockVerified::try_from((header, confidence)) else {
-			error!("Cannot create message from header");
-			continue;
-		};
+		.await,
+	)
+}
 
-		// notify dht-based application client
-		// that newly mined block has been received
-		if let Err(error) = channels.block_sender.send(client_msg) {
-			error!("Cannot send block verified message: {error}");
-			continue;
+/// Waits for the configured block processing delay, and processes the block.
+async fn delay_and_process_block(
+	db: impl Database,
+	network_client: &impl network::Client,
+	metrics: &Arc<impl Metrics>,
+	cfg: &LightClientConfig,
+	header: Header,
+	received_at: Instant,
+	sampling_secret: &[u8; 32],
+) -> (Header, Result<(Option<f64>, Option<Unavailability>)>) {
+	if let Some(seconds) = cfg.block_processing_delay.sleep_duration(received_at) {
+		if let Err(error) = metrics
+			.record(MetricValue::BlockProcessingDelay(seconds.as_secs_f64()))
+			.await
+		{
+			error!("Cannot record block processing delay: {}", error);
 		}
+		info!("Sleeping for {seconds:?} seconds");
+		tokio::time::sleep(seconds).await;
+	}
+
+	let result = process_block(
+		db,
+		network_client,
+		metrics,
+		cfg,
+		header.clone(),
+		received_at,
+		sampling_secret,
+	)
+	.await;
+	(header, result)
+}
+
+/// Updates the state and notifies the consumers that the block is processed.
+/// Returns `false` if block processing failed and the light client has to be stopped.
+fn complete_block(
+	header: Header,
+	result: Result<(Option<f64>, Option<Unavailability>)>,
+	state: &Arc<Mutex<State>>,
+	channels: &ClientChannels,
+	shutdown: &Controller<String>,
+) -> bool {
+	let (confidence, unavailability) = match result {
+		Ok(outcome) => outcome,
+		Err(error) => {
+			error!("Cannot process block: {error}");
+			let _ = shutdown.trigger_shutdown(format!("Cannot process block: {error:#}"));
+			return false;
+		},
+	};
+
+	if confidence.is_some() {
+		state.lock().unwrap().confidence_achieved.set(header.number);
+	}
+
+	let Ok(mut client_msg) = types::BlockVerified::try_from((header, confidence)) else {
+		error!("Cannot create message from header");
+		return true;
+	};
+	client_msg.unavailability = unavailability;
+
+	// notify dht-based application client
+	// that newly mined block has been received
+	if let Err(error) = channels.block_sender.send(client_msg) {
+		error!("Cannot send block verified message: {error}");
 	}
+	true
 }
 
 #[cfg(test)]
@@ -245,7 +680,7 @@ mod tests {
 
 	use super::*;
 	use crate::{
-		data::mem_db,
+		data::{mem_db, SampledCell, UnavailabilityReason},
 		network::rpc::{cell_count_for_confidence, CELL_COUNT_99_99},
 		telemetry,
 		types::RuntimeConfig,
@@ -261,6 +696,7 @@ mod tests {
 	use hex_literal::hex;
 	use kate_recovery::{data::Cell, matrix::Position};
 	use test_case::test_case;
+	use tokio::sync::broadcast;
 
 	#[test_case(99.9 => 10)]
 	#[test_case(99.99 => CELL_COUNT_99_99)]
@@ -274,23 +710,36 @@ mod tests {
 		cell_count_for_confidence(confidence)
 	}
 
-	#[tokio::test]
-	async fn test_process_block_with_rpc() {
-		let mut mock_network_client = network::MockClient::new();
-		let db = mem_db::MemoryDB::default();
-		let cfg = LightClientConfig::from(&RuntimeConfig::default());
-		let cells_fetched: Vec<Cell> = vec![];
-		let cells_unfetched = [
-			Position { row: 1, col: 3 },
-			Position { row: 0, col: 0 },
-			Position { row: 1, col: 2 },
-			Position { row: 0, col: 1 },
-		]
-		.to_vec();
-		let header = Header {
+	fn fetch_result(
+		positions: &[Position],
+		unfetched: Vec<Position>,
+		source: CellSource,
+	) -> Result<(Vec<Cell>, Vec<Position>, network::FetchStats)> {
+		let fetched = positions
+			.iter()
+			.filter(|position| !unfetched.contains(position))
+			.map(|&position| Cell {
+				position,
+				content: [0u8; 80],
+			})
+			.collect::<Vec<_>>();
+		let mut stats =
+			network::FetchStats::new(positions.len(), fetched.len(), Duration::from_secs(0), None);
+		stats.samples = positions
+			.iter()
+			.map(|&position| {
+				let verified = !unfetched.contains(&position);
+				SampledCell::new(position, Some(source), verified)
+			})
+			.collect();
+		Ok((fetched, unfetched, stats))
+	}
+
+	fn header(number: u32) -> Header {
+		Header {
 			parent_hash: hex!("c454470d840bc2583fcf881be4fd8a0f6daeac3a20d83b9fd4865737e56c9739")
 				.into(),
-			number: 57,
+			number,
 			state_root: hex!("7dae455e5305263f29310c60c0cc356f6f52263f9f434502121e8a40d5079c32")
 				.into(),
 			extrinsics_root: hex!(
@@ -321,8 +770,154 @@ mod tests {
 					index: vec![],
 				},
 			}),
+		}
+	}
+
+	#[test]
+	fn pending_blocks_collapse_into_range() {
+		let mut pending = PendingBlocks::default();
+		pending.push_skipped(1..3);
+		pending.push_skipped(3..5);
+		let last = 4 + MAX_PENDING_BLOCKS as u32;
+		for block_number in 5..last {
+			pending.push_received(header(block_number), Instant::now());
+		}
+		assert_eq!(pending.0.len(), MAX_PENDING_BLOCKS);
+
+		// Received blocks over the limit are collapsed into a single range
+		pending.push_received(header(last), Instant::now());
+		pending.push_received(header(last + 1), Instant::now());
+		assert_eq!(pending.0.len(), MAX_PENDING_BLOCKS + 1);
+		let Some(PendingBlock::Skipped(blocks)) = pending.0.back() else {
+			panic!("Expected skipped blocks");
 		};
-		let state = Arc::new(Mutex::new(State::default()));
+		assert_eq!(*blocks, last..last + 2);
+		assert_eq!(
+			pending.backlog(None, last + 1),
+			Some(BlockRange {
+				first: 1,
+				last: last + 1
+			})
+		);
+
+		// Skipped blocks are processed one by one
+		let Some(PendingBlock::Skipped(blocks)) = pending.pop() else {
+			panic!("Expected skipped block");
+		};
+		assert_eq!(blocks, 1..2);
+		assert_eq!(
+			pending.backlog(Some(1), last + 1).map(|range| range.first),
+			Some(1)
+		);
+		assert_eq!(
+			pending.backlog(None, last + 1).map(|range| range.first),
+			Some(2)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_fetch_verified_with_replacements() {
+		let mut mock_network_client = network::MockClient::new();
+		let mut round = 0;
+		let first = Position { row: 0, col: 0 };
+		let second = Position { row: 1, col: 1 };
+		mock_network_client
+			.expect_fetch_verified()
+			.times(2)
+			.returning(move |_, _, _, _, positions| {
+				round += 1;
+				// First cell fails in the first round, and its replacement is fetched in the second round
+				let unfetched = match round {
+					1 => vec![first],
+					_ => {
+						assert_eq!(positions.len(), 1);
+						assert!(positions[0] != first && positions[0] != second);
+						vec![]
+					},
+				};
+				let result = fetch_result(positions, unfetched, CellSource::Dht);
+				Box::pin(async move { result })
+			});
+
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		let positions = vec![first, second];
+		let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+		let (fetched, unfetched, stats) = fetch_verified_with_replacements(
+			&mock_network_client,
+			1,
+			H256::zero(),
+			dimensions,
+			&[],
+			&positions,
+			&mut rng,
+			Duration::from_secs(10),
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(fetched.len(), 2);
+		assert!(unfetched.is_empty());
+		assert!(!fetched.iter().any(|cell| cell.position == first));
+		assert_eq!(stats.rounds, 2);
+		assert_eq!(stats.replacements, 1);
+		assert_eq!(stats.dht_fetched, 2.0);
+		assert_eq!(stats.dht_fetched_percentage, 2.0 / 3.0);
+		let rounds = stats.samples.iter().map(|sample| sample.round);
+		assert_eq!(rounds.collect::<Vec<_>>(), vec![1, 1, 2]);
+		assert!(!stats.samples[0].verified);
+	}
+
+	#[tokio::test]
+	async fn test_fetch_verified_stops_on_invalid_node_cell() {
+		let mut mock_network_client = network::MockClient::new();
+		let first = Position { row: 0, col: 0 };
+		mock_network_client
+			.expect_fetch_verified()
+			.times(1)
+			.returning(move |_, _, _, _, positions| {
+				let result = fetch_result(positions, vec![first], CellSource::Rpc);
+				Box::pin(async move { result })
+			});
+
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		let positions = vec![first, Position { row: 1, col: 1 }];
+		let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+		let (fetched, unfetched, stats) = fetch_verified_with_replacements(
+			&mock_network_client,
+			1,
+			H256::zero(),
+			dimensions,
+			&[],
+			&positions,
+			&mut rng,
+			Duration::from_secs(10),
+		)
+		.await
+		.unwrap();
+
+		// Cell which failed the verification is not replaced, so the block cannot be considered available
+		assert_eq!(fetched.len(), 1);
+		assert_eq!(unfetched, vec![first]);
+		assert_eq!(stats.rounds, 1);
+		assert_eq!(stats.replacements, 0);
+	}
+
+	#[tokio::test]
+	async fn test_process_block_with_rpc() {
+		let mut mock_network_client = network::MockClient::new();
+		let db = mem_db::MemoryDB::default();
+		let mut cfg = LightClientConfig::from(&RuntimeConfig::default());
+		// Skip retries, since cells are never fetched
+		cfg.sampling_time_budget = Duration::ZERO;
+		let cells_fetched: Vec<Cell> = vec![];
+		let cells_unfetched = [
+			Position { row: 1, col: 3 },
+			Position { row: 0, col: 0 },
+			Position { row: 1, col: 2 },
+			Position { row: 0, col: 1 },
+		]
+		.to_vec();
+		let header = header(57);
 		let recv = Instant::now();
 		mock_network_client
 			.expect_fetch_verified()
@@ -342,16 +937,172 @@ mod tests {
 		mock_metrics.expect_count().returning(|_| ());
 		mock_metrics.expect_record().returning(|_| Ok(()));
 		mock_metrics.expect_set_multiaddress().returning(|_| ());
-		process_block(
-			db,
+		let (confidence, unavailability) = process_block(
+			db.clone(),
 			&mock_network_client,
 			&Arc::new(mock_metrics),
 			&cfg,
-			header,
+			header.clone(),
 			recv,
-			state,
+			&[0u8; 32],
 		)
 		.await
 		.unwrap();
+		assert!(confidence.is_none());
+		let expected = Unavailability {
+			reason: UnavailabilityReason::CellsNotFetched,
+			// Number of sampled cells is limited to the 8 cells of the extended 1x4 matrix
+			missing_cells: 8,
+		};
+		assert_eq!(unavailability, Some(expected));
+		let stored: Unavailability = db.get(Key::Unavailability(57)).unwrap().unwrap();
+		assert_eq!(stored, expected);
+
+		// Sampling audit is stored for unavailable blocks, with positions reproducible from the seed
+		let audit: SamplingAudit = db.get(Key::SamplingAudit(57)).unwrap().unwrap();
+		let header_hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+		assert_eq!(audit.seed, rpc::sampling_seed(&[0u8; 32], &header_hash));
+		assert_eq!(audit.rounds, 1);
+	}
+	#[tokio::test]
+	async fn test_process_block_reports_failed_verification_in_any_round() {
+		let mut mock_network_client = network::MockClient::new();
+		let db = mem_db::MemoryDB::default();
+		let mut cfg = LightClientConfig::from(&RuntimeConfig::default());
+		// Budget is enough for a single retry
+		cfg.sampling_time_budget = Duration::from_millis(700);
+		let mut round = 0;
+		mock_network_client
+			.expect_fetch_verified()
+			.returning(move |_, _, _, _, positions| {
+				round += 1;
+				// First cell fails the verification in the first round, and it is verified in the second round,
+				// while the second cell is never fetched
+				let (unfetched, samples) = match round {
+					1 => (
+						positions[..2].to_vec(),
+						positions
+							.iter()
+							.enumerate()
+							.map(|(i, &position)| match i {
+								0 => SampledCell::new(position, Some(CellSource::Dht), false),
+								1 => SampledCell::new(position, None, false),
+								_ => SampledCell::new(position, Some(CellSource::Dht), true),
+							})
+							.collect::<Vec<_>>(),
+					),
+					_ => (
+						positions[1..].to_vec(),
+						vec![
+							SampledCell::new(positions[0], Some(CellSource::Dht), true),
+							SampledCell::new(positions[1], None, false),
+						],
+					),
+				};
+				let mut result = fetch_result(positions, unfetched, CellSource::Dht);
+				if let Ok((_, _, stats)) = result.as_mut() {
+					stats.samples = samples;
+				}
+				Box::pin(async move { result })
+			});
+
+		let mut mock_metrics = telemetry::MockMetrics::new();
+		mock_metrics.expect_count().returning(|_| ());
+		mock_metrics.expect_record().returning(|_| Ok(()));
+		let (confidence, unavailability) = process_block(
+			db,
+			&mock_network_client,
+			&Arc::new(mock_metrics),
+			&cfg,
+			header(57),
+			Instant::now(),
+			&[0u8; 32],
+		)
+		.await
+		.unwrap();
+
+		assert!(confidence.is_none());
+		let unavailability = unavailability.unwrap();
+		assert_eq!(
+			unavailability.reason,
+			UnavailabilityReason::ProofVerificationFailed
+		);
+		assert_eq!(unavailability.missing_cells, 1);
+	}
+
+	#[tokio::test]
+	async fn test_run_completes_blocks_in_order() {
+		let mut mock_network_client = network::MockClient::new();
+		let db = mem_db::MemoryDB::default();
+		let mut cfg = LightClientConfig::from(&RuntimeConfig::default());
+		cfg.block_processing_concurrency = 2;
+		let fetch_completed = Arc::new(Mutex::new(vec![]));
+		let completed = fetch_completed.clone();
+		mock_network_client.expect_fetch_verified().returning(
+			move |block_number, _, _, _, positions| {
+				let result = fetch_result(positions, vec![], CellSource::Dht);
+				let completed = completed.clone();
+				Box::pin(async move {
+					// First block is processed slower than the second one
+					if block_number == 1 {
+						tokio::time::sleep(Duration::from_millis(200)).await;
+					}
+					completed.lock().unwrap().push(block_number);
+					result
+				})
+			},
+		);
+
+		let mut mock_metrics = telemetry::MockMetrics::new();
+		mock_metrics.expect_count().returning(|_| ());
+		mock_metrics.expect_record().returning(|_| Ok(()));
+		mock_metrics.expect_set_multiaddress().returning(|_| ());
+
+		let (event_sender, rpc_event_receiver) = broadcast::channel(10);
+		let (block_sender, mut block_receiver) = broadcast::channel(10);
+		let state = Arc::new(Mutex::new(State::default()));
+		let channels = ClientChannels {
+			block_sender,
+			rpc_event_receiver,
+		};
+
+		let light_client = tokio::spawn(run(
+			db,
+			mock_network_client,
+			sync_client::MockClient::new(),
+			cfg,
+			SyncClientConfig::from(&RuntimeConfig::default()),
+			1,
+			Arc::new(mock_metrics),
+			state.clone(),
+			channels,
+			Controller::new(),
+		));
+
+		for block_number in [1, 2] {
+			let header = header(block_number);
+			let received_at = Instant::now();
+			event_sender
+				.send(Event::HeaderUpdate {
+					header,
+					received_at,
+				})
+				.unwrap();
+		}
+
+		let block = block_receiver.recv().await.unwrap();
+		assert_eq!(block.block_num, 1);
+		assert!(block.confidence.is_some());
+		let confidence_achieved = state.lock().unwrap().confidence_achieved.clone();
+		assert_eq!(confidence_achieved, Some(BlockRange { first: 1, last: 1 }));
+
+		let block = block_receiver.recv().await.unwrap();
+		assert_eq!(block.block_num, 2);
+		let confidence_achieved = state.lock().unwrap().confidence_achieved.clone();
+		assert_eq!(confidence_achieved, Some(BlockRange { first: 1, last: 2 }));
+
+		// Second block was fetched first, but completed after the first one
+		assert_eq!(*fetch_completed.lock().unwrap(), vec![2, 1]);
+		light_client.abort();
 	}
 }
@@ -1,10 +1,12 @@
 use color_eyre::{eyre::WrapErr, Result};
 use std::sync::Arc;
-use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, warn};
 
 use crate::{
+	data::{Database, COLUMN_FAMILIES},
 	network::p2p::Client as P2pClient,
+	proof,
 	shutdown::Controller,
 	telemetry::{MetricValue, Metrics},
 	types::BlockVerified,
@@ -18,8 +20,25 @@ pub struct StaticConfigParams {
 	pub pruning_interval: u32,
 }
 
+/// Records estimated statistics of the database column families.
+async fn record_database_stats(db: &impl Database, metrics: &Arc<impl Metrics>) -> Result<()> {
+	for column_family in COLUMN_FAMILIES {
+		let stats = db.column_family_stats(column_family)?;
+		for value in [
+			MetricValue::DBEstimatedKeys(column_family, stats.estimated_keys),
+			MetricValue::DBLiveDataSize(column_family, stats.live_data_size),
+			MetricValue::DBSstFilesSize(column_family, stats.sst_files_size),
+			MetricValue::DBPendingCompactionBytes(column_family, stats.pending_compaction_bytes),
+		] {
+			metrics.record(value).await?;
+		}
+	}
+	Ok(())
+}
+
 pub async fn process_block(
 	block_number: u32,
+	db: &impl Database,
 	p2p_client: &P2pClient,
 	static_config_params: StaticConfigParams,
 	metrics: &Arc<impl Metrics>,
@@ -32,6 +51,11 @@ pub async fn process_block(
 		}
 	}
 
+	p2p_client
+		.set_latest_block(block_number)
+		.await
+		.wrap_err("Unable to set latest block")?;
+
 	p2p_client
 		.shrink_kademlia_map()
 		.await
@@ -78,11 +102,31 @@ pub async fn process_block(
 		.await?;
 	metrics.record(MetricValue::HealthCheck()).await?;
 
+	let verification_stats = proof::take_stats();
+	metrics
+		.record(MetricValue::ProofVerificationQueueDepth(
+			verification_stats.queued_batches,
+		))
+		.await?;
+	metrics
+		.record(MetricValue::ProofVerificationThroughput(
+			verification_stats.throughput,
+		))
+		.await?;
+
+	if let Err(error) = record_database_stats(db, metrics).await {
+		error!(
+			block_number,
+			"Recording database statistics failed: {error:#}"
+		);
+	}
+
 	info!(block_number, map_size, "Maintenance completed");
 	Ok(())
 }
 
 pub async fn run(
+	db: impl Database,
 	p2p_client: P2pClient,
 	metrics: Arc<impl Metrics>,
 	mut block_receiver: broadcast::Receiver<BlockVerified>,
@@ -94,7 +138,19 @@ pub async fn run(
 	loop {
 		let result = match block_receiver.recv().await {
 			Ok(block) => {
-				process_block(block.block_num, &p2p_client, static_config_params, &metrics).await
+				process_block(
+					block.block_num,
+					&db,
+					&p2p_client,
+					static_config_params,
+					&metrics,
+				)
+				.await
+			},
+			// Maintenance is periodic, so skipped blocks don't need to be processed
+			Err(RecvError::Lagged(skipped)) => {
+				warn!("Maintenance lagged behind, {skipped} blocks skipped");
+				continue;
 			},
 			Err(error) => Err(error.into()),
 		};
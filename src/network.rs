@@ -12,7 +12,10 @@ use std::{sync::Arc, time::Duration};
 use tokio::time::Instant;
 use tracing::{debug, info};
 
-use crate::proof;
+use crate::{
+	data::{CellSource, SampledCell},
+	proof,
+};
 
 pub mod p2p;
 pub mod rpc;
@@ -36,6 +39,13 @@ pub struct FetchStats {
 	pub dht_fetch_duration: f64,
 	pub rpc_fetched: Option<f64>,
 	pub rpc_fetch_duration: Option<f64>,
+	/// Number of sampling rounds
+	pub rounds: usize,
+	/// Number of replacement cells drawn for the cells which failed to fetch or verify
+	pub replacements: usize,
+	/// Fetch attempts of sampled cells, in sampling order
+	pub samples: Vec<SampledCell>,
+	total: usize,
 }
 
 type RPCFetchStats = (usize, Duration);
@@ -53,8 +63,37 @@ impl FetchStats {
 			dht_fetch_duration: dht_fetch_duration.as_secs_f64(),
 			rpc_fetched: rpc_fetch_stats.map(|(rpc_fetched, _)| rpc_fetched as f64),
 			rpc_fetch_duration: rpc_fetch_stats.map(|(_, duration)| duration.as_secs_f64()),
+			rounds: 1,
+			replacements: 0,
+			samples: vec![],
+			total,
 		}
 	}
+
+	/// Adds statistics of the sampling round, in which `replacements` replacement cells were fetched.
+	pub fn add_round(&mut self, round: FetchStats, replacements: usize) {
+		fn add(value: Option<f64>, other: Option<f64>) -> Option<f64> {
+			match (value, other) {
+				(None, None) => None,
+				(value, other) => Some(value.unwrap_or(0.0) + other.unwrap_or(0.0)),
+			}
+		}
+
+		self.total += round.total;
+		self.dht_fetched += round.dht_fetched;
+		self.dht_fetched_percentage = self.dht_fetched / self.total as f64;
+		self.dht_fetch_duration += round.dht_fetch_duration;
+		self.rpc_fetched = add(self.rpc_fetched, round.rpc_fetched);
+		self.rpc_fetch_duration = add(self.rpc_fetch_duration, round.rpc_fetch_duration);
+		self.rounds += round.rounds;
+		self.replacements += replacements;
+		let round_number = self.rounds as u32;
+		self.samples
+			.extend(round.samples.into_iter().map(|sample| SampledCell {
+				round: round_number,
+				..sample
+			}));
+	}
 }
 
 struct DHTWithRPCFallbackClient {
@@ -73,16 +112,19 @@ impl DHTWithRPCFallbackClient {
 		dimensions: Dimensions,
 		commitments: &Commitments,
 		positions: &[Position],
+		samples: &mut Vec<SampledCell>,
 	) -> Result<(Vec<Cell>, Vec<Position>, Duration)> {
 		let begin = Instant::now();
 
-		let (mut dht_fetched, mut unfetched) = self
+		let (fetched_with_peers, mut unfetched) = self
 			.p2p_client
-			.fetch_cells_from_dht(block_number, positions)
+			.fetch_cells_with_peers_from_dht(block_number, dimensions, positions)
 			.await;
 
 		let fetch_elapsed = begin.elapsed();
 
+		let (mut dht_fetched, peers): (Vec<_>, Vec<_>) = fetched_with_peers.into_iter().unzip();
+
 		let (verified, mut unverified) = proof::verify(
 			block_number,
 			dimensions,
@@ -103,6 +145,28 @@ impl DHTWithRPCFallbackClient {
 			"Cells fetched from DHT"
 		);
 
+		samples.extend(dht_fetched.iter().map(|cell| {
+			let is_verified = verified.contains(&cell.position);
+			SampledCell::new(cell.position, Some(CellSource::Dht), is_verified)
+		}));
+
+		// Penalise peers which served cells with invalid proofs
+		for (cell, peer_id) in dht_fetched.iter().zip(peers) {
+			let Some(peer_id) = peer_id else {
+				continue;
+			};
+			if !unverified.contains(&cell.position) {
+				continue;
+			}
+			if let Err(error) = self
+				.p2p_client
+				.report_peer(peer_id, p2p::Misbehaviour::InvalidProof)
+				.await
+			{
+				debug!("Cannot report peer {peer_id}: {error}");
+			}
+		}
+
 		dht_fetched.retain(|cell| verified.contains(&cell.position));
 		unfetched.append(&mut unverified);
 
@@ -116,6 +180,7 @@ impl DHTWithRPCFallbackClient {
 		dimensions: Dimensions,
 		commitments: &Commitments,
 		positions: &[Position],
+		samples: &mut Vec<SampledCell>,
 	) -> Result<(Vec<Cell>, Vec<Position>, Duration)> {
 		let begin = Instant::now();
 
@@ -146,6 +211,11 @@ impl DHTWithRPCFallbackClient {
 			"Cells fetched from RPC"
 		);
 
+		samples.extend(fetched.iter().map(|cell| {
+			let is_verified = verified.contains(&cell.position);
+			SampledCell::new(cell.position, Some(CellSource::Rpc), is_verified)
+		}));
+
 		fetched.retain(|cell| verified.contains(&cell.position));
 		Ok((fetched, unverified, fetch_elapsed))
 	}
@@ -161,13 +231,22 @@ impl Client for DHTWithRPCFallbackClient {
 		commitments: &Commitments,
 		positions: &[Position],
 	) -> Result<(Vec<Cell>, Vec<Position>, FetchStats)> {
+		let mut samples = vec![];
+
 		let (dht_fetched, unfetched, dht_fetch_duration) = self
-			.fetch_verified_from_dht(block_number, dimensions, commitments, positions)
+			.fetch_verified_from_dht(
+				block_number,
+				dimensions,
+				commitments,
+				positions,
+				&mut samples,
+			)
 			.await?;
 
 		if self.disable_rpc {
-			let stats =
+			let mut stats =
 				FetchStats::new(positions.len(), dht_fetched.len(), dht_fetch_duration, None);
+			stats.samples = with_unfetched(samples, &unfetched);
 			return Ok((dht_fetched, unfetched, stats));
 		};
 
@@ -178,6 +257,7 @@ impl Client for DHTWithRPCFallbackClient {
 				dimensions,
 				commitments,
 				&unfetched,
+				&mut samples,
 			)
 			.await?;
 
@@ -189,12 +269,25 @@ impl Client for DHTWithRPCFallbackClient {
 			debug!("Error inserting cells into DHT: {error}");
 		}
 
-		let stats = FetchStats::new(
+		// Cells inserted into the DHT are held in the local store, so they are announced to other clients
+		if !rpc_fetched.is_empty() {
+			let cells = rpc_fetched
+				.iter()
+				.map(|cell| (cell.position.row, cell.position.col))
+				.collect();
+			let holding = p2p::Holding::Cells(cells);
+			if let Err(error) = self.p2p_client.announce(block_number, holding).await {
+				debug!("Error announcing cells: {error}");
+			}
+		}
+
+		let mut stats = FetchStats::new(
 			positions.len(),
 			dht_fetched.len(),
 			dht_fetch_duration,
 			Some((rpc_fetched.len(), rpc_fetch_duration)),
 		);
+		stats.samples = with_unfetched(samples, &unfetched);
 
 		let mut fetched = vec![];
 		fetched.extend(dht_fetched);
@@ -204,6 +297,21 @@ impl Client for DHTWithRPCFallbackClient {
 	}
 }
 
+/// Records unfetched positions which were not fetched from any source.
+fn with_unfetched(mut samples: Vec<SampledCell>, unfetched: &[Position]) -> Vec<SampledCell> {
+	let missing = unfetched
+		.iter()
+		.filter(|position| {
+			!samples
+				.iter()
+				.any(|sample| sample.row == position.row && sample.col == position.col)
+		})
+		.map(|&position| SampledCell::new(position, None, false))
+		.collect::<Vec<_>>();
+	samples.extend(missing);
+	samples
+}
+
 pub fn new(
 	p2p_client: p2p::Client,
 	rpc_client: rpc::Client,
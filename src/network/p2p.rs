@@ -1,9 +1,13 @@
 use allow_block_list::BlockedPeers;
-use color_eyre::{eyre::WrapErr, Report, Result};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Report, Result,
+};
 use libp2p::{
-	autonat, dcutr, identify, identity,
+	autonat, dcutr, gossipsub, identify, identity,
 	kad::{self, PeerRecord, QueryId},
 	mdns, noise, ping, relay,
+	request_response::OutboundRequestId,
 	swarm::NetworkBehaviour,
 	tcp, upnp, yamux, PeerId, Swarm, SwarmBuilder,
 };
@@ -13,35 +17,53 @@ use tokio::sync::{
 	mpsc::{self},
 	oneshot,
 };
-use tracing::info;
+use tracing::{debug, info, trace};
 
 #[cfg(feature = "network-analysis")]
 pub mod analyzer;
+mod announcements;
+mod cell_exchange;
 mod client;
 mod event_loop;
 mod kad_mem_store;
+mod kad_rocksdb_store;
+mod kad_store;
+mod providers;
+pub mod record_validation;
+mod reputation;
 
 use crate::types::{LibP2PConfig, SecretKey};
+pub use announcements::Holding;
 pub use client::Client;
 pub use event_loop::EventLoop;
 pub use kad_mem_store::MemoryStoreConfig;
+pub use kad_store::Store;
+pub use reputation::{Misbehaviour, PeerReputation, PeerScore};
 
-use self::{client::BlockStat, kad_mem_store::MemoryStore};
+use self::{announcements::Announcements, client::BlockStat, providers::UploadedRecords};
 use libp2p_allow_block_list as allow_block_list;
 
 #[derive(Debug)]
 pub enum QueryChannel {
 	GetRecord(oneshot::Sender<Result<PeerRecord>>),
+	GetProviders(oneshot::Sender<Result<Vec<PeerId>>>),
 	PutRecord,
 	Bootstrap(oneshot::Sender<Result<()>>),
 }
 
+type CellExchangeChannel = oneshot::Sender<Result<cell_exchange::Response>>;
+
 pub struct EventLoopEntries<'a> {
 	swarm: &'a mut Swarm<Behaviour>,
 	pending_kad_queries: &'a mut HashMap<QueryId, QueryChannel>,
 	pending_swarm_events: &'a mut HashMap<PeerId, oneshot::Sender<Result<()>>>,
+	pending_cell_requests: &'a mut HashMap<OutboundRequestId, CellExchangeChannel>,
 	/// <block_num, (total_cells, result_cell_counter, time_stat)>
 	active_blocks: &'a mut HashMap<u32, BlockStat>,
+	reputation: &'a PeerReputation,
+	announcements: &'a mut Announcements,
+	/// Keys of the uploaded records, tracked only on fat clients
+	uploaded_records: Option<&'a mut UploadedRecords>,
 }
 
 impl<'a> EventLoopEntries<'a> {
@@ -49,13 +71,21 @@ impl<'a> EventLoopEntries<'a> {
 		swarm: &'a mut Swarm<Behaviour>,
 		pending_kad_queries: &'a mut HashMap<QueryId, QueryChannel>,
 		pending_swarm_events: &'a mut HashMap<PeerId, oneshot::Sender<Result<()>>>,
+		pending_cell_requests: &'a mut HashMap<OutboundRequestId, CellExchangeChannel>,
 		active_blocks: &'a mut HashMap<u32, BlockStat>,
+		reputation: &'a PeerReputation,
+		announcements: &'a mut Announcements,
+		uploaded_records: Option<&'a mut UploadedRecords>,
 	) -> Self {
 		Self {
 			swarm,
 			pending_kad_queries,
 			pending_swarm_events,
+			pending_cell_requests,
 			active_blocks,
+			reputation,
+			announcements,
+			uploaded_records,
 		}
 	}
 
@@ -71,6 +101,14 @@ impl<'a> EventLoopEntries<'a> {
 		self.pending_swarm_events.insert(peer_id, result_sender);
 	}
 
+	pub fn insert_cell_request(
+		&mut self,
+		request_id: OutboundRequestId,
+		result_sender: CellExchangeChannel,
+	) {
+		self.pending_cell_requests.insert(request_id, result_sender);
+	}
+
 	pub fn behavior_mut(&mut self) -> &mut Behaviour {
 		self.swarm.behaviour_mut()
 	}
@@ -78,6 +116,27 @@ impl<'a> EventLoopEntries<'a> {
 	pub fn swarm(&mut self) -> &mut Swarm<Behaviour> {
 		self.swarm
 	}
+
+	pub fn report_peer(&mut self, peer_id: PeerId, misbehaviour: Misbehaviour) {
+		report_peer(self.swarm, self.reputation, peer_id, misbehaviour);
+	}
+}
+
+/// Penalises peer for the misbehaviour, and blocks the peer if its score fell below the threshold.
+fn report_peer(
+	swarm: &mut Swarm<Behaviour>,
+	reputation: &PeerReputation,
+	peer_id: PeerId,
+	misbehaviour: Misbehaviour,
+) {
+	if !reputation.penalise(peer_id, misbehaviour) {
+		trace!("Peer {peer_id} penalised for {misbehaviour:?}");
+		return;
+	}
+
+	debug!("Blocking peer {peer_id} after {misbehaviour:?}, reputation is below the threshold");
+	swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+	swarm.behaviour_mut().blocked_peers.block_peer(peer_id);
 }
 
 pub trait Command {
@@ -93,7 +152,7 @@ type CommandReceiver = mpsc::UnboundedReceiver<SendableCommand>;
 #[derive(NetworkBehaviour)]
 #[behaviour(event_process = false)]
 pub struct Behaviour {
-	kademlia: kad::Behaviour<MemoryStore>,
+	kademlia: kad::Behaviour<Store>,
 	identify: identify::Behaviour,
 	ping: ping::Behaviour,
 	mdns: mdns::tokio::Behaviour,
@@ -102,6 +161,8 @@ pub struct Behaviour {
 	dcutr: dcutr::Behaviour,
 	upnp: upnp::tokio::Behaviour,
 	blocked_peers: allow_block_list::Behaviour<BlockedPeers>,
+	cell_exchange: cell_exchange::Behaviour,
+	gossipsub: gossipsub::Behaviour,
 }
 
 fn generate_config(config: libp2p::swarm::Config, cfg: &LibP2PConfig) -> libp2p::swarm::Config {
@@ -116,7 +177,7 @@ fn generate_config(config: libp2p::swarm::Config, cfg: &LibP2PConfig) -> libp2p:
 async fn build_swarm(
 	cfg: &LibP2PConfig,
 	id_keys: &libp2p::identity::Keypair,
-	kad_store: MemoryStore,
+	kad_store: Store,
 	is_ws_transport: bool,
 ) -> Result<Swarm<Behaviour>> {
 	// create Identify Protocol Config
@@ -134,6 +195,13 @@ async fn build_swarm(
 		..Default::default()
 	};
 
+	// create Gossipsub Config, announcements are validated before they are propagated
+	let gossipsub_cfg = gossipsub::ConfigBuilder::default()
+		.validation_mode(gossipsub::ValidationMode::Strict)
+		.validate_messages()
+		.build()
+		.map_err(|error| eyre!("Invalid gossipsub config: {error}"))?;
+
 	// build the Swarm, connecting the lower transport logic with the
 	// higher layer network behaviour logic
 	let tokio_swarm = SwarmBuilder::with_existing_identity(id_keys.clone()).with_tokio();
@@ -151,6 +219,12 @@ async fn build_swarm(
 			mdns: mdns::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?,
 			upnp: upnp::tokio::Behaviour::default(),
 			blocked_peers: allow_block_list::Behaviour::default(),
+			cell_exchange: cell_exchange::behaviour(&cfg.identify.cell_exchange_protocol),
+			// Announcements are signed with the local identity
+			gossipsub: gossipsub::Behaviour::new(
+				gossipsub::MessageAuthenticity::Signed(key.clone()),
+				gossipsub_cfg,
+			)?,
 		})
 	};
 
@@ -188,6 +262,14 @@ async fn build_swarm(
 		.kademlia
 		.set_mode(Some(cfg.kademlia.kademlia_mode.into()));
 
+	swarm
+		.behaviour_mut()
+		.gossipsub
+		.subscribe(&gossipsub::IdentTopic::new(
+			&cfg.identify.announcements_topic,
+		))
+		.wrap_err("Unable to subscribe to announcements topic")?;
+
 	Ok(swarm)
 }
 
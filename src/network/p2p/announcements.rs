@@ -0,0 +1,312 @@
+//! Block availability announcements
+//!
+//! Clients announce which block data they hold on a gossipsub topic scoped per genesis hash.
+//! Messages are signed with the libp2p identity of the author, and announcements are rate limited per author.
+//! Received announcements are used to prioritise connected peers which are asked directly for cells and rows.
+//! Only announcements of the blocks around the latest verified block are accepted,
+//! so announcements of far future or old blocks cannot evict the relevant ones.
+
+use kate_recovery::matrix::Position;
+use libp2p::{gossipsub::IdentTopic, PeerId};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	time::Duration,
+};
+use tokio::time::Instant;
+
+/// Maximum number of announcements per author within the rate limiting window
+const MAX_ANNOUNCEMENTS_PER_WINDOW: usize = 12;
+
+/// Rate limiting window
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Number of the latest blocks for which announcements are kept
+const MAX_ANNOUNCED_BLOCKS: u32 = 128;
+
+/// Number of blocks after the latest verified block for which announcements are accepted,
+/// since other peers can announce blocks before the local client verifies them
+const MAX_FUTURE_BLOCKS: u32 = 16;
+
+/// Block data held by the announcing peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Holding {
+	/// Fat client partition of the block matrix
+	Partition { number: u8, fraction: u8 },
+	/// Block matrix rows
+	Rows(Vec<u32>),
+	/// Sampled cells, as (row, column) pairs
+	Cells(Vec<(u32, u16)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+	pub block_number: u32,
+	pub holding: Holding,
+}
+
+/// Block data which is requested from the peers
+pub enum Wanted {
+	Cells(Vec<Position>),
+	Rows(Vec<u32>),
+}
+
+impl Wanted {
+	fn len(&self) -> usize {
+		match self {
+			Wanted::Cells(positions) => positions.len(),
+			Wanted::Rows(rows) => rows.len(),
+		}
+	}
+}
+
+impl Holding {
+	/// Expected number of the wanted cells or rows held by the peer.
+	fn relevance(&self, wanted: &Wanted) -> usize {
+		match (self, wanted) {
+			(Holding::Partition { fraction, .. }, wanted) => {
+				wanted.len().div_ceil((*fraction).max(1) as usize)
+			},
+			(Holding::Rows(rows), Wanted::Rows(wanted)) => {
+				let rows = rows.iter().collect::<HashSet<_>>();
+				wanted.iter().filter(|row| rows.contains(row)).count()
+			},
+			(Holding::Cells(cells), Wanted::Cells(wanted)) => {
+				let cells = cells.iter().collect::<HashSet<_>>();
+				wanted
+					.iter()
+					.filter(|position| cells.contains(&(position.row, position.col)))
+					.count()
+			},
+			_ => 0,
+		}
+	}
+}
+
+/// Fixed window rate limiter, tracking number of announcements per author.
+struct RateLimiter {
+	windows: HashMap<PeerId, (Instant, usize)>,
+}
+
+impl RateLimiter {
+	/// Returns `true` if the author is allowed to announce.
+	fn check(&mut self, author: PeerId, now: Instant) -> bool {
+		let (started_at, count) = self.windows.entry(author).or_insert((now, 0));
+		if now.duration_since(*started_at) >= RATE_LIMIT_WINDOW {
+			*started_at = now;
+			*count = 0;
+		}
+		if *count >= MAX_ANNOUNCEMENTS_PER_WINDOW {
+			return false;
+		}
+		*count += 1;
+		true
+	}
+
+	fn prune(&mut self, now: Instant) {
+		self.windows
+			.retain(|_, (started_at, _)| now.duration_since(*started_at) < RATE_LIMIT_WINDOW);
+	}
+}
+
+/// Received announcements, indexed by block number
+pub struct Announcements {
+	topic: IdentTopic,
+	blocks: BTreeMap<u32, HashMap<PeerId, Holding>>,
+	rate_limiter: RateLimiter,
+	/// Latest verified block, announcements are rejected until it is known
+	latest_block: Option<u32>,
+}
+
+impl Announcements {
+	pub fn new(topic: &str) -> Self {
+		Announcements {
+			topic: IdentTopic::new(topic),
+			blocks: BTreeMap::new(),
+			rate_limiter: RateLimiter {
+				windows: HashMap::new(),
+			},
+			latest_block: None,
+		}
+	}
+
+	pub fn topic(&self) -> &IdentTopic {
+		&self.topic
+	}
+
+	/// Checks and consumes the rate limit of the author.
+	pub fn rate_limit(&mut self, author: PeerId) -> bool {
+		let now = Instant::now();
+		self.rate_limiter.prune(now);
+		self.rate_limiter.check(author, now)
+	}
+
+	/// Sets the latest verified block and removes announcements of the blocks outside of the window.
+	/// Blocks can be verified out of order (e.g. while syncing), so the highest block is kept.
+	pub fn set_latest_block(&mut self, block_number: u32) {
+		if self
+			.latest_block
+			.is_some_and(|latest| latest >= block_number)
+		{
+			return;
+		}
+		self.latest_block = Some(block_number);
+		let first = block_number.saturating_sub(MAX_ANNOUNCED_BLOCKS - 1);
+		self.blocks = self.blocks.split_off(&first);
+	}
+
+	/// Checks if the announced block is within the window around the latest verified block.
+	pub fn is_relevant(&self, block_number: u32) -> bool {
+		let Some(latest) = self.latest_block else {
+			return false;
+		};
+		let first = latest.saturating_sub(MAX_ANNOUNCED_BLOCKS - 1);
+		let last = latest.saturating_add(MAX_FUTURE_BLOCKS);
+		(first..=last).contains(&block_number)
+	}
+
+	/// Stores the received announcement, if the announced block is relevant.
+	/// Returns `false` if the announcement is not stored.
+	pub fn insert(&mut self, author: PeerId, announcement: Announcement) -> bool {
+		let Announcement {
+			block_number,
+			holding,
+		} = announcement;
+
+		if !self.is_relevant(block_number) {
+			return false;
+		}
+
+		self.blocks
+			.entry(block_number)
+			.or_default()
+			.insert(author, holding);
+		true
+	}
+
+	/// Orders peers by the expected number of wanted cells or rows they hold, based on the announcements.
+	/// Order of the peers with the same relevance is preserved.
+	pub fn prioritise(&self, block_number: u32, wanted: &Wanted, peers: &mut [PeerId]) {
+		let Some(holdings) = self.blocks.get(&block_number) else {
+			return;
+		};
+		peers.sort_by_cached_key(|peer_id| {
+			let relevance = holdings
+				.get(peer_id)
+				.map(|holding| holding.relevance(wanted))
+				.unwrap_or(0);
+			std::cmp::Reverse(relevance)
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rate_limit_announcements() {
+		let mut announcements = Announcements::new("test");
+		let author = PeerId::random();
+
+		for _ in 0..MAX_ANNOUNCEMENTS_PER_WINDOW {
+			assert!(announcements.rate_limit(author));
+		}
+		assert!(!announcements.rate_limit(author));
+		assert!(announcements.rate_limit(PeerId::random()));
+
+		let mut rate_limiter = RateLimiter {
+			windows: HashMap::new(),
+		};
+		let now = Instant::now();
+		for _ in 0..MAX_ANNOUNCEMENTS_PER_WINDOW {
+			assert!(rate_limiter.check(author, now));
+		}
+		assert!(!rate_limiter.check(author, now));
+		assert!(rate_limiter.check(author, now + RATE_LIMIT_WINDOW));
+	}
+
+	#[test]
+	fn prioritise_announced_peers() {
+		let mut announcements = Announcements::new("test");
+		announcements.set_latest_block(1);
+		let [unknown, fat, light] = [PeerId::random(), PeerId::random(), PeerId::random()];
+
+		let holding = Holding::Partition {
+			number: 1,
+			fraction: 4,
+		};
+		announcements.insert(
+			fat,
+			Announcement {
+				block_number: 1,
+				holding,
+			},
+		);
+		let holding = Holding::Cells(vec![(0, 0), (0, 1), (1, 1)]);
+		announcements.insert(
+			light,
+			Announcement {
+				block_number: 1,
+				holding,
+			},
+		);
+
+		let wanted = Wanted::Cells(vec![
+			Position { row: 0, col: 0 },
+			Position { row: 0, col: 1 },
+			Position { row: 2, col: 2 },
+			Position { row: 3, col: 3 },
+		]);
+
+		let mut peers = [unknown, fat, light];
+		announcements.prioritise(1, &wanted, &mut peers);
+		assert_eq!(peers, [light, fat, unknown]);
+
+		// Peers are not reordered without announcements for the block
+		let mut peers = [unknown, fat, light];
+		announcements.prioritise(2, &wanted, &mut peers);
+		assert_eq!(peers, [unknown, fat, light]);
+
+		let wanted = Wanted::Rows(vec![0, 1]);
+		let mut peers = [unknown, light, fat];
+		announcements.prioritise(1, &wanted, &mut peers);
+		assert_eq!(peers, [fat, unknown, light]);
+	}
+
+	#[test]
+	fn keep_latest_announced_blocks() {
+		let mut announcements = Announcements::new("test");
+		let author = PeerId::random();
+		let announcement = |block_number| Announcement {
+			block_number,
+			holding: Holding::Rows(vec![0]),
+		};
+
+		// Announcements are rejected until the latest block is known
+		assert!(!announcements.insert(author, announcement(1)));
+
+		announcements.set_latest_block(200);
+		assert!(!announcements.insert(author, announcement(200 - MAX_ANNOUNCED_BLOCKS)));
+		assert!(!announcements.insert(author, announcement(201 + MAX_FUTURE_BLOCKS)));
+		assert!(!announcements.insert(author, announcement(u32::MAX)));
+		for block_number in (201 - MAX_ANNOUNCED_BLOCKS)..=(200 + MAX_FUTURE_BLOCKS) {
+			assert!(announcements.insert(author, announcement(block_number)));
+		}
+
+		// Older latest block doesn't move the window back
+		announcements.set_latest_block(100);
+		assert!(!announcements.insert(author, announcement(100)));
+
+		announcements.set_latest_block(210);
+		assert_eq!(
+			announcements.blocks.len(),
+			(MAX_ANNOUNCED_BLOCKS - 10 + MAX_FUTURE_BLOCKS) as usize
+		);
+		assert_eq!(
+			announcements.blocks.keys().next(),
+			Some(&(211 - MAX_ANNOUNCED_BLOCKS))
+		);
+	}
+}
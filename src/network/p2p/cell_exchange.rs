@@ -0,0 +1,296 @@
+//! Direct cell and row exchange
+//!
+//! Request-response protocol used to fetch a batch of block cells or rows directly from a connected peer,
+//! avoiding an iterative Kademlia lookup for each cell. Peers answer from their local record store.
+//! Responses are capped by the content size, and inbound requests are rate limited per peer,
+//! since requests are answered on the swarm event loop.
+
+use kate_recovery::matrix::{Position, RowIndex};
+use libp2p::{
+	kad::{store::RecordStore, RecordKey},
+	request_response::{self, ProtocolSupport},
+	PeerId, StreamProtocol,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use super::Store;
+
+/// Maximum number of cells or rows in a single request
+pub const MAX_BATCH_SIZE: usize = 1024;
+
+/// Maximum size of the cells or rows content in a single response.
+/// Content is encoded as CBOR array of integers (up to 2 bytes per content byte),
+/// so the encoded response stays below the 10 MiB response size limit of the codec.
+const MAX_RESPONSE_CONTENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Maximum number of inbound requests answered per peer within the rate limit window
+const MAX_PEER_REQUESTS: u32 = 10;
+
+/// Rate limit window of the inbound requests
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Number of rate limited peers after which the expired rate limit windows are removed
+const MAX_RATE_LIMITED_PEERS: usize = 1024;
+
+/// Timeout for a single request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type Behaviour = request_response::cbor::Behaviour<Request, Response>;
+
+pub type Event = request_response::Event<Request, Response>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+	Cells {
+		block_number: u32,
+		/// Cell positions as (row, column) pairs
+		positions: Vec<(u32, u16)>,
+	},
+	Rows {
+		block_number: u32,
+		row_indexes: Vec<u32>,
+	},
+}
+
+impl Request {
+	pub fn cells(block_number: u32, positions: &[Position]) -> Self {
+		Request::Cells {
+			block_number,
+			positions: positions
+				.iter()
+				.map(|position| (position.row, position.col))
+				.collect(),
+		}
+	}
+
+	pub fn rows(block_number: u32, row_indexes: &[u32]) -> Self {
+		Request::Rows {
+			block_number,
+			row_indexes: row_indexes.to_vec(),
+		}
+	}
+}
+
+/// Response contains only the requested cells or rows which are found in the peer's record store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+	Cells(Vec<CellContent>),
+	Rows(Vec<RowContent>),
+}
+
+impl Response {
+	/// Empty response for the given request, sent to the peers which exceeded the rate limit
+	pub fn empty(request: &Request) -> Self {
+		match request {
+			Request::Cells { .. } => Response::Cells(vec![]),
+			Request::Rows { .. } => Response::Rows(vec![]),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellContent {
+	pub row: u32,
+	pub col: u16,
+	pub content: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowContent {
+	pub row: u32,
+	pub data: Vec<u8>,
+}
+
+/// Creates request-response behaviour for the given protocol name.
+pub fn behaviour(protocol: &str) -> Behaviour {
+	let protocol = StreamProtocol::try_from_owned(protocol.to_string())
+		.expect("Invalid cell exchange protocol name");
+	Behaviour::new(
+		[(protocol, ProtocolSupport::Full)],
+		request_response::Config::default().with_request_timeout(REQUEST_TIMEOUT),
+	)
+}
+
+fn get_value(store: &Store, reference: String, now: Instant) -> Option<Vec<u8>> {
+	let key = RecordKey::from(reference.into_bytes());
+	store
+		.get(&key)
+		.filter(|record| !record.is_expired(now))
+		.map(|record| record.value.clone())
+}
+
+/// Limits the number of inbound requests per peer, so peers cannot flood the local store with lookups
+#[derive(Default)]
+pub struct RateLimiter {
+	/// Start of the current rate limit window and number of requests within it, per peer
+	windows: HashMap<PeerId, (Instant, u32)>,
+}
+
+impl RateLimiter {
+	/// Counts the inbound request of the peer.
+	/// Returns `false` if the peer exceeded the number of requests within the current window.
+	pub fn allow(&mut self, peer_id: PeerId, now: Instant) -> bool {
+		let is_expired =
+			|started_at: &Instant| now.saturating_duration_since(*started_at) >= RATE_LIMIT_WINDOW;
+
+		if self.windows.len() >= MAX_RATE_LIMITED_PEERS && !self.windows.contains_key(&peer_id) {
+			self.windows
+				.retain(|_, (started_at, _)| !is_expired(started_at));
+		}
+
+		let (started_at, requests) = self.windows.entry(peer_id).or_insert((now, 0));
+		if is_expired(started_at) {
+			*started_at = now;
+			*requests = 0;
+		}
+		*requests += 1;
+		*requests <= MAX_PEER_REQUESTS
+	}
+}
+
+/// Answers the request from the local record store.
+/// Requests with more than `MAX_BATCH_SIZE` cells or rows are truncated,
+/// and the response is truncated once its content exceeds `MAX_RESPONSE_CONTENT_SIZE`.
+pub fn respond(store: &Store, request: Request) -> Response {
+	let now = Instant::now();
+	let mut size = 0;
+	let mut fits = |content: &[u8]| {
+		size += content.len();
+		size <= MAX_RESPONSE_CONTENT_SIZE
+	};
+	match request {
+		Request::Cells {
+			block_number,
+			positions,
+		} => Response::Cells(
+			positions
+				.into_iter()
+				.take(MAX_BATCH_SIZE)
+				.filter_map(|(row, col)| {
+					let reference = Position { row, col }.reference(block_number);
+					let content = get_value(store, reference, now)?;
+					Some(CellContent { row, col, content })
+				})
+				.take_while(|cell| fits(&cell.content))
+				.collect(),
+		),
+		Request::Rows {
+			block_number,
+			row_indexes,
+		} => Response::Rows(
+			row_indexes
+				.into_iter()
+				.take(MAX_BATCH_SIZE)
+				.filter_map(|row| {
+					let reference = RowIndex(row).reference(block_number);
+					let data = get_value(store, reference, now)?;
+					Some(RowContent { row, data })
+				})
+				.take_while(|row| fits(&row.data))
+				.collect(),
+		),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::p2p::{kad_mem_store::MemoryStore, MemoryStoreConfig};
+	use libp2p::{kad::Record, PeerId};
+
+	fn store(records: &[(&str, Vec<u8>)]) -> Store {
+		let mut store = Store::Memory(MemoryStore::with_config(
+			PeerId::random(),
+			MemoryStoreConfig::default(),
+		));
+		for (key, value) in records {
+			let record = Record::new(RecordKey::new(key), value.clone());
+			store.put(record).unwrap();
+		}
+		store
+	}
+
+	#[test]
+	fn respond_with_stored_cells() {
+		let store = store(&[("1:0:1", vec![1; 80]), ("1:2:3", vec![2; 80])]);
+		let positions = [
+			Position { row: 0, col: 1 },
+			Position { row: 1, col: 1 },
+			Position { row: 2, col: 3 },
+		];
+
+		let response = respond(&store, Request::cells(1, &positions));
+		assert_eq!(
+			response,
+			Response::Cells(vec![
+				CellContent {
+					row: 0,
+					col: 1,
+					content: vec![1; 80]
+				},
+				CellContent {
+					row: 2,
+					col: 3,
+					content: vec![2; 80]
+				},
+			])
+		);
+
+		let response = respond(&store, Request::cells(2, &positions));
+		assert_eq!(response, Response::Cells(vec![]));
+	}
+
+	#[test]
+	fn respond_with_stored_rows() {
+		let store = store(&[("1:0", vec![1; 64]), ("1:2:3", vec![2; 80])]);
+
+		let response = respond(&store, Request::rows(1, &[0, 2]));
+		assert_eq!(
+			response,
+			Response::Rows(vec![RowContent {
+				row: 0,
+				data: vec![1; 64]
+			}])
+		);
+	}
+
+	#[test]
+	fn respond_truncates_large_rows() {
+		let row = vec![1; 64_000];
+		let records = (0..100)
+			.map(|row_index| (format!("1:{row_index}"), row.clone()))
+			.collect::<Vec<_>>();
+		let records = records
+			.iter()
+			.map(|(key, value)| (key.as_str(), value.clone()))
+			.collect::<Vec<_>>();
+		let store = store(&records);
+
+		let row_indexes = (0..100).collect::<Vec<_>>();
+		let Response::Rows(rows) = respond(&store, Request::rows(1, &row_indexes)) else {
+			panic!("Rows response expected");
+		};
+		assert_eq!(rows.len(), MAX_RESPONSE_CONTENT_SIZE / row.len());
+		assert_eq!(rows[0].row, 0);
+	}
+
+	#[test]
+	fn rate_limiter_limits_requests_per_peer() {
+		let mut limiter = RateLimiter::default();
+		let peer_id = PeerId::random();
+		let now = Instant::now();
+
+		for _ in 0..MAX_PEER_REQUESTS {
+			assert!(limiter.allow(peer_id, now));
+		}
+		assert!(!limiter.allow(peer_id, now));
+		// Other peers are not affected
+		assert!(limiter.allow(PeerId::random(), now));
+		// Requests are allowed again in the next window
+		assert!(limiter.allow(peer_id, now + RATE_LIMIT_WINDOW));
+	}
+}
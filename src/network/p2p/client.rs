@@ -1,27 +1,38 @@
-use super::{Command, CommandSender, EventLoopEntries, QueryChannel, SendableCommand};
+use super::{
+	announcements::{Announcement, Holding, Wanted},
+	cell_exchange::{self, CellContent, RowContent},
+	providers,
+	record_validation::RowCommitments,
+	Command, CommandSender, EventLoopEntries, Misbehaviour, QueryChannel, SendableCommand,
+};
 use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Report, Result,
 };
+use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
 use futures::future::join_all;
 use kate_recovery::{
 	config,
 	data::Cell,
-	matrix::{Dimensions, Position, RowIndex},
+	matrix::{Dimensions, Partition, Position, RowIndex},
 };
 use libp2p::{
-	kad::{PeerRecord, Quorum, Record, RecordKey},
+	kad::{store::RecordStore, PeerRecord, Quorum, Record, RecordKey},
 	swarm::dial_opts::DialOpts,
 	Multiaddr, PeerId,
 };
+use rand::seq::SliceRandom;
 use std::str;
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	time::{Duration, Instant},
 };
 use tokio::sync::oneshot;
 use tracing::{debug, trace};
 
+/// Maximum number of connected peers asked directly for cells or rows, before falling back to the DHT
+const MAX_DIRECT_PEERS: usize = 3;
+
 #[derive(Clone)]
 pub struct Client {
 	command_sender: CommandSender,
@@ -29,6 +40,8 @@ pub struct Client {
 	dht_parallelization_limit: usize,
 	/// Cell time to live in DHT (in seconds)
 	ttl: u64,
+	/// Fraction of the partitions provided by fat clients, used for provider lookups
+	provider_partition_fraction: Option<u8>,
 }
 
 struct DHTCell(Cell);
@@ -89,9 +102,9 @@ impl Command for PruneExpiredRecords {
 	fn run(&mut self, mut entries: EventLoopEntries) -> Result<(), Report> {
 		let store = entries.behavior_mut().kademlia.store_mut();
 
-		let before = store.records_iter().count();
+		let before = store.records_count();
 		store.retain(|_, record| !record.is_expired(self.now));
-		let after = store.records_iter().count();
+		let after = store.records_count();
 
 		self.response_sender
 			.take()
@@ -152,6 +165,20 @@ impl Command for AddAddress {
 	fn abort(&mut self, _error: Report) {}
 }
 
+struct ReportPeer {
+	peer_id: PeerId,
+	misbehaviour: Misbehaviour,
+}
+
+impl Command for ReportPeer {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		entries.report_peer(self.peer_id, self.misbehaviour);
+		Ok(())
+	}
+
+	fn abort(&mut self, _error: Report) {}
+}
+
 struct Bootstrap {
 	response_sender: Option<oneshot::Sender<Result<()>>>,
 }
@@ -201,6 +228,91 @@ impl Command for GetKadRecord {
 	}
 }
 
+struct GetProviders {
+	key: RecordKey,
+	response_sender: Option<oneshot::Sender<Result<Vec<PeerId>>>>,
+}
+
+impl Command for GetProviders {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let query_id = entries
+			.behavior_mut()
+			.kademlia
+			.get_providers(self.key.clone());
+
+		// insert response channel into KAD Queries pending map
+		let response_sender = self.response_sender.take().unwrap();
+		entries.insert_query(query_id, QueryChannel::GetProviders(response_sender));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		self.response_sender
+			.take()
+			.unwrap()
+			.send(Err(error))
+			.expect("GetProviders receiver dropped");
+	}
+}
+
+struct StoreRecord {
+	record: Record,
+}
+
+impl Command for StoreRecord {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		entries
+			.behavior_mut()
+			.kademlia
+			.store_mut()
+			.put(self.record.clone())
+			.wrap_err("Unable to store record")
+	}
+
+	fn abort(&mut self, _: Report) {}
+}
+
+struct StartProviding {
+	block_number: u32,
+	partition: Partition,
+}
+
+impl Command for StartProviding {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let oldest_block = self
+			.block_number
+			.saturating_sub(providers::PROVIDED_BLOCKS - 1);
+		let expired_records = entries
+			.uploaded_records
+			.as_mut()
+			.map(|uploaded_records| uploaded_records.take_older(oldest_block))
+			.unwrap_or_default();
+		let kademlia = &mut entries.behavior_mut().kademlia;
+
+		// Stop providing blocks older than the provided blocks window, and remove their uploaded records
+		let expired = kademlia
+			.store_mut()
+			.provided()
+			.map(|record| record.key.clone())
+			.filter(|key| providers::block_number(key).is_some_and(|block| block < oldest_block))
+			.collect::<Vec<_>>();
+		for key in expired {
+			kademlia.stop_providing(&key);
+		}
+		for key in expired_records {
+			kademlia.remove_record(&key);
+		}
+
+		let key = providers::partition_key(self.block_number, &self.partition);
+		kademlia
+			.start_providing(key)
+			.wrap_err("Unable to start providing partition")?;
+		Ok(())
+	}
+
+	fn abort(&mut self, _: Report) {}
+}
+
 struct PutKadRecord {
 	records: Vec<Record>,
 	quorum: Quorum,
@@ -224,6 +336,12 @@ impl Command for PutKadRecord {
 				time_stat: 0,
 			});
 
+		// Fat clients keep uploaded records of the provided blocks, and remove them afterwards
+		if let Some(uploaded_records) = entries.uploaded_records.as_mut() {
+			let keys = self.records.iter().map(|record| record.key.clone());
+			uploaded_records.insert(self.block_num, keys);
+		}
+
 		for record in self.records.clone() {
 			let query_id = entries
 				.behavior_mut()
@@ -264,6 +382,105 @@ impl Command for CountConnectedPeers {
 	}
 }
 
+struct SendCellRequest {
+	peer_id: PeerId,
+	request: cell_exchange::Request,
+	response_sender: Option<oneshot::Sender<Result<cell_exchange::Response>>>,
+}
+
+impl Command for SendCellRequest {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let request_id = entries
+			.behavior_mut()
+			.cell_exchange
+			.send_request(&self.peer_id, self.request.clone());
+
+		// insert response channel into cell requests pending map
+		entries.insert_cell_request(request_id, self.response_sender.take().unwrap());
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		// TODO: consider what to do if this results with None
+		self.response_sender
+			.take()
+			.unwrap()
+			.send(Err(error))
+			.expect("SendCellRequest receiver dropped");
+	}
+}
+
+struct DirectPeers {
+	block_number: u32,
+	wanted: Wanted,
+	response_sender: Option<oneshot::Sender<Result<Vec<PeerId>>>>,
+}
+
+impl Command for DirectPeers {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		let mut peers = entries.swarm.connected_peers().cloned().collect::<Vec<_>>();
+
+		// Peers are shuffled first, so peers without announcements are picked randomly
+		peers.shuffle(&mut rand::thread_rng());
+		entries
+			.announcements
+			.prioritise(self.block_number, &self.wanted, &mut peers);
+		peers.truncate(MAX_DIRECT_PEERS);
+
+		self.response_sender
+			.take()
+			.unwrap()
+			.send(Ok(peers))
+			.expect("DirectPeers receiver dropped");
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		self.response_sender
+			.take()
+			.unwrap()
+			.send(Err(error))
+			.expect("DirectPeers receiver dropped");
+	}
+}
+
+struct Announce {
+	announcement: Announcement,
+}
+
+impl Command for Announce {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let local_peer_id = *entries.swarm.local_peer_id();
+		if !entries.announcements.rate_limit(local_peer_id) {
+			trace!("Announcement rate limit exceeded, skipping announcement");
+			return Ok(());
+		}
+
+		let data = serde_json::to_vec(&self.announcement)?;
+		let topic = entries.announcements.topic().clone();
+		// Publishing fails if there are no subscribed peers, which is expected on startup
+		if let Err(error) = entries.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+			debug!("Cannot publish announcement: {error}");
+		}
+		Ok(())
+	}
+
+	fn abort(&mut self, _: Report) {}
+}
+
+struct SetLatestBlock {
+	block_number: u32,
+}
+
+impl Command for SetLatestBlock {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		entries.announcements.set_latest_block(self.block_number);
+		Ok(())
+	}
+
+	fn abort(&mut self, _: Report) {}
+}
+
 struct ListConnectedPeers {
 	response_sender: Option<oneshot::Sender<Result<Vec<String>>>>,
 }
@@ -303,8 +520,8 @@ struct GetCellsInDHTPerBlock {
 impl Command for GetCellsInDHTPerBlock {
 	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
 		let mut occurrence_map = HashMap::new();
-		for record in entries.behavior_mut().kademlia.store_mut().records_iter() {
-			let vec_key = record.0.to_vec();
+		for record in entries.behavior_mut().kademlia.store_mut().records() {
+			let vec_key = record.key.to_vec();
 			let record_key = str::from_utf8(&vec_key);
 
 			let (block_num, _) = record_key
@@ -382,7 +599,7 @@ struct ReduceKademliaMapSize {
 
 impl Command for ReduceKademliaMapSize {
 	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
-		entries.behavior_mut().kademlia.store_mut().shrink_hashmap();
+		entries.behavior_mut().kademlia.store_mut().shrink();
 
 		// send result back
 		// TODO: consider what to do if this results with None
@@ -406,12 +623,7 @@ struct GetKademliaMapSize {
 
 impl Command for GetKademliaMapSize {
 	fn run(&mut self, mut entries: EventLoopEntries) -> Result<(), Report> {
-		let size = entries
-			.behavior_mut()
-			.kademlia
-			.store_mut()
-			.records_iter()
-			.count();
+		let size = entries.behavior_mut().kademlia.store_mut().records_count();
 
 		self.response_sender
 			.take()
@@ -486,11 +698,17 @@ impl Command for AddAutonatServer {
 }
 
 impl Client {
-	pub fn new(sender: CommandSender, dht_parallelization_limit: usize, ttl: u64) -> Self {
+	pub fn new(
+		sender: CommandSender,
+		dht_parallelization_limit: usize,
+		ttl: u64,
+		provider_partition_fraction: Option<u8>,
+	) -> Self {
 		Self {
 			command_sender: sender,
 			dht_parallelization_limit,
 			ttl,
+			provider_partition_fraction,
 		}
 	}
 
@@ -524,6 +742,16 @@ impl Client {
 			.context("failed to add address to the routing table")
 	}
 
+	/// Penalises peer for the misbehaviour, blocking it if its reputation falls below the threshold.
+	pub async fn report_peer(&self, peer_id: PeerId, misbehaviour: Misbehaviour) -> Result<()> {
+		self.command_sender
+			.send(Box::new(ReportPeer {
+				peer_id,
+				misbehaviour,
+			}))
+			.context("failed to report peer")
+	}
+
 	pub async fn dial_peer(&self, peer_id: PeerId, peer_address: Multiaddr) -> Result<()> {
 		self.execute_sync(|response_sender| {
 			Box::new(DialPeer {
@@ -650,7 +878,13 @@ impl Client {
 
 	// Since callers ignores DHT errors, debug logs are used to observe DHT behavior.
 	// Return type assumes that cell is not found in case when error is present.
-	async fn fetch_cell_from_dht(&self, block_number: u32, position: Position) -> Option<Cell> {
+	// Cell is returned together with the publisher of the record, if the record isn't local,
+	// so invalid records are attributed to their publisher rather than to the peer which stored them.
+	async fn fetch_cell_from_dht(
+		&self,
+		block_number: u32,
+		position: Position,
+	) -> Option<(Cell, Option<PeerId>)> {
 		let reference = position.reference(block_number);
 		let record_key = RecordKey::from(reference.as_bytes().to_vec());
 
@@ -660,15 +894,24 @@ impl Client {
 			Ok(peer_record) => {
 				trace!("Fetched cell {reference} from the DHT");
 
+				let publisher = peer_record.peer.and(peer_record.record.publisher);
 				let try_content: Result<[u8; config::COMMITMENT_SIZE + config::CHUNK_SIZE], _> =
 					peer_record.record.value.try_into();
 
 				let Ok(content) = try_content else {
 					debug!("Cannot convert cell {reference} into 80 bytes");
+					if let Some(peer_id) = publisher {
+						if let Err(error) = self
+							.report_peer(peer_id, Misbehaviour::MalformedRecord)
+							.await
+						{
+							debug!("Cannot report peer {peer_id}: {error}");
+						}
+					}
 					return None;
 				};
 
-				Some(Cell { position, content })
+				Some((Cell { position, content }, publisher))
 			},
 			Err(error) => {
 				trace!("Cell {reference} not found in the DHT: {error}");
@@ -680,6 +923,7 @@ impl Client {
 	async fn fetch_row_from_dht(
 		&self,
 		block_number: u32,
+		row_commitments: &RowCommitments,
 		row_index: u32,
 	) -> Option<(u32, Vec<u8>)> {
 		let row_index = RowIndex(row_index);
@@ -689,7 +933,18 @@ impl Client {
 		trace!("Getting DHT record for reference {}", reference);
 
 		match self.get_kad_record(record_key).await {
-			Ok(peer_record) => Some((row_index.0, peer_record.record.value)),
+			Ok(peer_record) => {
+				let publisher = peer_record.peer.and(peer_record.record.publisher);
+				let data = peer_record.record.value;
+				if !self.check_row(row_commitments, row_index.0, &data) {
+					debug!("Row {reference} from the DHT doesn't match the row commitment");
+					if let Some(peer_id) = publisher {
+						self.report_invalid(peer_id).await;
+					}
+					return None;
+				}
+				Some((row_index.0, data))
+			},
 			Err(error) => {
 				debug!("Row {reference} not found in the DHT: {error}");
 				None
@@ -697,19 +952,324 @@ impl Client {
 		}
 	}
 
+	async fn send_cell_request(
+		&self,
+		peer_id: PeerId,
+		request: cell_exchange::Request,
+	) -> Result<cell_exchange::Response> {
+		self.execute_sync(|response_sender| {
+			Box::new(SendCellRequest {
+				peer_id,
+				request,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns connected peers which are asked directly for cells or rows.
+	/// Peers which announced the wanted cells or rows are preferred, other peers are picked randomly.
+	async fn direct_peers(&self, block_number: u32, wanted: Wanted) -> Vec<PeerId> {
+		let result = self
+			.execute_sync(|response_sender| {
+				Box::new(DirectPeers {
+					block_number,
+					wanted,
+					response_sender: Some(response_sender),
+				})
+			})
+			.await;
+
+		match result {
+			Ok(peers) => peers,
+			Err(error) => {
+				debug!("Cannot get connected peers: {error}");
+				vec![]
+			},
+		}
+	}
+
+	async fn report_malformed(&self, peer_id: PeerId) {
+		if let Err(error) = self
+			.report_peer(peer_id, Misbehaviour::MalformedRecord)
+			.await
+		{
+			debug!("Cannot report peer {peer_id}: {error}");
+		}
+	}
+
+	async fn report_invalid(&self, peer_id: PeerId) {
+		if let Err(error) = self.report_peer(peer_id, Misbehaviour::InvalidProof).await {
+			debug!("Cannot report peer {peer_id}: {error}");
+		}
+	}
+
+	/// Returns `true` if the fetched row matches its row commitment.
+	fn check_row(&self, row_commitments: &RowCommitments, row_index: u32, data: &[u8]) -> bool {
+		match row_commitments.check(row_index, data) {
+			Ok(is_valid) => is_valid,
+			Err(error) => {
+				debug!("Cannot check row {row_index}: {error}");
+				false
+			},
+		}
+	}
+
+	/// Returns fat clients providing the partitions which contain any of the positions.
+	/// Providers are dialed once they are found.
+	async fn partition_providers(
+		&self,
+		block_number: u32,
+		dimensions: Dimensions,
+		positions: &[Position],
+	) -> Vec<PeerId> {
+		let Some(fraction) = self.provider_partition_fraction else {
+			return vec![];
+		};
+
+		let get_providers = |partition: Partition| {
+			let key = providers::partition_key(block_number, &partition);
+			self.execute_sync(|response_sender| {
+				Box::new(GetProviders {
+					key,
+					response_sender: Some(response_sender),
+				})
+			})
+		};
+		let partitions = providers::partitions(dimensions, fraction, positions);
+		let results = join_all(partitions.into_iter().map(get_providers)).await;
+
+		let mut found = vec![];
+		for result in results {
+			match result {
+				Ok(peers) => found.extend(peers),
+				Err(error) => trace!("Cannot get partition providers: {error}"),
+			}
+		}
+		found.sort();
+		found.dedup();
+		found
+	}
+
+	/// Fetches cells directly from the connected peers, using the cell exchange protocol.
+	/// Returns fetched cells together with the peers which served them, and unfetched positions.
+	async fn fetch_cells_from_peers(
+		&self,
+		block_number: u32,
+		positions: &[Position],
+	) -> (Vec<(Cell, Option<PeerId>)>, Vec<Position>) {
+		let wanted = Wanted::Cells(positions.to_vec());
+		let peers = self.direct_peers(block_number, wanted).await;
+		self.request_cells(peers, block_number, positions).await
+	}
+
+	/// Fetches cells from the fat clients providing the partitions which contain the positions.
+	/// Returns fetched cells together with the peers which served them, and unfetched positions.
+	async fn fetch_cells_from_providers(
+		&self,
+		block_number: u32,
+		dimensions: Dimensions,
+		positions: &[Position],
+	) -> (Vec<(Cell, Option<PeerId>)>, Vec<Position>) {
+		let providers = self
+			.partition_providers(block_number, dimensions, positions)
+			.await;
+		self.request_cells(providers, block_number, positions).await
+	}
+
+	/// Requests cells from the peers in order, using the cell exchange protocol.
+	async fn request_cells(
+		&self,
+		peers: Vec<PeerId>,
+		block_number: u32,
+		positions: &[Position],
+	) -> (Vec<(Cell, Option<PeerId>)>, Vec<Position>) {
+		let mut fetched = vec![];
+		let mut unfetched = positions.to_vec();
+
+		for peer_id in peers {
+			if unfetched.is_empty() {
+				break;
+			}
+
+			let request = |positions: &[Position]| {
+				let request = cell_exchange::Request::cells(block_number, positions);
+				self.send_cell_request(peer_id, request)
+			};
+			let responses =
+				join_all(unfetched.chunks(cell_exchange::MAX_BATCH_SIZE).map(request)).await;
+
+			let requested = unfetched.iter().cloned().collect::<HashSet<_>>();
+			let mut fetched_positions = HashSet::new();
+			for response in responses {
+				let cells = match response {
+					Ok(cell_exchange::Response::Cells(cells)) => cells,
+					Ok(cell_exchange::Response::Rows(_)) => {
+						self.report_malformed(peer_id).await;
+						continue;
+					},
+					Err(error) => {
+						trace!("Cannot fetch cells from peer {peer_id}: {error}");
+						continue;
+					},
+				};
+
+				for CellContent { row, col, content } in cells {
+					let position = Position { row, col };
+					if !requested.contains(&position) || fetched_positions.contains(&position) {
+						continue;
+					}
+					let try_content: Result<[u8; config::COMMITMENT_SIZE + config::CHUNK_SIZE], _> =
+						content.try_into();
+					let Ok(content) = try_content else {
+						debug!("Cannot convert cell {row}:{col} from peer {peer_id} into 80 bytes");
+						self.report_malformed(peer_id).await;
+						continue;
+					};
+					fetched_positions.insert(position);
+					fetched.push((Cell { position, content }, Some(peer_id)));
+				}
+			}
+			unfetched.retain(|position| !fetched_positions.contains(position));
+		}
+
+		trace!(
+			block_number,
+			fetched = fetched.len(),
+			unfetched = unfetched.len(),
+			"Cells fetched directly from peers"
+		);
+		(fetched, unfetched)
+	}
+
+	/// Fetches rows directly from the connected peers, using the cell exchange protocol.
+	async fn fetch_rows_from_peers(
+		&self,
+		block_number: u32,
+		row_commitments: &RowCommitments,
+		row_indexes: &[u32],
+	) -> Vec<(u32, Vec<u8>)> {
+		let wanted = Wanted::Rows(row_indexes.to_vec());
+		let peers = self.direct_peers(block_number, wanted).await;
+		self.request_rows(peers, block_number, row_commitments, row_indexes)
+			.await
+	}
+
+	/// Fetches rows from the fat clients providing the partitions which contain the rows.
+	async fn fetch_rows_from_providers(
+		&self,
+		block_number: u32,
+		dimensions: Dimensions,
+		row_commitments: &RowCommitments,
+		row_indexes: &[u32],
+	) -> Vec<(u32, Vec<u8>)> {
+		let positions = row_indexes
+			.iter()
+			.map(|&row| Position { row, col: 0 })
+			.collect::<Vec<_>>();
+		let providers = self
+			.partition_providers(block_number, dimensions, &positions)
+			.await;
+		self.request_rows(providers, block_number, row_commitments, row_indexes)
+			.await
+	}
+
+	/// Requests rows from the peers in order, using the cell exchange protocol.
+	/// Rows are checked against the row commitments, and rows which don't match
+	/// are requested from the next peer, while the peer which served them is penalised.
+	async fn request_rows(
+		&self,
+		peers: Vec<PeerId>,
+		block_number: u32,
+		row_commitments: &RowCommitments,
+		row_indexes: &[u32],
+	) -> Vec<(u32, Vec<u8>)> {
+		let mut fetched = vec![];
+		let mut unfetched = row_indexes.to_vec();
+
+		for peer_id in peers {
+			if unfetched.is_empty() {
+				break;
+			}
+
+			let request = |row_indexes: &[u32]| {
+				let request = cell_exchange::Request::rows(block_number, row_indexes);
+				self.send_cell_request(peer_id, request)
+			};
+			let responses =
+				join_all(unfetched.chunks(cell_exchange::MAX_BATCH_SIZE).map(request)).await;
+
+			for response in responses {
+				let rows = match response {
+					Ok(cell_exchange::Response::Rows(rows)) => rows,
+					Ok(cell_exchange::Response::Cells(_)) => {
+						self.report_malformed(peer_id).await;
+						continue;
+					},
+					Err(error) => {
+						trace!("Cannot fetch rows from peer {peer_id}: {error}");
+						continue;
+					},
+				};
+
+				for RowContent { row, data } in rows {
+					if !unfetched.contains(&row) {
+						continue;
+					}
+					if !self.check_row(row_commitments, row, &data) {
+						debug!("Row {row} from peer {peer_id} doesn't match the row commitment");
+						self.report_invalid(peer_id).await;
+						continue;
+					}
+					unfetched.retain(|&unfetched| unfetched != row);
+					fetched.push((row, data));
+				}
+			}
+		}
+
+		fetched
+	}
+
 	/// Fetches cells from DHT.
 	/// Returns fetched cells and unfetched positions (so we can try RPC fetch).
 	///
 	/// # Arguments
 	///
 	/// * `block_number` - Block number
+	/// * `dimensions` - Block matrix dimensions
 	/// * `positions` - Cell positions to fetch
 	pub async fn fetch_cells_from_dht(
 		&self,
 		block_number: u32,
+		dimensions: Dimensions,
 		positions: &[Position],
 	) -> (Vec<Cell>, Vec<Position>) {
-		let mut cells = Vec::<Option<Cell>>::with_capacity(positions.len());
+		let (fetched, unfetched) = self
+			.fetch_cells_with_peers_from_dht(block_number, dimensions, positions)
+			.await;
+		let fetched = fetched.into_iter().map(|(cell, _)| cell).collect();
+		(fetched, unfetched)
+	}
+
+	/// Fetches cells from DHT, together with the peers which served them directly,
+	/// or published the DHT records. Peer is not set for the cells found in the local store.
+	/// Returns fetched cells and unfetched positions (so we can try RPC fetch).
+	///
+	/// # Arguments
+	///
+	/// * `block_number` - Block number
+	/// * `dimensions` - Block matrix dimensions
+	/// * `positions` - Cell positions to fetch
+	pub async fn fetch_cells_with_peers_from_dht(
+		&self,
+		block_number: u32,
+		dimensions: Dimensions,
+		positions: &[Position],
+	) -> (Vec<(Cell, Option<PeerId>)>, Vec<Position>) {
+		// Connected peers are asked directly first, remaining cells are looked up in the DHT
+		let (mut fetched, positions) = self.fetch_cells_from_peers(block_number, positions).await;
+
+		let mut cells = Vec::<Option<(Cell, Option<PeerId>)>>::with_capacity(positions.len());
 
 		for positions in positions.chunks(self.dht_parallelization_limit) {
 			let fetch = |&position| self.fetch_cell_from_dht(block_number, position);
@@ -719,37 +1279,93 @@ impl Client {
 
 		let unfetched = cells
 			.iter()
-			.zip(positions)
+			.zip(&positions)
 			.filter(|(cell, _)| cell.is_none())
 			.map(|(_, &position)| position)
 			.collect::<Vec<_>>();
 
-		let fetched = cells.into_iter().flatten().collect();
+		fetched.extend(cells.into_iter().flatten());
+
+		if unfetched.is_empty() {
+			return (fetched, unfetched);
+		}
+
+		// Cells which are not found in the DHT are requested from the partition providers
+		let (from_providers, unfetched) = self
+			.fetch_cells_from_providers(block_number, dimensions, &unfetched)
+			.await;
+		fetched.extend(from_providers);
 
 		(fetched, unfetched)
 	}
 
 	/// Fetches rows from DHT.
+	/// Rows which don't match the row commitments are skipped, and fetched from the next source.
 	/// Returns fetched rows and unfetched row indexes (so we can try RPC fetch).
 	///
 	/// # Arguments
 	///
+	/// * `pp` - Public parameters (i.e. SRS) needed for the row commitments
 	/// * `block_number` - Block number
+	/// * `dimensions` - Block matrix dimensions
+	/// * `commitments` - Block row commitments
 	/// * `rows` - Row indexes to fetch
 	pub async fn fetch_rows_from_dht(
 		&self,
+		pp: &PublicParameters,
 		block_number: u32,
 		dimensions: Dimensions,
+		commitments: &[[u8; config::COMMITMENT_SIZE]],
 		row_indexes: &[u32],
 	) -> Vec<Option<Vec<u8>>> {
 		let mut rows = vec![None; dimensions.extended_rows() as usize];
+
+		let row_commitments = match RowCommitments::new(pp, dimensions, commitments) {
+			Ok(row_commitments) => row_commitments,
+			Err(error) => {
+				debug!(block_number, "Cannot fetch rows: {error}");
+				return rows;
+			},
+		};
+
+		// Connected peers are asked directly first, remaining rows are looked up in the DHT
+		let fetched = self
+			.fetch_rows_from_peers(block_number, &row_commitments, row_indexes)
+			.await;
+		for (row_index, row) in fetched {
+			if let Some(entry) = rows.get_mut(row_index as usize) {
+				*entry = Some(row);
+			}
+		}
+		let row_indexes = row_indexes
+			.iter()
+			.filter(|&&row_index| matches!(rows.get(row_index as usize), Some(None)))
+			.cloned()
+			.collect::<Vec<_>>();
+
 		for row_indexes in row_indexes.chunks(self.dht_parallelization_limit) {
-			let fetch = |row| self.fetch_row_from_dht(block_number, row);
+			let fetch = |row| self.fetch_row_from_dht(block_number, &row_commitments, row);
 			let fetched_rows = join_all(row_indexes.iter().cloned().map(fetch)).await;
 			for (row_index, row) in fetched_rows.into_iter().flatten() {
 				rows[row_index as usize] = Some(row);
 			}
 		}
+
+		// Rows which are not found in the DHT are requested from the partition providers
+		let row_indexes = row_indexes
+			.into_iter()
+			.filter(|&row_index| rows[row_index as usize].is_none())
+			.collect::<Vec<_>>();
+		if !row_indexes.is_empty() {
+			let fetched = self
+				.fetch_rows_from_providers(block_number, dimensions, &row_commitments, &row_indexes)
+				.await;
+			for (row_index, row) in fetched {
+				if let Some(entry) = rows.get_mut(row_index as usize) {
+					*entry = Some(row);
+				}
+			}
+		}
 		rows
 	}
 
@@ -808,6 +1424,55 @@ impl Client {
 		self.insert_into_dht(records, block).await
 	}
 
+	/// Announces on the gossipsub announcements topic that the block data is held by the local peer.
+	/// Announcements exceeding the rate limit are skipped.
+	///
+	/// # Arguments
+	///
+	/// * `block_number` - Block number
+	/// * `holding` - Partition, rows or cells held by the local peer
+	pub async fn announce(&self, block_number: u32, holding: Holding) -> Result<()> {
+		self.command_sender
+			.send(Box::new(Announce {
+				announcement: Announcement {
+					block_number,
+					holding,
+				},
+			}))
+			.context("failed to announce")
+	}
+
+	/// Sets the latest verified block, only announcements of the blocks around it are accepted.
+	pub async fn set_latest_block(&self, block_number: u32) -> Result<()> {
+		self.command_sender
+			.send(Box::new(SetLatestBlock { block_number }))
+			.context("failed to set latest block")
+	}
+
+	/// Stores validated inbound record into the local record store.
+	pub async fn store_record(&self, record: Record) -> Result<()> {
+		self.command_sender
+			.send(Box::new(StoreRecord { record }))
+			.context("failed to store record")
+	}
+
+	/// Publishes provider record for the block partition, so light and app clients can find and dial the fat client.
+	/// Uploaded records of the latest provided blocks are kept in the local store,
+	/// and older blocks are no longer provided.
+	///
+	/// # Arguments
+	///
+	/// * `block_number` - Block number
+	/// * `partition` - Partition uploaded into the DHT
+	pub async fn start_providing(&self, block_number: u32, partition: Partition) -> Result<()> {
+		self.command_sender
+			.send(Box::new(StartProviding {
+				block_number,
+				partition,
+			}))
+			.context("failed to start providing")
+	}
+
 	pub async fn get_multiaddress_and_ip(&self) -> Result<Vec<String>> {
 		let addr = self
 			.get_multiaddress()
@@ -2,15 +2,17 @@ use color_eyre::{eyre::eyre, Result};
 use futures::StreamExt;
 use libp2p::{
 	autonat::{self, NatStatus},
-	dcutr,
+	dcutr, gossipsub,
 	identify::{self, Info},
 	identity::Keypair,
 	kad::{
-		self, BootstrapOk, GetRecordOk, InboundRequest, QueryId, QueryResult, QueryStats, RecordKey,
+		self, store::RecordStore, BootstrapOk, GetProvidersOk, GetRecordOk, InboundRequest,
+		QueryId, QueryResult, QueryStats, RecordKey,
 	},
 	mdns,
 	multiaddr::Protocol,
 	ping,
+	request_response::{self, OutboundFailure, OutboundRequestId},
 	swarm::{
 		dial_opts::{DialOpts, PeerCondition},
 		ConnectionError, SwarmEvent,
@@ -26,17 +28,24 @@ use tokio::{
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-	network::p2p::kad_mem_store::MemoryStore,
 	shutdown::Controller,
 	telemetry::{MetricCounter, MetricValue, Metrics},
 	types::{AgentVersion, IdentifyConfig, KademliaMode, LibP2PConfig, TimeToLive},
 };
 
 use super::{
-	build_swarm, client::BlockStat, Behaviour, BehaviourEvent, CommandReceiver, EventLoopEntries,
-	QueryChannel, SendableCommand,
+	announcements::{Announcement, Announcements},
+	build_swarm, cell_exchange,
+	client::BlockStat,
+	providers::UploadedRecords,
+	record_validation::{InboundRecord, RecordSender},
+	report_peer, Behaviour, BehaviourEvent, CellExchangeChannel, CommandReceiver, EventLoopEntries,
+	Misbehaviour, PeerReputation, QueryChannel, SendableCommand, Store,
 };
 
+/// Interval in which expired peer blocks are removed
+const REPUTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 // RelayState keeps track of all things relay related
 struct RelayState {
 	// id of the selected Relay that needs to be connected
@@ -89,17 +98,31 @@ pub struct EventLoop {
 	pending_kad_queries: HashMap<QueryId, QueryChannel>,
 	// Tracking swarm events (i.e. peer dialing)
 	pending_swarm_events: HashMap<PeerId, oneshot::Sender<Result<()>>>,
+	// Tracking direct cell and row requests
+	pending_cell_requests: HashMap<OutboundRequestId, CellExchangeChannel>,
+	/// Rate limiter of the inbound direct cell and row requests
+	cell_requests_limiter: cell_exchange::RateLimiter,
 	relay: RelayState,
 	bootstrap: BootstrapState,
 	/// Blocks we monitor for PUT success rate
 	active_blocks: HashMap<u32, BlockStat>,
+	/// Scores of the misbehaving peers, shared with the API
+	reputation: PeerReputation,
+	/// Timer responsible for unblocking peers with expired blocks
+	reputation_timer: Interval,
+	/// Block availability announcements received from other peers
+	announcements: Announcements,
+	/// Keys of the records uploaded by the fat client, removed once the block is no longer provided
+	uploaded_records: Option<UploadedRecords>,
+	/// Inbound records are stored after they are validated
+	record_sender: RecordSender,
 	shutdown: Controller<String>,
 
 	event_loop_config: EventLoopConfig,
 }
 
 #[derive(PartialEq, Debug)]
-enum DHTKey {
+pub enum DHTKey {
 	Cell(u32, u32, u32),
 	Row(u32, u32),
 }
@@ -125,15 +148,15 @@ impl EventLoop {
 	pub async fn new(
 		cfg: LibP2PConfig,
 		id_keys: &Keypair,
+		kad_store: Store,
 		is_fat_client: bool,
 		is_ws_transport: bool,
+		reputation: PeerReputation,
+		record_sender: RecordSender,
 		shutdown: Controller<String>,
 	) -> Self {
 		let bootstrap_interval = cfg.bootstrap_interval;
-		let peer_id = id_keys.public().to_peer_id();
-		let store = MemoryStore::with_config(peer_id, (&cfg).into());
-
-		let swarm = build_swarm(&cfg, id_keys, store, is_ws_transport)
+		let swarm = build_swarm(&cfg, id_keys, kad_store, is_ws_transport)
 			.await
 			.expect("Unable to build swarm.");
 
@@ -141,6 +164,8 @@ impl EventLoop {
 			swarm,
 			pending_kad_queries: Default::default(),
 			pending_swarm_events: Default::default(),
+			pending_cell_requests: Default::default(),
+			cell_requests_limiter: Default::default(),
 			relay: RelayState {
 				id: PeerId::random(),
 				address: Multiaddr::empty(),
@@ -152,6 +177,14 @@ impl EventLoop {
 				timer: interval_at(Instant::now() + bootstrap_interval, bootstrap_interval),
 			},
 			active_blocks: Default::default(),
+			reputation,
+			reputation_timer: interval_at(
+				Instant::now() + REPUTATION_CHECK_INTERVAL,
+				REPUTATION_CHECK_INTERVAL,
+			),
+			announcements: Announcements::new(&cfg.identify.announcements_topic),
+			uploaded_records: is_fat_client.then(UploadedRecords::default),
+			record_sender,
 			shutdown,
 			event_loop_config: EventLoopConfig {
 				identity_data: cfg.identify,
@@ -180,6 +213,7 @@ impl EventLoop {
 					},
 				},
 				_ = self.bootstrap.timer.tick() => self.handle_periodic_bootstraps(),
+				_ = self.reputation_timer.tick() => self.handle_reputation_expiry(metrics.clone()).await,
 				// if the shutdown was triggered,
 				// break the loop immediately, proceed to the cleanup phase
 				_ = self.shutdown.triggered_shutdown() => {
@@ -234,12 +268,36 @@ impl EventLoop {
 							metrics.count(MetricCounter::IncomingPutRecord).await;
 							match record {
 								Some(mut record) => {
+									let key = match DHTKey::try_from(record.key.clone()) {
+										Ok(key) => key,
+										Err(error) => {
+											debug!("Rejecting record with invalid key from {source}: {error}");
+											metrics.count(MetricCounter::RejectedPutRecord).await;
+											report_peer(
+												&mut self.swarm,
+												&self.reputation,
+												source,
+												Misbehaviour::MalformedRecord,
+											);
+											return;
+										},
+									};
+
 									let ttl = &self.event_loop_config.kad_record_ttl;
 
 									// Set TTL for all incoming records
 									// TTL will be set to a lower value between the local TTL and incoming record TTL
 									record.expires = record.expires.min(ttl.expires());
-									_ = self.swarm.behaviour_mut().kademlia.store_mut().put(record);
+
+									// Record is stored once it is validated against the block commitments
+									let record = InboundRecord {
+										source,
+										key,
+										record,
+									};
+									if self.record_sender.try_send(record).is_err() {
+										trace!("Record validation queue is full, dropping record from {source}");
+									}
 								},
 								None => {
 									debug!("Received empty cell record from: {source:?}");
@@ -273,6 +331,43 @@ impl EventLoop {
 							},
 							_ => (),
 						},
+						QueryResult::GetProviders(result) => match result {
+							Ok(GetProvidersOk::FoundProviders { providers, .. }) => {
+								if let Some(QueryChannel::GetProviders(ch)) =
+									self.pending_kad_queries.remove(&id)
+								{
+									let providers = providers.into_iter().collect::<Vec<_>>();
+									// Providers are dialed while the query is active, so their addresses are known
+									for &provider in &providers {
+										_ = self.swarm.dial(
+											DialOpts::peer_id(provider)
+												.condition(PeerCondition::DisconnectedAndNotDialing)
+												.build(),
+										);
+									}
+									_ = ch.send(Ok(providers));
+									if let Some(mut query) =
+										self.swarm.behaviour_mut().kademlia.query_mut(&id)
+									{
+										query.finish();
+									}
+								}
+							},
+							Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => {
+								if let Some(QueryChannel::GetProviders(ch)) =
+									self.pending_kad_queries.remove(&id)
+								{
+									_ = ch.send(Ok(vec![]));
+								}
+							},
+							Err(err) => {
+								if let Some(QueryChannel::GetProviders(ch)) =
+									self.pending_kad_queries.remove(&id)
+								{
+									_ = ch.send(Err(err.into()));
+								}
+							},
+						},
 						QueryResult::PutRecord(Err(error)) => {
 							if self.pending_kad_queries.remove(&id).is_none() {
 								return;
@@ -437,13 +532,30 @@ impl EventLoop {
 					trace!("Hole punching failed with: {remote_peer_id:#?}. Error: {err:#?}")
 				},
 			},
-			SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event { result, .. })) => {
-				if let Ok(rtt) = result {
-					let _ = metrics
-						.record(MetricValue::PingLatency(rtt.as_millis() as f64))
-						.await;
+			SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event { peer, result, .. })) => {
+				match result {
+					Ok(rtt) => {
+						let _ = metrics
+							.record(MetricValue::PingLatency(rtt.as_millis() as f64))
+							.await;
+					},
+					Err(ping::Failure::Timeout) => {
+						report_peer(
+							&mut self.swarm,
+							&self.reputation,
+							peer,
+							Misbehaviour::Timeout,
+						);
+					},
+					Err(_) => (),
 				}
 			},
+			SwarmEvent::Behaviour(BehaviourEvent::CellExchange(event)) => {
+				self.handle_cell_exchange_event(event)
+			},
+			SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(event)) => {
+				self.handle_gossipsub_event(event)
+			},
 			SwarmEvent::Behaviour(BehaviourEvent::Upnp(event)) => match event {
 				upnp::Event::NewExternalAddr(addr) => {
 					trace!("[UPnP] New external address: {addr}");
@@ -539,12 +651,146 @@ impl EventLoop {
 			&mut self.swarm,
 			&mut self.pending_kad_queries,
 			&mut self.pending_swarm_events,
+			&mut self.pending_cell_requests,
 			&mut self.active_blocks,
+			&self.reputation,
+			&mut self.announcements,
+			self.uploaded_records.as_mut(),
 		)) {
 			command.abort(eyre!(err));
 		}
 	}
 
+	fn handle_cell_exchange_event(&mut self, event: cell_exchange::Event) {
+		match event {
+			request_response::Event::Message { peer, message } => match message {
+				request_response::Message::Request {
+					request, channel, ..
+				} => {
+					trace!("Cell exchange request from {peer}: {request:?}");
+					let response = if self
+						.cell_requests_limiter
+						.allow(peer, Instant::now().into_std())
+					{
+						let store = self.swarm.behaviour_mut().kademlia.store_mut();
+						cell_exchange::respond(store, request)
+					} else {
+						debug!("Cell exchange request from {peer} exceeded the rate limit");
+						cell_exchange::Response::empty(&request)
+					};
+					if self
+						.swarm
+						.behaviour_mut()
+						.cell_exchange
+						.send_response(channel, response)
+						.is_err()
+					{
+						debug!("Cannot send cell exchange response to {peer}, connection closed");
+					}
+				},
+				request_response::Message::Response {
+					request_id,
+					response,
+				} => {
+					if let Some(ch) = self.pending_cell_requests.remove(&request_id) {
+						_ = ch.send(Ok(response));
+					}
+				},
+			},
+			request_response::Event::OutboundFailure {
+				peer,
+				request_id,
+				error,
+			} => {
+				trace!("Cell exchange request to {peer} failed: {error}");
+				if let OutboundFailure::Timeout = error {
+					report_peer(
+						&mut self.swarm,
+						&self.reputation,
+						peer,
+						Misbehaviour::Timeout,
+					);
+				}
+				if let Some(ch) = self.pending_cell_requests.remove(&request_id) {
+					_ = ch.send(Err(error.into()));
+				}
+			},
+			request_response::Event::InboundFailure { peer, error, .. } => {
+				trace!("Cell exchange response to {peer} failed: {error}");
+			},
+			request_response::Event::ResponseSent { .. } => {},
+		}
+	}
+
+	fn handle_gossipsub_event(&mut self, event: gossipsub::Event) {
+		let gossipsub::Event::Message {
+			propagation_source,
+			message_id,
+			message,
+		} = event
+		else {
+			trace!("Gossipsub event: {event:?}");
+			return;
+		};
+
+		if message.topic != self.announcements.topic().hash() {
+			return;
+		}
+
+		// Messages are signed, so the source is always set
+		let Some(author) = message.source else {
+			return;
+		};
+
+		let acceptance = match serde_json::from_slice::<Announcement>(&message.data) {
+			Ok(_) if !self.announcements.rate_limit(author) => {
+				trace!("Announcement from {author} exceeds the rate limit");
+				gossipsub::MessageAcceptance::Ignore
+			},
+			Ok(announcement) => {
+				trace!("Announcement from {author}: {announcement:?}");
+				if self.announcements.insert(author, announcement) {
+					gossipsub::MessageAcceptance::Accept
+				} else {
+					trace!("Announcement from {author} is outside of the announced blocks window");
+					gossipsub::MessageAcceptance::Ignore
+				}
+			},
+			Err(error) => {
+				debug!("Cannot decode announcement from {author}: {error}");
+				report_peer(
+					&mut self.swarm,
+					&self.reputation,
+					propagation_source,
+					Misbehaviour::MalformedRecord,
+				);
+				gossipsub::MessageAcceptance::Reject
+			},
+		};
+
+		_ = self
+			.swarm
+			.behaviour_mut()
+			.gossipsub
+			.report_message_validation_result(&message_id, &propagation_source, acceptance);
+	}
+
+	async fn handle_reputation_expiry(&mut self, metrics: Arc<impl Metrics>) {
+		for peer_id in self.reputation.take_expired(Instant::now()) {
+			debug!("Unblocking peer {peer_id}, block expired");
+			self.swarm
+				.behaviour_mut()
+				.blocked_peers
+				.unblock_peer(peer_id);
+		}
+
+		let (penalised, blocked) = self.reputation.counts();
+		_ = metrics
+			.record(MetricValue::PenalisedPeersNum(penalised))
+			.await;
+		_ = metrics.record(MetricValue::BlockedPeersNum(blocked)).await;
+	}
+
 	fn handle_periodic_bootstraps(&mut self) {
 		// commence with periodic bootstraps,
 		// only when the initial startup bootstrap is done
@@ -646,12 +892,6 @@ impl EventLoop {
 					.record(MetricValue::DHTPutDuration(block.time_stat as f64))
 					.await;
 			}
-
-			if self.event_loop_config.is_fat_client {
-				// Remove local records for fat clients (memory optimization)
-				debug!("Pruning local records on fat client");
-				self.swarm.behaviour_mut().kademlia.remove_record(&key);
-			}
 		} else {
 			debug!("Can't find block in the active blocks list")
 		}
@@ -89,7 +89,6 @@ impl MemoryStore {
 		}
 	}
 
-	#[allow(dead_code)]
 	/// Retains the records satisfying a predicate.
 	pub fn retain<F>(&mut self, f: F)
 	where
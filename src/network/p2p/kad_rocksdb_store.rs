@@ -0,0 +1,322 @@
+use super::kad_mem_store::{MemoryStore, MemoryStoreConfig};
+use crate::data::KADEMLIA_STORE_CF;
+use codec::{Decode, Encode};
+use color_eyre::eyre::{eyre, WrapErr};
+use libp2p::identity::PeerId;
+use libp2p::kad::store::{Error, RecordStore, Result};
+use libp2p::kad::{ProviderRecord, Record, RecordKey};
+use rocksdb::{BoundColumnFamily, IteratorMode, WriteBatch};
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, trace};
+
+/// RocksDB implementation of a `RecordStore`.
+///
+/// Records are stored on disk, so they are served after the restart.
+/// Provider records are kept in memory.
+pub struct RocksDBStore {
+	db: Arc<rocksdb::DB>,
+	/// The configuration of the store.
+	config: MemoryStoreConfig,
+	/// Number of stored records, used to enforce the records limit without scanning the store.
+	records_count: usize,
+	/// The stored provider records.
+	providers: MemoryStore,
+}
+
+/// Record as stored on disk, with expiration time as UNIX timestamp in milliseconds.
+#[derive(Encode, Decode)]
+struct StoredRecord {
+	value: Vec<u8>,
+	publisher: Option<Vec<u8>>,
+	expires: Option<u64>,
+}
+
+fn to_timestamp(instant: Instant) -> u64 {
+	let (now, system_now) = (Instant::now(), SystemTime::now());
+	let time = if instant >= now {
+		system_now.checked_add(instant - now)
+	} else {
+		system_now.checked_sub(now - instant)
+	};
+	time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+		.map(|duration| duration.as_millis() as u64)
+		.unwrap_or_default()
+}
+
+fn to_instant(timestamp: u64) -> Instant {
+	let (now, system_now) = (Instant::now(), SystemTime::now());
+	let time = UNIX_EPOCH + Duration::from_millis(timestamp);
+	// Expired records are set to expire now
+	match time.duration_since(system_now) {
+		Ok(remaining) => now.checked_add(remaining).unwrap_or(now),
+		Err(error) => now.checked_sub(error.duration()).unwrap_or(now),
+	}
+}
+
+impl From<&Record> for StoredRecord {
+	fn from(record: &Record) -> Self {
+		StoredRecord {
+			value: record.value.clone(),
+			publisher: record.publisher.map(|peer_id| peer_id.to_bytes()),
+			expires: record.expires.map(to_timestamp),
+		}
+	}
+}
+
+fn decode_record(key: &[u8], value: &[u8]) -> Option<Record> {
+	let stored = StoredRecord::decode(&mut &value[..])
+		.map_err(|error| trace!("Cannot decode stored Kademlia record: {error}"))
+		.ok()?;
+	Some(Record {
+		key: RecordKey::from(key.to_vec()),
+		value: stored.value,
+		publisher: stored
+			.publisher
+			.and_then(|publisher| PeerId::from_bytes(&publisher).ok()),
+		expires: stored.expires.map(to_instant),
+	})
+}
+
+fn cf_handle(db: &rocksdb::DB) -> Arc<BoundColumnFamily<'_>> {
+	db.cf_handle(KADEMLIA_STORE_CF)
+		.expect("Kademlia store column family is checked on store creation")
+}
+
+impl RocksDBStore {
+	/// Creates a new `RocksDBStore` with the given configuration, counting already stored records.
+	pub fn with_config(
+		db: Arc<rocksdb::DB>,
+		local_id: PeerId,
+		config: MemoryStoreConfig,
+	) -> color_eyre::Result<Self> {
+		let records_count = {
+			let cf_handle = db
+				.cf_handle(KADEMLIA_STORE_CF)
+				.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+			let mut count = 0;
+			for item in db.iterator_cf(&cf_handle, IteratorMode::Start) {
+				item.wrap_err("Iteration with Column Family failed on RocksDB")?;
+				count += 1;
+			}
+			count
+		};
+
+		Ok(RocksDBStore {
+			db,
+			providers: MemoryStore::with_config(local_id, config.clone()),
+			config,
+			records_count,
+		})
+	}
+
+	/// Retains the records satisfying a predicate.
+	/// Records modified by the predicate are stored back.
+	pub fn retain<F>(&mut self, mut f: F)
+	where
+		F: FnMut(&RecordKey, &mut Record) -> bool,
+	{
+		let cf_handle = cf_handle(&self.db);
+		let mut batch = WriteBatch::default();
+		let mut removed = 0;
+
+		for (key, value) in self
+			.db
+			.iterator_cf(&cf_handle, IteratorMode::Start)
+			.filter_map(|item| item.ok())
+		{
+			let Some(mut record) = decode_record(&key, &value) else {
+				batch.delete_cf(&cf_handle, key);
+				removed += 1;
+				continue;
+			};
+			let original = record.clone();
+			if !f(&original.key, &mut record) {
+				batch.delete_cf(&cf_handle, key);
+				removed += 1;
+			} else if record != original {
+				batch.put_cf(&cf_handle, key, StoredRecord::from(&record).encode());
+			}
+		}
+
+		if let Err(error) = self.db.write(batch) {
+			error!("Failed to prune Kademlia records: {error}");
+			return;
+		}
+		self.records_count = self.records_count.saturating_sub(removed);
+	}
+
+	/// Returns the number of stored records.
+	pub fn records_count(&self) -> usize {
+		self.records_count
+	}
+}
+
+impl RecordStore for RocksDBStore {
+	type RecordsIter<'a> = Box<dyn Iterator<Item = Cow<'a, Record>> + 'a>;
+
+	type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+	fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+		let value = self
+			.db
+			.get_pinned_cf(&cf_handle(&self.db), k.to_vec())
+			.map_err(|error| error!("Failed to get Kademlia record: {error}"))
+			.ok()??;
+		decode_record(&k.to_vec(), &value).map(Cow::Owned)
+	}
+
+	fn put(&mut self, r: Record) -> Result<()> {
+		if r.value.len() >= self.config.max_value_bytes {
+			return Err(Error::ValueTooLarge);
+		}
+
+		let cf_handle = cf_handle(&self.db);
+		let key = r.key.to_vec();
+		let exists = matches!(self.db.get_pinned_cf(&cf_handle, &key), Ok(Some(_)));
+		if !exists && self.records_count >= self.config.max_records {
+			return Err(Error::MaxRecords);
+		}
+
+		if let Err(error) = self
+			.db
+			.put_cf(&cf_handle, key, StoredRecord::from(&r).encode())
+		{
+			// Store errors have no variant for I/O failures, record is rejected as if the store is full
+			error!("Failed to store Kademlia record: {error}");
+			return Err(Error::MaxRecords);
+		}
+
+		if !exists {
+			self.records_count += 1;
+		}
+		Ok(())
+	}
+
+	fn remove(&mut self, k: &RecordKey) {
+		let cf_handle = cf_handle(&self.db);
+		let key = k.to_vec();
+		if !matches!(self.db.get_pinned_cf(&cf_handle, &key), Ok(Some(_))) {
+			return;
+		}
+		match self.db.delete_cf(&cf_handle, key) {
+			Ok(()) => self.records_count = self.records_count.saturating_sub(1),
+			Err(error) => error!("Failed to remove Kademlia record: {error}"),
+		}
+	}
+
+	fn records(&self) -> Self::RecordsIter<'_> {
+		Box::new(
+			self.db
+				.iterator_cf(&cf_handle(&self.db), IteratorMode::Start)
+				.filter_map(|item| item.ok())
+				.filter_map(|(key, value)| decode_record(&key, &value))
+				.map(Cow::Owned),
+		)
+	}
+
+	fn add_provider(&mut self, record: ProviderRecord) -> Result<()> {
+		self.providers.add_provider(record)
+	}
+
+	fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+		self.providers.providers(key)
+	}
+
+	fn provided(&self) -> Self::ProvidedIter<'_> {
+		self.providers.provided()
+	}
+
+	fn remove_provider(&mut self, key: &RecordKey, provider: &PeerId) {
+		self.providers.remove_provider(key, provider)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{data::rocks_db::RocksDB, utils::test_utils::TempDir};
+
+	fn store(path: &TempDir) -> RocksDBStore {
+		let db = RocksDB::open(&path.0).unwrap();
+		RocksDBStore::with_config(db.inner(), PeerId::random(), Default::default()).unwrap()
+	}
+
+	fn record(key: &str, expires: Option<Instant>) -> Record {
+		Record {
+			key: RecordKey::from(key.as_bytes().to_vec()),
+			value: vec![1, 2, 3],
+			publisher: Some(PeerId::random()),
+			expires,
+		}
+	}
+
+	#[test]
+	fn put_get_remove_record() {
+		let path = TempDir::new();
+		let mut store = store(&path);
+		let r = record("1:2:3", None);
+		assert!(store.put(r.clone()).is_ok());
+		assert_eq!(Some(Cow::Owned(r.clone())), store.get(&r.key));
+		assert_eq!(store.records_count(), 1);
+		store.remove(&r.key);
+		assert!(store.get(&r.key).is_none());
+		assert_eq!(store.records_count(), 0);
+	}
+
+	#[test]
+	fn records_survive_reopen() {
+		let path = TempDir::new();
+		let expires = Instant::now() + Duration::from_secs(60);
+		{
+			let mut store = store(&path);
+			store.put(record("1:0:0", Some(expires))).unwrap();
+			store.put(record("1:0:1", None)).unwrap();
+		}
+
+		let store = store(&path);
+		assert_eq!(store.records_count(), 2);
+		let restored = store
+			.get(&RecordKey::from(b"1:0:0".to_vec()))
+			.unwrap()
+			.expires
+			.unwrap();
+		let difference = restored.max(expires) - restored.min(expires);
+		assert!(difference < Duration::from_secs(1));
+	}
+
+	#[test]
+	fn retain_removes_expired_records() {
+		let path = TempDir::new();
+		let mut store = store(&path);
+		let now = Instant::now();
+		store.put(record("1:0:0", Some(now))).unwrap();
+		store
+			.put(record("1:0:1", Some(now + Duration::from_secs(60))))
+			.unwrap();
+		store.put(record("1:0:2", None)).unwrap();
+
+		store.retain(|_, record| !record.is_expired(now + Duration::from_secs(1)));
+		assert_eq!(store.records_count(), 2);
+		assert_eq!(store.records().count(), 2);
+		assert!(store.get(&RecordKey::from(b"1:0:0".to_vec())).is_none());
+	}
+
+	#[test]
+	fn max_records() {
+		let path = TempDir::new();
+		let db = RocksDB::open(&path.0).unwrap();
+		let config = MemoryStoreConfig {
+			max_records: 1,
+			..Default::default()
+		};
+		let mut store = RocksDBStore::with_config(db.inner(), PeerId::random(), config).unwrap();
+		store.put(record("1:0:0", None)).unwrap();
+		store.put(record("1:0:0", None)).unwrap();
+		assert!(matches!(
+			store.put(record("1:0:1", None)),
+			Err(Error::MaxRecords)
+		));
+	}
+}
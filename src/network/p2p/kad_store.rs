@@ -0,0 +1,129 @@
+use super::{kad_mem_store::MemoryStore, kad_rocksdb_store::RocksDBStore};
+use crate::{
+	data::rocks_db::RocksDB,
+	types::{LibP2PConfig, RecordStoreType},
+};
+use color_eyre::eyre::eyre;
+use libp2p::identity::PeerId;
+use libp2p::kad::store::{RecordStore, Result};
+use libp2p::kad::{ProviderRecord, Record, RecordKey};
+use std::borrow::Cow;
+
+/// Kademlia record store, selected with the `kad_record_store` configuration parameter.
+pub enum Store {
+	Memory(MemoryStore),
+	RocksDB(RocksDBStore),
+}
+
+impl Store {
+	/// Creates a new record store of the configured type.
+	/// RocksDB record store requires RocksDB database backend.
+	pub fn new(
+		cfg: &LibP2PConfig,
+		local_id: PeerId,
+		db: Option<&RocksDB>,
+	) -> color_eyre::Result<Self> {
+		Ok(match (cfg.kademlia.kad_record_store, db) {
+			(RecordStoreType::Memory, _) => {
+				Store::Memory(MemoryStore::with_config(local_id, cfg.into()))
+			},
+			(RecordStoreType::RocksDB, Some(db)) => {
+				Store::RocksDB(RocksDBStore::with_config(db.inner(), local_id, cfg.into())?)
+			},
+			(RecordStoreType::RocksDB, None) => {
+				return Err(eyre!(
+					"RocksDB Kademlia record store is not supported with in-memory database"
+				))
+			},
+		})
+	}
+
+	/// Retains the records satisfying a predicate.
+	pub fn retain<F>(&mut self, f: F)
+	where
+		F: FnMut(&RecordKey, &mut Record) -> bool,
+	{
+		match self {
+			Store::Memory(store) => store.retain(f),
+			Store::RocksDB(store) => store.retain(f),
+		}
+	}
+
+	/// Returns the number of stored records.
+	pub fn records_count(&mut self) -> usize {
+		match self {
+			Store::Memory(store) => store.records_iter().len(),
+			Store::RocksDB(store) => store.records_count(),
+		}
+	}
+
+	/// Shrinks the capacity of the in-memory store as much as possible.
+	/// Disk space of the removed records is reclaimed by RocksDB compaction.
+	pub fn shrink(&mut self) {
+		if let Store::Memory(store) = self {
+			store.shrink_hashmap();
+		}
+	}
+}
+
+impl RecordStore for Store {
+	type RecordsIter<'a> = Box<dyn Iterator<Item = Cow<'a, Record>> + 'a>;
+
+	type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+	fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+		match self {
+			Store::Memory(store) => store.get(k),
+			Store::RocksDB(store) => store.get(k),
+		}
+	}
+
+	fn put(&mut self, r: Record) -> Result<()> {
+		match self {
+			Store::Memory(store) => store.put(r),
+			Store::RocksDB(store) => store.put(r),
+		}
+	}
+
+	fn remove(&mut self, k: &RecordKey) {
+		match self {
+			Store::Memory(store) => store.remove(k),
+			Store::RocksDB(store) => store.remove(k),
+		}
+	}
+
+	fn records(&self) -> Self::RecordsIter<'_> {
+		match self {
+			Store::Memory(store) => Box::new(store.records()),
+			Store::RocksDB(store) => store.records(),
+		}
+	}
+
+	fn add_provider(&mut self, record: ProviderRecord) -> Result<()> {
+		match self {
+			Store::Memory(store) => store.add_provider(record),
+			Store::RocksDB(store) => store.add_provider(record),
+		}
+	}
+
+	fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+		match self {
+			Store::Memory(store) => store.providers(key),
+			Store::RocksDB(store) => store.providers(key),
+		}
+	}
+
+	fn provided(&self) -> Self::ProvidedIter<'_> {
+		match self {
+			Store::Memory(store) => store.provided(),
+			Store::RocksDB(store) => store.provided(),
+		}
+	}
+
+	fn remove_provider(&mut self, key: &RecordKey, provider: &PeerId) {
+		match self {
+			Store::Memory(store) => store.remove_provider(key, provider),
+			Store::RocksDB(store) => store.remove_provider(key, provider),
+		}
+	}
+}
@@ -0,0 +1,119 @@
+//! Partition provider records
+//!
+//! Fat clients publish Kademlia provider records for each block and partition they upload into the DHT,
+//! and keep the uploaded records of the latest provided blocks in the local store.
+//! Keys of the uploaded records are tracked per block, so records of older blocks are removed directly.
+//! Light and app clients look up providers of the partitions containing the cells which are not found in the DHT,
+//! and fetch the cells directly from the providers.
+
+use kate_recovery::matrix::{Dimensions, Partition, Position};
+use libp2p::kad::RecordKey;
+use std::collections::{BTreeMap, HashSet};
+
+/// Number of the latest blocks provided by the fat client
+pub const PROVIDED_BLOCKS: u32 = 8;
+
+/// Provider record key for the block partition.
+pub fn partition_key(block_number: u32, partition: &Partition) -> RecordKey {
+	let Partition { number, fraction } = partition;
+	RecordKey::new(&format!("{block_number}:partition:{number}/{fraction}"))
+}
+
+/// Parses block number from the record or provider record key.
+pub fn block_number(key: &RecordKey) -> Option<u32> {
+	let key = std::str::from_utf8(key.as_ref()).ok()?;
+	key.split(':').next()?.parse().ok()
+}
+
+/// Keys of the records uploaded by the fat client, per block
+#[derive(Default)]
+pub struct UploadedRecords(BTreeMap<u32, Vec<RecordKey>>);
+
+impl UploadedRecords {
+	pub fn insert(&mut self, block_number: u32, keys: impl IntoIterator<Item = RecordKey>) {
+		self.0.entry(block_number).or_default().extend(keys);
+	}
+
+	/// Removes and returns keys of the records uploaded for the blocks older than the given block.
+	pub fn take_older(&mut self, block_number: u32) -> Vec<RecordKey> {
+		let newer = self.0.split_off(&block_number);
+		std::mem::replace(&mut self.0, newer)
+			.into_values()
+			.flatten()
+			.collect()
+	}
+}
+
+/// Returns partitions with the given fraction, which contain any of the positions.
+pub fn partitions(dimensions: Dimensions, fraction: u8, positions: &[Position]) -> Vec<Partition> {
+	let positions = positions.iter().collect::<HashSet<_>>();
+	(1..=fraction)
+		.map(|number| Partition { number, fraction })
+		.filter(|partition| {
+			dimensions
+				.iter_extended_partition_positions(partition)
+				.any(|position| positions.contains(&position))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_block_number() {
+		let partition = Partition {
+			number: 2,
+			fraction: 10,
+		};
+		assert_eq!(block_number(&partition_key(42, &partition)), Some(42));
+		assert_eq!(block_number(&RecordKey::new(&"3:2:1")), Some(3));
+		assert_eq!(block_number(&RecordKey::new(&"3:2")), Some(3));
+		assert_eq!(block_number(&RecordKey::new(&"partition")), None);
+	}
+
+	#[test]
+	fn take_older_uploaded_records() {
+		let mut uploaded = UploadedRecords::default();
+		uploaded.insert(1, [RecordKey::new(&"1:0:0"), RecordKey::new(&"1:0:1")]);
+		uploaded.insert(2, [RecordKey::new(&"2:0")]);
+		uploaded.insert(3, [RecordKey::new(&"3:0")]);
+		uploaded.insert(1, [RecordKey::new(&"1:1")]);
+
+		let mut older = uploaded.take_older(3);
+		older.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+		let expected = ["1:0:0", "1:0:1", "1:1", "2:0"].map(|key| RecordKey::new(&key));
+		assert_eq!(older, expected);
+
+		assert!(uploaded.take_older(3).is_empty());
+		assert_eq!(uploaded.take_older(4), [RecordKey::new(&"3:0")]);
+	}
+
+	#[test]
+	fn partitions_containing_positions() {
+		let dimensions = Dimensions::new(2, 4).unwrap();
+		let third = Partition {
+			number: 3,
+			fraction: 4,
+		};
+		let positions = dimensions
+			.iter_extended_partition_positions(&third)
+			.take(1)
+			.collect::<Vec<_>>();
+
+		let found = partitions(dimensions, 4, &positions);
+		assert_eq!(found.len(), 1);
+		assert_eq!(found[0].number, 3);
+
+		let entire_block = Partition {
+			number: 1,
+			fraction: 1,
+		};
+		let positions = dimensions
+			.iter_extended_partition_positions(&entire_block)
+			.collect::<Vec<_>>();
+		assert_eq!(partitions(dimensions, 4, &positions).len(), 4);
+		assert!(partitions(dimensions, 4, &[]).is_empty());
+	}
+}
@@ -0,0 +1,701 @@
+//! Inbound Kademlia record validation
+//!
+//! Records received with inbound PUT requests are validated before they are stored in the local record store.
+//! Records are taken from the queue in batches and grouped per block. Cells of the block are verified
+//! against the block commitments together, and rows are checked against the row commitments
+//! using the commit key trimmed once per row width. Records of different blocks are validated concurrently.
+//! Headers of the finalized blocks are kept on arrival, and records of blocks whose header
+//! has not arrived yet wait for it in a bounded pending set. Other records without a header are dropped.
+//! Peers sending invalid records are penalised.
+
+use avail_subxt::primitives::Header;
+use color_eyre::{eyre::WrapErr, Result};
+use dusk_bytes::Serializable;
+use dusk_plonk::{
+	commitment_scheme::kzg10::{CommitKey, PublicParameters},
+	fft::{EvaluationDomain, Evaluations},
+	prelude::BlsScalar,
+};
+use kate_recovery::{
+	commitments, config,
+	data::Cell,
+	matrix::{Dimensions, Position},
+};
+use libp2p::{kad::Record, PeerId};
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	sync::Arc,
+};
+use tokio::sync::{
+	broadcast::{self, error::RecvError},
+	mpsc, Semaphore,
+};
+use tracing::{debug, info, trace, warn};
+
+use super::{event_loop::DHTKey, Client, Misbehaviour};
+use crate::{
+	data::{Database, Key},
+	network::rpc,
+	proof,
+	telemetry::{MetricCounter, Metrics},
+	utils::extract_kate,
+};
+
+/// Maximum number of inbound records waiting for validation, records are dropped if the queue is full
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Number of the latest finalized headers kept for validation
+const MAX_RECENT_HEADERS: usize = 64;
+
+/// Maximum number of records waiting for their block header
+const MAX_PENDING_RECORDS: usize = 4096;
+
+/// Number of blocks after the latest finalized block for which records wait for the header
+const MAX_PENDING_BLOCKS: u32 = 4;
+
+/// Maximum number of inbound records taken from the queue and validated together
+const MAX_BATCH_RECORDS: usize = 1024;
+
+/// Maximum number of blocks whose records are validated concurrently
+const MAX_CONCURRENT_BLOCKS: usize = 4;
+
+/// Record received with the inbound PUT request
+pub struct InboundRecord {
+	pub source: PeerId,
+	pub key: DHTKey,
+	pub record: Record,
+}
+
+pub type RecordSender = mpsc::Sender<InboundRecord>;
+pub type RecordReceiver = mpsc::Receiver<InboundRecord>;
+
+/// Creates bounded queue of the inbound records waiting for validation.
+pub fn channel() -> (RecordSender, RecordReceiver) {
+	mpsc::channel(QUEUE_CAPACITY)
+}
+
+impl InboundRecord {
+	fn block_number(&self) -> u32 {
+		match self.key {
+			DHTKey::Cell(block_number, _, _) | DHTKey::Row(block_number, _) => block_number,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
+enum Validation {
+	Valid,
+	Invalid,
+}
+
+/// Latest finalized headers, and inbound records waiting for the headers of their blocks
+#[derive(Default)]
+struct BlockHeaders {
+	headers: BTreeMap<u32, Header>,
+	pending: BTreeMap<u32, Vec<InboundRecord>>,
+	pending_count: usize,
+}
+
+impl BlockHeaders {
+	/// Returns the finalized header, or the header stored in the database.
+	fn get(&self, db: &impl Database, block_number: u32) -> Result<Option<Header>> {
+		if let Some(header) = self.headers.get(&block_number) {
+			return Ok(Some(header.clone()));
+		}
+		db.get::<Header>(Key::BlockHeader(block_number))
+	}
+
+	/// Stores the finalized header, and returns records which were waiting for it.
+	/// Records of the older blocks and of the blocks too far ahead are dropped.
+	fn insert(&mut self, header: Header) -> Vec<InboundRecord> {
+		let block_number = header.number;
+		self.headers.insert(block_number, header);
+		while self.headers.len() > MAX_RECENT_HEADERS {
+			self.headers.pop_first();
+		}
+
+		let records = self.pending.remove(&block_number).unwrap_or_default();
+		let last = block_number.saturating_add(MAX_PENDING_BLOCKS);
+		self.pending
+			.retain(|&pending_block, _| pending_block > block_number && pending_block <= last);
+		self.pending_count = self.pending.values().map(Vec::len).sum();
+		records
+	}
+
+	/// Keeps the record until the header of its block arrives.
+	/// Returns the record back if its header is not expected or the pending set is full.
+	fn defer(&mut self, record: InboundRecord) -> Option<InboundRecord> {
+		let block_number = record.block_number();
+		let is_expected = match self.headers.last_key_value() {
+			Some((&latest, _)) => {
+				block_number > latest && block_number <= latest.saturating_add(MAX_PENDING_BLOCKS)
+			},
+			None => true,
+		};
+		if !is_expected || self.pending_count >= MAX_PENDING_RECORDS {
+			return Some(record);
+		}
+		self.pending.entry(block_number).or_default().push(record);
+		self.pending_count += 1;
+		None
+	}
+}
+
+/// Commit keys trimmed to the row width, computed once per width and reused for all rows
+#[derive(Default)]
+struct CommitKeys(HashMap<usize, Arc<CommitKey>>);
+
+impl CommitKeys {
+	fn get(&mut self, pp: &PublicParameters, width: usize) -> Result<Arc<CommitKey>> {
+		if let Some(commit_key) = self.0.get(&width) {
+			return Ok(commit_key.clone());
+		}
+		let (commit_key, _) = pp.trim(width).wrap_err("Cannot trim public parameters")?;
+		let commit_key = Arc::new(commit_key);
+		self.0.insert(width, commit_key.clone());
+		Ok(commit_key)
+	}
+}
+
+/// Converts the cell record content into the cell, if the cell is within the block matrix.
+fn to_cell(dimensions: Dimensions, position: (u32, u32), content: &[u8]) -> Option<Cell> {
+	let (row, col) = position;
+	let col = u16::try_from(col).ok()?;
+	if row >= dimensions.extended_rows() || col >= dimensions.cols().get() {
+		return None;
+	}
+	let content = <[u8; config::COMMITMENT_SIZE + config::CHUNK_SIZE]>::try_from(content).ok()?;
+	Some(Cell {
+		position: Position { row, col },
+		content,
+	})
+}
+
+/// Verifies cell proofs against the block commitments, with a single proof verification call per distinct positions.
+/// Cells with the same position are verified in the following calls, since at most one of them can be valid.
+/// Returns validation results in the order of the given cells.
+async fn validate_cells(
+	block_number: u32,
+	dimensions: Dimensions,
+	commitments: &[[u8; config::COMMITMENT_SIZE]],
+	pp: Arc<PublicParameters>,
+	cells: Vec<Cell>,
+) -> Result<Vec<Validation>> {
+	let mut validations = cells
+		.iter()
+		.map(|_| Validation::Invalid)
+		.collect::<Vec<_>>();
+	let mut remaining = cells.into_iter().enumerate().collect::<Vec<_>>();
+	while !remaining.is_empty() {
+		let mut positions = HashSet::new();
+		let (batch, rest): (Vec<_>, Vec<_>) = remaining
+			.into_iter()
+			.partition(|(_, cell)| positions.insert(cell.position));
+		remaining = rest;
+
+		let cells = batch
+			.iter()
+			.map(|(_, cell)| cell.clone())
+			.collect::<Vec<_>>();
+		let (verified, _) =
+			proof::verify(block_number, dimensions, &cells, commitments, pp.clone()).await?;
+		let verified = verified.into_iter().collect::<HashSet<_>>();
+		for (index, cell) in batch {
+			if verified.contains(&cell.position) {
+				validations[index] = Validation::Valid;
+			}
+		}
+	}
+	Ok(validations)
+}
+
+/// Computes commitment of the row, by interpolating the row cells over the row evaluation domain.
+fn row_commitment(
+	commit_key: &CommitKey,
+	domain: &EvaluationDomain,
+	data: &[u8],
+) -> Result<Option<[u8; config::COMMITMENT_SIZE]>> {
+	if data.len() != domain.size() * config::CHUNK_SIZE {
+		return Ok(None);
+	}
+	let Some(scalars) = data
+		.chunks_exact(config::CHUNK_SIZE)
+		.map(|chunk| {
+			let chunk = <&[u8; config::CHUNK_SIZE]>::try_from(chunk).ok()?;
+			BlsScalar::from_bytes(chunk).ok()
+		})
+		.collect::<Option<Vec<_>>>()
+	else {
+		return Ok(None);
+	};
+
+	let polynomial = Evaluations::from_vec_and_domain(scalars, *domain).interpolate();
+	let commitment = commit_key
+		.commit(&polynomial)
+		.wrap_err("Cannot commit to the row")?;
+	Ok(Some(commitment.to_bytes()))
+}
+
+/// Checks the row against its row commitment.
+fn validate_row(
+	commit_key: &CommitKey,
+	domain: &EvaluationDomain,
+	commitments: &[[u8; config::COMMITMENT_SIZE]],
+	row_index: u32,
+	data: &[u8],
+) -> Result<Validation> {
+	let Some(expected) = commitments.get(row_index as usize) else {
+		return Ok(Validation::Invalid);
+	};
+
+	Ok(match row_commitment(commit_key, domain, data)? {
+		Some(commitment) if commitment == *expected => Validation::Valid,
+		_ => Validation::Invalid,
+	})
+}
+
+/// Row commitments of the block, used to check rows fetched from other peers.
+/// Commit key is trimmed to the row width once, and reused for all rows of the block.
+pub struct RowCommitments {
+	commit_key: CommitKey,
+	domain: EvaluationDomain,
+	commitments: Vec<[u8; config::COMMITMENT_SIZE]>,
+}
+
+impl RowCommitments {
+	pub fn new(
+		pp: &PublicParameters,
+		dimensions: Dimensions,
+		commitments: &[[u8; config::COMMITMENT_SIZE]],
+	) -> Result<Self> {
+		let (commit_key, _) = pp
+			.trim(dimensions.width())
+			.wrap_err("Cannot trim public parameters")?;
+		let domain = EvaluationDomain::new(dimensions.width()).wrap_err("Invalid row domain")?;
+		Ok(Self {
+			commit_key,
+			domain,
+			commitments: commitments.to_vec(),
+		})
+	}
+
+	/// Returns `true` if the row matches its row commitment.
+	pub fn check(&self, row_index: u32, data: &[u8]) -> Result<bool> {
+		let validation = validate_row(
+			&self.commit_key,
+			&self.domain,
+			&self.commitments,
+			row_index,
+			data,
+		)?;
+		Ok(validation == Validation::Valid)
+	}
+}
+
+/// Validates inbound records of the block against its header.
+/// Cells are verified together, and rows are checked with the shared commit key.
+/// Returns validation results in the order of the given records.
+async fn validate(
+	header: &Header,
+	pp: Arc<PublicParameters>,
+	commit_key: &CommitKey,
+	records: &[InboundRecord],
+) -> Result<Vec<Validation>> {
+	let (rows, cols, _, commitment) = extract_kate(&header.extension);
+	let Some(dimensions) = Dimensions::new(rows, cols) else {
+		return Ok(records.iter().map(|_| Validation::Invalid).collect());
+	};
+	let commitments = commitments::from_slice(&commitment)?;
+	let domain = EvaluationDomain::new(dimensions.width()).wrap_err("Invalid row domain")?;
+
+	let mut validations = records
+		.iter()
+		.map(|_| Validation::Invalid)
+		.collect::<Vec<_>>();
+	let mut cells = vec![];
+	for (index, InboundRecord { key, record, .. }) in records.iter().enumerate() {
+		match *key {
+			DHTKey::Cell(_, row, col) => {
+				if let Some(cell) = to_cell(dimensions, (row, col), &record.value) {
+					cells.push((index, cell));
+				}
+			},
+			DHTKey::Row(_, row) if row < dimensions.extended_rows() => {
+				validations[index] =
+					validate_row(commit_key, &domain, &commitments, row, &record.value)?;
+			},
+			DHTKey::Row(..) => (),
+		}
+	}
+
+	let (indices, cells): (Vec<_>, Vec<_>) = cells.into_iter().unzip();
+	let cell_validations =
+		validate_cells(header.number, dimensions, &commitments, pp, cells).await?;
+	for (index, validation) in indices.into_iter().zip(cell_validations) {
+		validations[index] = validation;
+	}
+	Ok(validations)
+}
+
+/// Validates inbound records of the block against its header, stores valid records and penalises peers which sent invalid records.
+async fn process_records(
+	p2p_client: &Client,
+	pp: Arc<PublicParameters>,
+	commit_key: &CommitKey,
+	metrics: &Arc<impl Metrics>,
+	header: &Header,
+	records: Vec<InboundRecord>,
+) {
+	let validations = match validate(header, pp, commit_key, &records).await {
+		Ok(validations) => validations,
+		Err(error) => {
+			let block_number = header.number;
+			debug!(block_number, "Cannot validate inbound records: {error:#}");
+			return;
+		},
+	};
+
+	for (InboundRecord { source, record, .. }, validation) in records.into_iter().zip(validations) {
+		match validation {
+			Validation::Valid => {
+				if let Err(error) = p2p_client.store_record(record).await {
+					debug!("Cannot store inbound record: {error:#}");
+				}
+			},
+			Validation::Invalid => {
+				debug!("Rejecting invalid inbound record from {source}");
+				metrics.count(MetricCounter::RejectedPutRecord).await;
+				if let Err(error) = p2p_client
+					.report_peer(source, Misbehaviour::InvalidProof)
+					.await
+				{
+					debug!("Cannot report peer {source}: {error:#}");
+				}
+			},
+		}
+	}
+}
+
+/// Validates inbound records of the blocks concurrently, up to [`MAX_CONCURRENT_BLOCKS`] blocks at once.
+struct Validator<M> {
+	p2p_client: Client,
+	pp: Arc<PublicParameters>,
+	metrics: Arc<M>,
+	commit_keys: CommitKeys,
+	limit: Arc<Semaphore>,
+}
+
+impl<M: Metrics + Send + Sync + 'static> Validator<M> {
+	/// Spawns validation of the block records, waiting while the maximum number of blocks is being validated.
+	async fn spawn(&mut self, header: Header, records: Vec<InboundRecord>) {
+		if records.is_empty() {
+			return;
+		}
+		let (_, cols, _, _) = extract_kate(&header.extension);
+		let commit_key = match self.commit_keys.get(&self.pp, usize::from(cols)) {
+			Ok(commit_key) => commit_key,
+			Err(error) => {
+				debug!("Cannot validate inbound records: {error:#}");
+				return;
+			},
+		};
+		let Ok(permit) = self.limit.clone().acquire_owned().await else {
+			return;
+		};
+
+		let p2p_client = self.p2p_client.clone();
+		let pp = self.pp.clone();
+		let metrics = self.metrics.clone();
+		tokio::spawn(async move {
+			process_records(&p2p_client, pp, &commit_key, &metrics, &header, records).await;
+			drop(permit);
+		});
+	}
+}
+
+/// Receives the next batch of inbound records, up to [`MAX_BATCH_RECORDS`] records, grouped by block number.
+/// Returns `None` if the channel is closed.
+async fn recv_batch(
+	record_receiver: &mut RecordReceiver,
+) -> Option<BTreeMap<u32, Vec<InboundRecord>>> {
+	let record = record_receiver.recv().await?;
+	let mut batch = BTreeMap::<u32, Vec<InboundRecord>>::new();
+	batch.entry(record.block_number()).or_default().push(record);
+	for _ in 1..MAX_BATCH_RECORDS {
+		let Ok(record) = record_receiver.try_recv() else {
+			break;
+		};
+		batch.entry(record.block_number()).or_default().push(record);
+	}
+	Some(batch)
+}
+
+/// Validates inbound records, stores valid records and penalises peers which sent invalid records.
+/// Records are validated in batches per block, and blocks are validated concurrently.
+///
+/// # Arguments
+///
+/// * `p2p_client` - Peer to peer client, used to store records and report peers
+/// * `db` - Database with stored block headers
+/// * `pp` - Public parameters used for the proof verification
+/// * `metrics` - Metrics registry
+/// * `rpc_events` - Finalized headers receiver
+/// * `record_receiver` - Inbound records receiver
+pub async fn run(
+	p2p_client: Client,
+	db: impl Database,
+	pp: Arc<PublicParameters>,
+	metrics: Arc<impl Metrics + Send + Sync + 'static>,
+	mut rpc_events: broadcast::Receiver<rpc::Event>,
+	mut record_receiver: RecordReceiver,
+) {
+	info!("Starting inbound record validation...");
+
+	let mut headers = BlockHeaders::default();
+	let mut validator = Validator {
+		p2p_client,
+		pp,
+		metrics,
+		commit_keys: CommitKeys::default(),
+		limit: Arc::new(Semaphore::new(MAX_CONCURRENT_BLOCKS)),
+	};
+	loop {
+		tokio::select! {
+			event = rpc_events.recv() => match event {
+				Ok(rpc::Event::HeaderUpdate { header, .. }) => {
+					let records = headers.insert(header.clone());
+					validator.spawn(header, records).await;
+				},
+				// Records of the skipped headers are validated against the stored headers or dropped
+				Err(RecvError::Lagged(skipped)) => {
+					warn!("Record validation lagged behind, {skipped} headers skipped");
+				},
+				Err(RecvError::Closed) => {
+					info!("RPC event channel closed, exiting record validation");
+					break;
+				},
+			},
+			batch = recv_batch(&mut record_receiver) => {
+				let Some(batch) = batch else {
+					info!("Inbound record channel closed, exiting record validation");
+					break;
+				};
+				for (block_number, records) in batch {
+					match headers.get(&db, block_number) {
+						Ok(Some(header)) => validator.spawn(header, records).await,
+						Ok(None) => {
+							for record in records {
+								if let Some(InboundRecord { source, .. }) = headers.defer(record) {
+									trace!("Dropping inbound record from {source}, it cannot be validated");
+								}
+							}
+						},
+						Err(error) => {
+							debug!(block_number, "Cannot get header of the inbound records: {error:#}");
+						},
+					}
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::mem_db::MemoryDB;
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		utils::H256,
+	};
+	use kate_recovery::testnet;
+	use subxt::config::substrate::Digest;
+
+	/// Commitment of the row with all cells equal to zero
+	const IDENTITY: [u8; 48] = {
+		let mut bytes = [0u8; 48];
+		bytes[0] = 0xc0;
+		bytes
+	};
+
+	fn header() -> Header {
+		header_with_commitment(1, vec![0; 96])
+	}
+
+	fn header_with_commitment(number: u32, commitment: Vec<u8>) -> Header {
+		Header {
+			parent_hash: H256::default(),
+			number,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment {
+					rows: 1,
+					cols: 4,
+					data_root: H256::default(),
+					commitment,
+				},
+				app_lookup: CompactDataLookup {
+					size: 0,
+					index: vec![],
+				},
+			}),
+			digest: Digest { logs: vec![] },
+		}
+	}
+
+	fn record(block_number: u32) -> InboundRecord {
+		InboundRecord {
+			source: PeerId::random(),
+			key: DHTKey::Row(block_number, 0),
+			record: Record::new(format!("{block_number}:0").into_bytes(), vec![0; 128]),
+		}
+	}
+
+	#[test]
+	fn records_wait_for_header() {
+		let db = MemoryDB::default();
+		let mut headers = BlockHeaders::default();
+		assert!(headers.get(&db, 1).unwrap().is_none());
+
+		// Records wait for the first header
+		assert!(headers.defer(record(1)).is_none());
+		let records = headers.insert(header());
+		assert_eq!(records.len(), 1);
+		assert!(headers.get(&db, 1).unwrap().is_some());
+
+		// Headers of the older blocks are not expected
+		assert!(headers.defer(record(1)).is_some());
+		// Records of the blocks too far ahead are not kept
+		assert!(headers.defer(record(2 + MAX_PENDING_BLOCKS)).is_some());
+
+		assert!(headers.defer(record(2)).is_none());
+		assert!(headers.defer(record(3)).is_none());
+		let records = headers.insert(header_with_commitment(3, vec![0; 96]));
+		assert_eq!(records.len(), 1);
+		// Records of the skipped block are dropped
+		assert_eq!(headers.pending_count, 0);
+		assert!(headers.pending.is_empty());
+
+		// Headers stored in the database are used
+		db.put(Key::BlockHeader(2), header_with_commitment(2, vec![0; 96]))
+			.unwrap();
+		assert!(headers.get(&db, 2).unwrap().is_some());
+	}
+
+	async fn validate_records(header: &Header, records: Vec<(DHTKey, Vec<u8>)>) -> Vec<Validation> {
+		let pp = Arc::new(testnet::public_params(16));
+		let commit_key = CommitKeys::default().get(&pp, 4).unwrap();
+		let records = records
+			.into_iter()
+			.map(|(key, value)| InboundRecord {
+				source: PeerId::random(),
+				key,
+				record: Record::new(vec![], value),
+			})
+			.collect::<Vec<_>>();
+		validate(header, pp, &commit_key, &records).await.unwrap()
+	}
+
+	/// Cell with the proof of the zero polynomial evaluation
+	fn cell(value: u8) -> Vec<u8> {
+		let mut content = vec![0u8; 80];
+		content[..48].copy_from_slice(&IDENTITY);
+		content[48] = value;
+		content
+	}
+
+	#[tokio::test]
+	async fn validate_row_against_commitment() {
+		let commitment = [IDENTITY, IDENTITY].concat();
+		let header = header_with_commitment(1, commitment);
+
+		let mut data = vec![0; 128];
+		data[0] = 1;
+		let validations = validate_records(
+			&header,
+			vec![
+				(DHTKey::Row(1, 1), vec![0; 128]),
+				(DHTKey::Row(1, 1), data),
+				// Row with invalid length
+				(DHTKey::Row(1, 1), vec![0; 96]),
+			],
+		)
+		.await;
+		assert_eq!(
+			validations,
+			vec![Validation::Valid, Validation::Invalid, Validation::Invalid]
+		);
+	}
+
+	#[test]
+	fn check_fetched_rows() {
+		let pp = testnet::public_params(16);
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		let row_commitments = RowCommitments::new(&pp, dimensions, &[IDENTITY, IDENTITY]).unwrap();
+
+		let mut data = vec![0; 128];
+		assert!(row_commitments.check(1, &data).unwrap());
+		// Row outside of the extended matrix
+		assert!(!row_commitments.check(2, &data).unwrap());
+		data[0] = 1;
+		assert!(!row_commitments.check(1, &data).unwrap());
+		assert!(!row_commitments.check(1, &[0; 96]).unwrap());
+	}
+
+	#[tokio::test]
+	async fn validate_block_cells() {
+		let commitment = [IDENTITY, IDENTITY].concat();
+		let header = header_with_commitment(1, commitment);
+
+		// Cells with the same position are verified separately
+		let validations = validate_records(
+			&header,
+			vec![
+				(DHTKey::Cell(1, 0, 0), cell(0)),
+				(DHTKey::Cell(1, 0, 0), cell(1)),
+				(DHTKey::Cell(1, 1, 2), cell(0)),
+				(DHTKey::Row(1, 0), vec![0; 128]),
+				(DHTKey::Cell(1, 1, 2), cell(0)),
+			],
+		)
+		.await;
+		assert_eq!(
+			validations,
+			vec![
+				Validation::Valid,
+				Validation::Invalid,
+				Validation::Valid,
+				Validation::Valid,
+				Validation::Valid
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn validate_invalid_records() {
+		let header = header();
+		let validations = validate_records(
+			&header,
+			vec![
+				// Cell outside of the block matrix
+				(DHTKey::Cell(1, 0, 4), vec![0; 80]),
+				// Cell with invalid length
+				(DHTKey::Cell(1, 0, 0), vec![0; 79]),
+				// Row outside of the block matrix
+				(DHTKey::Row(1, 2), vec![0; 128]),
+				// Row which doesn't match the row commitment
+				(DHTKey::Row(1, 0), vec![0; 128]),
+			],
+		)
+		.await;
+		assert!(validations
+			.iter()
+			.all(|validation| *validation == Validation::Invalid));
+		assert_eq!(validations.len(), 4);
+	}
+}
@@ -0,0 +1,292 @@
+//! Peer reputation
+//!
+//! Peers are penalised for serving invalid cells, malformed records and for timeouts.
+//! Peers whose score falls below the configured threshold are blocked until the block expires,
+//! after which their score is reset.
+//! Scores of the peers which are not blocked recover over time, so occasional misbehaviour is forgiven,
+//! and fully recovered peers are forgotten. Bootstrap peers are never penalised.
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::time::Instant;
+
+/// Score points recovered per minute by the peers which are not blocked
+const SCORE_RECOVERY_PER_MINUTE: i32 = 1;
+
+/// Maximum number of tracked peers, least penalised peers are forgotten first
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// Peer misbehaviour which lowers the peer score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehaviour {
+	/// Peer served a cell which failed the proof verification
+	InvalidProof,
+	/// Peer served a record which cannot be decoded
+	MalformedRecord,
+	/// Peer didn't respond in time
+	Timeout,
+}
+
+impl Misbehaviour {
+	fn penalty(&self) -> i32 {
+		match self {
+			Misbehaviour::InvalidProof => 20,
+			Misbehaviour::MalformedRecord => 10,
+			Misbehaviour::Timeout => 5,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Reputation {
+	score: i32,
+	blocked_until: Option<Instant>,
+	/// Last time the score recovery was applied
+	recovered_at: Instant,
+}
+
+impl Reputation {
+	fn new(now: Instant) -> Self {
+		Reputation {
+			score: 0,
+			blocked_until: None,
+			recovered_at: now,
+		}
+	}
+
+	/// Recovers the score for the whole minutes passed since the last recovery.
+	fn recover(&mut self, now: Instant) {
+		let minutes = now.saturating_duration_since(self.recovered_at).as_secs() / 60;
+		if minutes == 0 {
+			return;
+		}
+		self.recovered_at += Duration::from_secs(minutes * 60);
+		let recovered = i32::try_from(minutes)
+			.unwrap_or(i32::MAX)
+			.saturating_mul(SCORE_RECOVERY_PER_MINUTE);
+		self.score = self.score.saturating_add(recovered).min(0);
+	}
+}
+
+/// Peer score, as exposed through the API
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PeerScore {
+	pub peer_id: String,
+	pub score: i32,
+	/// Remaining block duration in seconds, if the peer is blocked
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub blocked_for: Option<u64>,
+}
+
+/// Shared peer reputation store
+#[derive(Clone)]
+pub struct PeerReputation {
+	peers: Arc<Mutex<HashMap<PeerId, Reputation>>>,
+	/// Peers with score below the threshold are blocked
+	block_threshold: i32,
+	block_duration: Duration,
+	/// Peers which are never penalised (e.g. bootstrap peers)
+	exempt_peers: Arc<HashSet<PeerId>>,
+}
+
+impl PeerReputation {
+	pub fn new(
+		block_threshold: i32,
+		block_duration: Duration,
+		exempt_peers: HashSet<PeerId>,
+	) -> Self {
+		PeerReputation {
+			peers: Default::default(),
+			block_threshold,
+			block_duration,
+			exempt_peers: Arc::new(exempt_peers),
+		}
+	}
+
+	/// Penalises peer for the misbehaviour.
+	/// Returns `true` if the peer score fell below the threshold, and the peer has to be blocked.
+	pub fn penalise(&self, peer_id: PeerId, misbehaviour: Misbehaviour) -> bool {
+		if self.exempt_peers.contains(&peer_id) {
+			return false;
+		}
+
+		let now = Instant::now();
+		let mut peers = self.peers.lock().expect("Lock should be acquired");
+		if !peers.contains_key(&peer_id) && peers.len() >= MAX_TRACKED_PEERS {
+			// Blocked peers are kept, since they have to be unblocked once the block expires
+			let Some(forgotten) = peers
+				.iter()
+				.filter(|(_, reputation)| reputation.blocked_until.is_none())
+				.max_by_key(|(_, reputation)| reputation.score)
+				.map(|(&peer_id, _)| peer_id)
+			else {
+				return false;
+			};
+			peers.remove(&forgotten);
+		}
+
+		let reputation = peers.entry(peer_id).or_insert_with(|| Reputation::new(now));
+		if reputation.blocked_until.is_some() {
+			return false;
+		}
+
+		reputation.recover(now);
+		reputation.score -= misbehaviour.penalty();
+		if reputation.score >= self.block_threshold {
+			return false;
+		}
+
+		reputation.blocked_until = Some(now + self.block_duration);
+		true
+	}
+
+	/// Removes peers with expired blocks, resetting their score.
+	/// Scores of the other peers are recovered, and fully recovered peers are forgotten.
+	/// Returns peers which have to be unblocked.
+	pub fn take_expired(&self, now: Instant) -> Vec<PeerId> {
+		let mut peers = self.peers.lock().expect("Lock should be acquired");
+		peers.retain(|_, reputation| {
+			if reputation.blocked_until.is_some() {
+				return true;
+			}
+			reputation.recover(now);
+			reputation.score < 0
+		});
+
+		let expired = peers
+			.iter()
+			.filter(|(_, reputation)| reputation.blocked_until.is_some_and(|until| until <= now))
+			.map(|(&peer_id, _)| peer_id)
+			.collect::<Vec<_>>();
+		for peer_id in &expired {
+			peers.remove(peer_id);
+		}
+		expired
+	}
+
+	/// Returns scores of the penalised peers, starting with the lowest score.
+	pub fn scores(&self) -> Vec<PeerScore> {
+		let now = Instant::now();
+		let peers = self.peers.lock().expect("Lock should be acquired");
+		let mut scores = peers
+			.iter()
+			.map(|(peer_id, reputation)| PeerScore {
+				peer_id: peer_id.to_string(),
+				score: reputation.score,
+				blocked_for: reputation
+					.blocked_until
+					.map(|until| until.saturating_duration_since(now).as_secs()),
+			})
+			.collect::<Vec<_>>();
+		scores.sort_by(|a, b| a.score.cmp(&b.score).then(a.peer_id.cmp(&b.peer_id)));
+		scores
+	}
+
+	/// Returns number of penalised and number of blocked peers.
+	pub fn counts(&self) -> (usize, usize) {
+		let peers = self.peers.lock().expect("Lock should be acquired");
+		let blocked = peers
+			.values()
+			.filter(|reputation| reputation.blocked_until.is_some())
+			.count();
+		(peers.len(), blocked)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn penalise_blocks_peer_below_threshold() {
+		let reputation = PeerReputation::new(-30, Duration::from_secs(60), HashSet::new());
+		let peer_id = PeerId::random();
+
+		assert!(!reputation.penalise(peer_id, Misbehaviour::InvalidProof));
+		assert!(!reputation.penalise(peer_id, Misbehaviour::MalformedRecord));
+		assert_eq!(reputation.counts(), (1, 0));
+
+		assert!(reputation.penalise(peer_id, Misbehaviour::Timeout));
+		assert_eq!(reputation.counts(), (1, 1));
+
+		// Blocked peer is not penalised again
+		assert!(!reputation.penalise(peer_id, Misbehaviour::InvalidProof));
+
+		let scores = reputation.scores();
+		assert_eq!(scores.len(), 1);
+		assert_eq!(scores[0].score, -35);
+		assert!(scores[0].blocked_for.is_some_and(|seconds| seconds <= 60));
+	}
+
+	#[test]
+	fn take_expired_resets_blocked_peers() {
+		let reputation = PeerReputation::new(-10, Duration::from_secs(60), HashSet::new());
+		let blocked = PeerId::random();
+		let penalised = PeerId::random();
+
+		assert!(reputation.penalise(blocked, Misbehaviour::InvalidProof));
+		assert!(!reputation.penalise(penalised, Misbehaviour::Timeout));
+
+		assert!(reputation.take_expired(Instant::now()).is_empty());
+		let expired = reputation.take_expired(Instant::now() + Duration::from_secs(60));
+		assert_eq!(expired, vec![blocked]);
+
+		let scores = reputation.scores();
+		assert_eq!(scores.len(), 1);
+		assert_eq!(scores[0].peer_id, penalised.to_string());
+		assert_eq!(scores[0].blocked_for, None);
+	}
+
+	#[test]
+	fn scores_recover_over_time() {
+		let reputation = PeerReputation::new(-100, Duration::from_secs(60), HashSet::new());
+		let peer_id = PeerId::random();
+		assert!(!reputation.penalise(peer_id, Misbehaviour::Timeout));
+
+		let now = Instant::now();
+		reputation.take_expired(now + Duration::from_secs(3 * 60));
+		assert_eq!(reputation.scores()[0].score, -2);
+
+		// Fully recovered peers are forgotten
+		reputation.take_expired(now + Duration::from_secs(5 * 60));
+		assert!(reputation.scores().is_empty());
+	}
+
+	#[test]
+	fn exempt_peers_are_not_penalised() {
+		let bootstrap = PeerId::random();
+		let reputation =
+			PeerReputation::new(-10, Duration::from_secs(60), HashSet::from([bootstrap]));
+
+		assert!(!reputation.penalise(bootstrap, Misbehaviour::InvalidProof));
+		assert_eq!(reputation.counts(), (0, 0));
+	}
+
+	#[test]
+	fn least_penalised_peers_are_forgotten() {
+		let reputation = PeerReputation::new(-100, Duration::from_secs(60), HashSet::new());
+		let penalised = PeerId::random();
+		reputation.penalise(penalised, Misbehaviour::InvalidProof);
+		let least_penalised = PeerId::random();
+		reputation.penalise(least_penalised, Misbehaviour::Timeout);
+		for _ in 2..MAX_TRACKED_PEERS {
+			reputation.penalise(PeerId::random(), Misbehaviour::MalformedRecord);
+		}
+		assert_eq!(reputation.counts(), (MAX_TRACKED_PEERS, 0));
+
+		reputation.penalise(PeerId::random(), Misbehaviour::InvalidProof);
+		assert_eq!(reputation.counts(), (MAX_TRACKED_PEERS, 0));
+		let scores = reputation.scores();
+		assert!(scores
+			.iter()
+			.all(|score| score.peer_id != least_penalised.to_string()));
+		assert!(scores
+			.iter()
+			.any(|score| score.peer_id == penalised.to_string()));
+	}
+}
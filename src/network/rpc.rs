@@ -5,7 +5,7 @@ use color_eyre::{eyre::eyre, Report, Result};
 use kate_recovery::matrix::{Dimensions, Position};
 use rand::{seq::SliceRandom, thread_rng, Rng};
 use serde::{de, Deserialize};
-use sp_core::bytes::from_hex;
+use sp_core::{blake2_256, bytes::from_hex};
 use std::{
 	collections::HashSet,
 	fmt::Display,
@@ -218,24 +218,41 @@ pub async fn init<T: Database>(
 	Ok((rpc_client, event_sender, subscriptions))
 }
 
-/// Generates random cell positions for sampling
-pub fn generate_random_cells(dimensions: Dimensions, cell_count: u32) -> Vec<Position> {
-	let max_cells = dimensions.extended_size();
+/// Derives per-block sampling seed from the client sampling secret and the block hash
+pub fn sampling_seed(secret: &[u8; 32], block_hash: &H256) -> [u8; 32] {
+	blake2_256(&[&secret[..], block_hash.as_bytes()].concat())
+}
+
+/// Generates cell positions for sampling using given random number generator,
+/// excluding already sampled positions. Positions are returned in the order they were drawn,
+/// so seeded generator yields reproducible positions.
+pub fn generate_cells(
+	dimensions: Dimensions,
+	cell_count: u32,
+	excluded: &HashSet<Position>,
+	rng: &mut impl Rng,
+) -> Vec<Position> {
+	let max_cells = dimensions
+		.extended_size()
+		.saturating_sub(excluded.len() as u32);
 	let count = if max_cells < cell_count {
 		debug!("Max cells count {max_cells} is lesser than cell_count {cell_count}");
 		max_cells
 	} else {
 		cell_count
 	};
-	let mut rng = thread_rng();
-	let mut indices = HashSet::new();
-	while (indices.len() as u16) < count as u16 {
+	let mut drawn = HashSet::new();
+	let mut positions = vec![];
+	while (positions.len() as u32) < count {
 		let col = rng.gen_range(0..dimensions.cols().into());
 		let row = rng.gen_range(0..dimensions.extended_rows());
-		indices.insert(Position { row, col });
+		let position = Position { row, col };
+		if !excluded.contains(&position) && drawn.insert(position) {
+			positions.push(position);
+		}
 	}
 
-	indices.into_iter().collect::<Vec<_>>()
+	positions
 }
 
 /* @note: fn to take the number of cells needs to get equal to or greater than
@@ -11,12 +11,12 @@ use std::{
 };
 use tokio::sync::broadcast::Sender;
 use tokio_stream::StreamExt;
-use tracing::{debug, info, trace};
+use tracing::{debug, error, info, trace};
 
 use super::{Client, Subscription};
 use crate::{
 	data::Database,
-	data::{FinalitySyncCheckpoint, Key},
+	data::{FinalityProof, FinalitySyncCheckpoint, Key},
 	finality::{check_finality, ValidatorSet},
 	types::{GrandpaJustification, OptionBlockRange, State},
 	utils::filter_auth_set_changes,
@@ -179,6 +179,14 @@ impl<T: Database> SubscriptionLoop<T> {
 
 				is_final.expect("Finality check failed");
 
+				// keep the justification and the validator set used for verification
+				let finality_proof = FinalityProof {
+					justification_block_number: header.number,
+					set_id: valset.set_id,
+					validator_set: valset.validator_set.clone(),
+					justification: justification.encode(),
+				};
+
 				// To avoid locking the global state all the time, after finality is synced, it will not be necessary to read the state
 				if !finality_synced {
 					finality_synced = self.state.lock().unwrap().finality_synced;
@@ -224,6 +232,8 @@ impl<T: Database> SubscriptionLoop<T> {
 								(a, Instant::now())
 							},
 						};
+						// skipped block is finalized by the justification of its descendant
+						self.store_finality_proof(bl_num, &finality_proof);
 						// send as output event
 						self.event_sender
 							.send(Event::HeaderUpdate {
@@ -234,6 +244,8 @@ impl<T: Database> SubscriptionLoop<T> {
 					}
 				}
 
+				self.store_finality_proof(header.number, &finality_proof);
+
 				info!("Sending finalized block {}", header.number);
 				// reset Last Finalized Block Header
 				self.block_data.last_finalized_block_header = Some(header.clone());
@@ -257,4 +269,13 @@ impl<T: Database> SubscriptionLoop<T> {
 			}
 		}
 	}
+
+	fn store_finality_proof(&self, block_number: u32, finality_proof: &FinalityProof) {
+		if let Err(error) = self
+			.db
+			.put(Key::FinalityProof(block_number), finality_proof.clone())
+		{
+			error!(block_number, "Failed to store finality proof: {error:#}");
+		}
+	}
 }
@@ -1,6 +1,13 @@
 //! Parallelized proof verification
+//!
+//! Proofs are verified on a dedicated thread pool, so CPU-heavy pairing checks don't block the async runtime.
+//! Cells are grouped by row, and each pool task verifies a batch of cells against the shared row commitment,
+//! checking proof of every cell separately. Number of batches queued on the pool is bounded,
+//! so verification calls wait for a free slot instead of growing the pool queue.
+//!
+//! Public parameters can be loaded from a file, in which case their precomputed form is cached on disk, next to the file.
 
-use color_eyre::eyre;
+use color_eyre::eyre::{self, eyre, WrapErr};
 use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
 use itertools::{Either, Itertools};
 use kate_recovery::{
@@ -8,18 +15,215 @@ use kate_recovery::{
 	matrix::{Dimensions, Position},
 	proof,
 };
-use std::sync::Arc;
-use tokio::{task::JoinSet, time::Instant};
-use tracing::debug;
+use sp_core::blake2_256;
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex, OnceLock,
+	},
+};
+use threadpool::ThreadPool;
+use tokio::{
+	sync::{oneshot, Semaphore},
+	time::Instant,
+};
+use tracing::{debug, info, warn};
 
-async fn verify_proof(
-	public_parameters: Arc<PublicParameters>,
+/// Maximum number of cells verified in a single batch
+const BATCH_SIZE: usize = 32;
+
+/// Maximum number of batches queued or verified on the pool, per verification thread
+const MAX_QUEUED_BATCHES_PER_THREAD: usize = 4;
+
+/// Suffix of the public parameters cache file, which is stored next to the public parameters file
+const PUBLIC_PARAMS_CACHE_SUFFIX: &str = ".cache";
+
+/// Length of the public parameters cache header (source file hash and cached parameters hash)
+const CACHE_HEADER_LEN: usize = 64;
+
+struct Verifier {
+	/// Pool handle, cloned by the verification calls so the lock is held only for the clone
+	pool: Mutex<ThreadPool>,
+	/// Permits for the batches queued or verified on the pool
+	queue: Arc<Semaphore>,
+	verified_cells: AtomicU64,
+	stats_taken_at: Mutex<Instant>,
+}
+
+static VERIFIER: OnceLock<Verifier> = OnceLock::new();
+
+fn verifier() -> &'static Verifier {
+	VERIFIER.get_or_init(|| {
+		let threads = num_cpus::get();
+		Verifier {
+			pool: Mutex::new(ThreadPool::with_name(
+				"proof-verification".to_string(),
+				threads,
+			)),
+			queue: Arc::new(Semaphore::new(threads * MAX_QUEUED_BATCHES_PER_THREAD)),
+			verified_cells: AtomicU64::new(0),
+			stats_taken_at: Mutex::new(Instant::now()),
+		}
+	})
+}
+
+/// Parses hex encoded 32 bytes hash, with optional `0x` prefix.
+fn parse_hash(hash: &str) -> eyre::Result<[u8; 32]> {
+	let hash = hex::decode(hash.trim_start_matches("0x")).wrap_err("Invalid hex encoding")?;
+	hash.try_into()
+		.map_err(|_| eyre!("Hash has to be 32 bytes long"))
+}
+
+/// Loads precomputed public parameters from the cache, if they are cached for the given source file hash.
+fn load_cached_public_params(
+	cache_path: &Path,
+	source_hash: &[u8; 32],
+) -> eyre::Result<Option<PublicParameters>> {
+	if !cache_path.exists() {
+		return Ok(None);
+	}
+
+	let cache = fs::read(cache_path).wrap_err("Failed to read public parameters cache")?;
+	if cache.len() < CACHE_HEADER_LEN || &cache[..32] != source_hash {
+		return Ok(None);
+	}
+
+	let (header, raw) = cache.split_at(CACHE_HEADER_LEN);
+	if header[32..] != blake2_256(raw) {
+		return Err(eyre!("Public parameters cache is corrupted"));
+	}
+
+	// SAFETY: Raw parameters were serialized from the checked parameters, and their integrity is verified above
+	let public_params = unsafe { PublicParameters::from_slice_unchecked(raw) };
+	// Cache header is not authenticated, so loaded parameters are checked against the expected source file hash
+	if blake2_256(&public_params.to_var_bytes()) != *source_hash {
+		return Err(eyre!(
+			"Public parameters cache doesn't match the expected hash"
+		));
+	}
+	Ok(Some(public_params))
+}
+
+/// Returns path of the public parameters cache file for the given public parameters file.
+pub fn public_params_cache_path(path: &str) -> PathBuf {
+	PathBuf::from(format!("{path}{PUBLIC_PARAMS_CACHE_SUFFIX}"))
+}
+
+/// Stores precomputed public parameters into the cache, together with the source file hash.
+fn store_cached_public_params(
+	cache_path: &Path,
+	source_hash: &[u8; 32],
+	public_params: &PublicParameters,
+) -> eyre::Result<()> {
+	let raw = public_params.to_raw_var_bytes();
+	let mut cache = Vec::with_capacity(CACHE_HEADER_LEN + raw.len());
+	cache.extend_from_slice(source_hash);
+	cache.extend_from_slice(&blake2_256(&raw));
+	cache.extend_from_slice(&raw);
+	fs::write(cache_path, cache).wrap_err("Failed to write public parameters cache")
+}
+
+/// Loads public parameters from the file (serialized with `PublicParameters::to_var_bytes`),
+/// and checks the file against the expected hex encoded Blake2-256 hash.
+/// If cache path is given, precomputed parameters are loaded from the cache if cached for the same file,
+/// otherwise they are cached after the file is loaded.
+pub fn load_public_params(
+	path: &str,
+	expected_hash: &str,
+	cache_path: Option<&Path>,
+) -> eyre::Result<PublicParameters> {
+	let expected_hash = parse_hash(expected_hash).wrap_err("Invalid public parameters hash")?;
+
+	if let Some(cache_path) = cache_path {
+		match load_cached_public_params(cache_path, &expected_hash) {
+			Ok(Some(public_params)) => {
+				info!(
+					"Public parameters loaded from cache {}",
+					cache_path.display()
+				);
+				return Ok(public_params);
+			},
+			Ok(None) => (),
+			Err(error) => warn!("Cannot load cached public parameters: {error:#}"),
+		}
+	}
+
+	let bytes = fs::read(path).wrap_err(format!("Failed to read public parameters file {path}"))?;
+	let hash = blake2_256(&bytes);
+	if hash != expected_hash {
+		return Err(eyre!(
+			"Public parameters hash mismatch: expected 0x{}, got 0x{}",
+			hex::encode(expected_hash),
+			hex::encode(hash)
+		));
+	}
+
+	let public_params = PublicParameters::from_slice(&bytes)
+		.map_err(|error| eyre!("Failed to decode public parameters: {error:?}"))?;
+	info!("Public parameters loaded from {path}");
+
+	if let Some(cache_path) = cache_path {
+		if let Err(error) = store_cached_public_params(cache_path, &expected_hash, &public_params) {
+			warn!("Cannot cache public parameters: {error:#}");
+		}
+	}
+
+	Ok(public_params)
+}
+
+/// Proof verification statistics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerificationStats {
+	/// Number of batches waiting for a free verification thread
+	pub queued_batches: usize,
+	/// Verified cells per second since the previous statistics were taken
+	pub throughput: f64,
+}
+
+/// Takes proof verification statistics, and resets the throughput measurement.
+pub fn take_stats() -> VerificationStats {
+	let verifier = verifier();
+	let queued_batches = verifier
+		.pool
+		.lock()
+		.expect("Lock should be acquired")
+		.queued_count();
+
+	let mut taken_at = verifier
+		.stats_taken_at
+		.lock()
+		.expect("Lock should be acquired");
+	let verified_cells = verifier.verified_cells.swap(0, Ordering::Relaxed);
+	let elapsed = taken_at.elapsed().as_secs_f64();
+	*taken_at = Instant::now();
+
+	VerificationStats {
+		queued_batches,
+		throughput: if elapsed > 0.0 {
+			verified_cells as f64 / elapsed
+		} else {
+			0.0
+		},
+	}
+}
+
+/// Verifies proofs of the cells which share the same row commitment, one cell at a time
+fn verify_row_cells(
+	public_parameters: &PublicParameters,
 	dimensions: Dimensions,
-	commitment: [u8; 48],
-	cell: Cell,
-) -> Result<(Position, bool), proof::Error> {
-	proof::verify(&public_parameters, dimensions, &commitment, &cell)
-		.map(|verified| (cell.position, verified))
+	commitment: &[u8; 48],
+	cells: &[Cell],
+) -> Result<Vec<(Position, bool)>, proof::Error> {
+	cells
+		.iter()
+		.map(|cell| {
+			proof::verify(public_parameters, dimensions, commitment, cell)
+				.map(|verified| (cell.position, verified))
+		})
+		.collect()
 }
 
 /// Verifies proofs for given block, cells and commitments
@@ -35,24 +239,54 @@ pub async fn verify(
 	};
 
 	let start_time = Instant::now();
+	let verifier = verifier();
 
-	let mut tasks = JoinSet::new();
-
+	let mut rows = BTreeMap::<u32, Vec<Cell>>::new();
 	for cell in cells {
-		tasks.spawn(verify_proof(
-			public_parameters.clone(),
-			dimensions,
-			commitments[cell.position.row as usize],
-			cell.clone(),
-		));
+		rows.entry(cell.position.row)
+			.or_default()
+			.push(cell.clone());
 	}
 
+	let pool = verifier
+		.pool
+		.lock()
+		.expect("Lock should be acquired")
+		.clone();
+	let mut batches = Vec::new();
+	for (row, row_cells) in rows {
+		let commitment = commitments[row as usize];
+		for batch in row_cells.chunks(BATCH_SIZE) {
+			// Waits until the number of queued batches drops below the limit
+			let permit = verifier
+				.queue
+				.clone()
+				.acquire_owned()
+				.await
+				.wrap_err("Proof verification queue is closed")?;
+			let (sender, receiver) = oneshot::channel();
+			let batch = batch.to_vec();
+			let public_parameters = public_parameters.clone();
+			pool.execute(move || {
+				let result = verify_row_cells(&public_parameters, dimensions, &commitment, &batch);
+				verifier
+					.verified_cells
+					.fetch_add(batch.len() as u64, Ordering::Relaxed);
+				drop(permit);
+				// Receiver is dropped if verification is cancelled
+				let _ = sender.send(result);
+			});
+			batches.push(receiver);
+		}
+	}
+
+	let batches_count = batches.len();
 	let mut results = Vec::with_capacity(cells.len());
-	while let Some(result) = tasks.join_next().await {
-		results.push(result??)
+	for batch in batches {
+		results.extend(batch.await??);
 	}
 
-	debug!(block_num, duration = ?start_time.elapsed(), "Proof verification completed");
+	debug!(block_num, batches = batches_count, duration = ?start_time.elapsed(), "Proof verification completed");
 
 	Ok(results
 		.into_iter()
@@ -61,3 +295,116 @@ pub async fn verify(
 			false => Either::Right(position),
 		}))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::test_utils::TempDir;
+	use hex_literal::hex;
+	use kate_recovery::testnet;
+	use std::collections::HashSet;
+
+	/// Compressed BLS12-381 G1 identity, commitment to the zero polynomial
+	const IDENTITY: [u8; 48] = {
+		let mut bytes = [0u8; 48];
+		bytes[0] = 0xc0;
+		bytes
+	};
+
+	/// Compressed BLS12-381 G1 generator, commitment to the constant polynomial 1
+	const GENERATOR: [u8; 48] = hex!("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb");
+
+	/// Cell with the proof of the constant polynomial evaluation (witness is zero polynomial)
+	fn cell(row: u32, col: u16, value: u8) -> Cell {
+		let mut content = [0u8; 80];
+		content[..48].copy_from_slice(&IDENTITY);
+		content[48] = value;
+		Cell {
+			position: Position { row, col },
+			content,
+		}
+	}
+
+	#[test]
+	fn load_public_params_with_cache() {
+		let dir = TempDir::new();
+		let path = dir.join("public_params.data");
+		let bytes = testnet::public_params(16).to_var_bytes();
+		fs::write(&path, &bytes).unwrap();
+		let path = path.to_str().unwrap();
+		let cache_path = public_params_cache_path(path);
+		let hash = hex::encode(blake2_256(&bytes));
+
+		let wrong_hash = hex::encode([0u8; 32]);
+		assert!(load_public_params(path, &wrong_hash, Some(&cache_path)).is_err());
+		assert!(!cache_path.exists());
+
+		let public_params = load_public_params(path, &hash, Some(&cache_path)).unwrap();
+		assert!(cache_path.exists());
+
+		// Cached parameters are loaded even if the source file is removed
+		fs::remove_file(path).unwrap();
+		let cached = load_public_params(path, &format!("0x{hash}"), Some(&cache_path)).unwrap();
+		assert_eq!(cached.to_raw_var_bytes(), public_params.to_raw_var_bytes());
+	}
+
+	#[test]
+	fn forged_public_params_cache_is_rejected() {
+		let dir = TempDir::new();
+		let path = dir.join("public_params.data");
+		let bytes = testnet::public_params(16).to_var_bytes();
+		fs::write(&path, &bytes).unwrap();
+		let path = path.to_str().unwrap();
+		let cache_path = public_params_cache_path(path);
+		let hash = blake2_256(&bytes);
+
+		// Cache of the other parameters, labeled with the hash of the public parameters file
+		let forged = testnet::public_params(32);
+		store_cached_public_params(&cache_path, &hash, &forged).unwrap();
+		assert!(load_cached_public_params(&cache_path, &hash).is_err());
+
+		// Public parameters are loaded from the file instead
+		let public_params =
+			load_public_params(path, &hex::encode(hash), Some(&cache_path)).unwrap();
+		assert_eq!(public_params.to_var_bytes(), bytes);
+	}
+
+	#[tokio::test]
+	async fn verify_partitions_mixed_batches() {
+		let pp = Arc::new(testnet::public_params(16));
+		let dimensions = Dimensions::new(2, 64).unwrap();
+		// Row 0 commits to the constant 0, and row 1 to the constant 1 polynomial
+		let commitments = [IDENTITY, GENERATOR];
+
+		let mut cells = vec![];
+		let mut expected_verified = HashSet::new();
+		let mut expected_unverified = HashSet::new();
+		// Rows are interleaved, and row cells span more than one batch
+		for col in 0..64 {
+			for row in 0..2 {
+				let valid = col % 3 != 0;
+				let value = (valid == (row == 1)) as u8;
+				cells.push(cell(row, col, value));
+				let position = Position { row, col };
+				match valid {
+					true => expected_verified.insert(position),
+					false => expected_unverified.insert(position),
+				};
+			}
+		}
+
+		let (verified, unverified) = verify(1, dimensions, &cells, &commitments, pp)
+			.await
+			.unwrap();
+
+		assert_eq!(verified.len() + unverified.len(), cells.len());
+		assert_eq!(
+			verified.into_iter().collect::<HashSet<_>>(),
+			expected_verified
+		);
+		assert_eq!(
+			unverified.into_iter().collect::<HashSet<_>>(),
+			expected_unverified
+		);
+	}
+}
@@ -4,11 +4,15 @@
 //!
 //! # Flow
 //!
-//! * For each block, fetches block header from RPC and stores it into database
-//! * Generate random cells for random data sampling
+//! * For each block, fetches block header from the database, or from RPC if it is not stored
+//! * Generate random cells for random data sampling, seeded from the client sampling secret and the block hash
 //! * Retrieve cell proofs from a) DHT and/or b) via RPC call from the node, in that order
 //! * Verify proof using the received cells
-//! * Calculate block confidence and store it in RocksDB
+//! * Store sampling audit record (seed, sampled positions, sources and verification results)
+//! * Calculate block confidence, and store block header, verified cell count, confidence parameters
+//!   and verified cells into database in a single write batch
+//! * If the block is unavailable, store its unavailability outcome instead
+//! * Notify the consumers (app client, WebSocket topics and unavailability webhook) that the block is verified
 //! * Insert cells to to DHT for remote fetch
 //!
 //! # Notes
@@ -16,13 +20,15 @@
 //! In case RPC is disabled, RPC calls will be skipped.
 
 use crate::{
-	data::{Database, Key},
+	data::{
+		self, Batch, ConfidenceParams, Database, Key, SamplingAudit, Unavailability, VerifiedCell,
+	},
 	network::{
 		self,
 		rpc::{self, Client as RpcClient},
 	},
 	types::{BlockVerified, OptionBlockRange, State, SyncClientConfig},
-	utils::{calculate_confidence, extract_app_lookup, extract_kate},
+	utils::{extract_app_lookup, extract_kate},
 };
 
 use async_trait::async_trait;
@@ -32,10 +38,13 @@ use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Result,
 };
-use kate_recovery::{commitments, matrix::Dimensions};
+use kate_recovery::{commitments, data::Cell, matrix::Dimensions};
 use mockall::automock;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use sp_core::blake2_256;
 use std::{
+	collections::HashSet,
 	ops::Range,
 	sync::{Arc, Mutex},
 	time::Instant,
@@ -48,7 +57,16 @@ use tracing::{error, info, warn};
 pub trait Client {
 	async fn get_header_by_block_number(&self, block_number: u32) -> Result<(DaHeader, H256)>;
 	fn is_confidence_stored(&self, block_number: u32) -> Result<bool>;
-	fn store_confidence(&self, count: u32, block_number: u32) -> Result<()>;
+	fn sampling_secret(&self) -> Result<[u8; 32]>;
+	fn store_sampling_audit(&self, block_number: u32, audit: SamplingAudit) -> Result<()>;
+	fn store_unavailability(&self, block_number: u32, unavailability: Unavailability)
+		-> Result<()>;
+	fn store_block(
+		&self,
+		header: &DaHeader,
+		cells: &[Cell],
+		confidence_params: ConfidenceParams,
+	) -> Result<()>;
 }
 
 #[derive(Clone)]
@@ -88,10 +106,6 @@ impl<T: Database + Sync> Client for SyncClient<T> {
 			Err(error) => return Err(error),
 		};
 
-		self.db
-			.put(Key::BlockHeader(block_number), header.clone())
-			.wrap_err("Sync Client failed to store Block Header")?;
-
 		Ok((header, hash))
 	}
 
@@ -102,21 +116,65 @@ impl<T: Database + Sync> Client for SyncClient<T> {
 			.map(|c: Option<u32>| c.is_some())
 	}
 
-	fn store_confidence(&self, count: u32, block_number: u32) -> Result<()> {
+	fn sampling_secret(&self) -> Result<[u8; 32]> {
+		data::load_or_init_sampling_secret(&self.db)
+	}
+
+	fn store_sampling_audit(&self, block_number: u32, audit: SamplingAudit) -> Result<()> {
+		self.db
+			.put(Key::SamplingAudit(block_number), audit)
+			.wrap_err("Sync Client failed to store Sampling Audit")
+	}
+
+	fn store_unavailability(
+		&self,
+		block_number: u32,
+		unavailability: Unavailability,
+	) -> Result<()> {
 		self.db
+			.put(Key::Unavailability(block_number), unavailability)
+			.wrap_err("Sync Client failed to store Unavailability")
+	}
+
+	fn store_block(
+		&self,
+		header: &DaHeader,
+		cells: &[Cell],
+		confidence_params: ConfidenceParams,
+	) -> Result<()> {
+		let block_number = header.number;
+		let count: u32 = cells.len().try_into()?;
+		let mut batch = self.db.new_batch();
+		batch
+			.put(Key::BlockHeader(block_number), header.clone())
+			.wrap_err("Sync Client failed to store Block Header")?;
+		batch
 			.put(Key::VerifiedCellCount(block_number), count)
-			.wrap_err("Sync Client failed to store Confidence Factor")
+			.wrap_err("Sync Client failed to store Confidence Factor")?;
+		batch
+			.put(Key::ConfidenceParams(block_number), confidence_params)
+			.wrap_err("Sync Client failed to store Confidence Parameters")?;
+		batch
+			.put(
+				Key::VerifiedCells(block_number),
+				cells.iter().map(VerifiedCell::from).collect::<Vec<_>>(),
+			)
+			.wrap_err("Sync Client failed to store Verified Cells")?;
+		self.db
+			.write_batch(batch)
+			.wrap_err("Sync Client failed to store processed block")
 	}
 }
 
-async fn process_block(
+/// Samples the block and stores the sampling results.
+/// Returns achieved confidence, or unavailability outcome if the block is unavailable.
+async fn sample_block(
 	client: &impl Client,
 	network_client: &impl network::Client,
-	header: DaHeader,
+	header: &DaHeader,
 	header_hash: H256,
 	cfg: &SyncClientConfig,
-	block_verified_sender: broadcast::Sender<BlockVerified>,
-) -> Result<()> {
+) -> Result<(Option<f64>, Option<Unavailability>)> {
 	let block_number = header.number;
 	let begin = Instant::now();
 
@@ -130,11 +188,13 @@ async fn process_block(
 
 	let commitments = commitments::from_slice(&commitment)?;
 
-	// now this is in `u64`
-	let cell_count = rpc::cell_count_for_confidence(cfg.confidence);
-	let positions = rpc::generate_random_cells(dimensions, cell_count);
+	let cell_count = cfg.confidence_model.cell_count(cfg.confidence, dimensions);
+	let seed = rpc::sampling_seed(&client.sampling_secret()?, &header_hash);
+	let mut rng = ChaCha20Rng::from_seed(seed);
+	let positions = rpc::generate_cells(dimensions, cell_count, &HashSet::new(), &mut rng);
 
-	let (fetched, unfetched, _fetch_stats) = network_client
+	let sampling_begin = Instant::now();
+	let (fetched, unfetched, fetch_stats) = network_client
 		.fetch_verified(
 			block_number,
 			header_hash,
@@ -144,17 +204,51 @@ async fn process_block(
 		)
 		.await?;
 
+	let audit = SamplingAudit {
+		seed,
+		cells: fetch_stats.samples.clone(),
+		rounds: fetch_stats.rounds as u32,
+		duration_ms: sampling_begin.elapsed().as_millis() as u64,
+	};
+	client.store_sampling_audit(block_number, audit)?;
+
 	if positions.len() > fetched.len() {
-		error!(block_number, "Failed to fetch {} cells", unfetched.len());
-		return Ok(());
+		let missing_cells = (positions.len() - fetched.len()) as u32;
+		let unavailability = Unavailability::new(&fetch_stats.samples, missing_cells);
+		error!(
+			block_number,
+			reason = ?unavailability.reason,
+			"Failed to fetch {} cells, block is unavailable",
+			unfetched.len()
+		);
+		client.store_unavailability(block_number, unavailability)?;
+		return Ok((None, Some(unavailability)));
 	}
 
-	// write confidence factor into on-disk database
-	client.store_confidence(fetched.len().try_into()?, block_number)?;
+	// write block header, confidence factor, confidence parameters and verified cells into on-disk database
+	let confidence_params = ConfidenceParams::new(cfg.confidence_model, dimensions);
+	client.store_block(header, &fetched, confidence_params)?;
+
+	let confidence = cfg
+		.confidence_model
+		.confidence(fetched.len() as u32, dimensions);
+	Ok((Some(confidence), None))
+}
 
-	let confidence = Some(calculate_confidence(fetched.len() as u32));
-	let client_msg =
+async fn process_block(
+	client: &impl Client,
+	network_client: &impl network::Client,
+	header: DaHeader,
+	header_hash: H256,
+	cfg: &SyncClientConfig,
+	block_verified_sender: broadcast::Sender<BlockVerified>,
+) -> Result<()> {
+	let (confidence, unavailability) =
+		sample_block(client, network_client, &header, header_hash, cfg).await?;
+
+	let mut client_msg =
 		BlockVerified::try_from((header, confidence)).wrap_err("converting to message failed")?;
+	client_msg.unavailability = unavailability;
 
 	if let Err(error) = block_verified_sender.send(client_msg) {
 		error!("Cannot send block verified message: {error}");
@@ -163,6 +257,31 @@ async fn process_block(
 	Ok(())
 }
 
+/// Samples the block skipped by the lagging receiver, using the same path as the sync.
+/// Returns the block header with achieved confidence, or unavailability outcome if the block is unavailable,
+/// or `None` if the block was already processed.
+///
+/// # Arguments
+///
+/// * `client` - Sync client, used to get the block header from the database or RPC
+/// * `network_client` - Network client used to fetch and verify cells
+/// * `cfg` - Sync client configuration
+/// * `block_number` - Number of the skipped block
+pub async fn backfill_block(
+	client: &impl Client,
+	network_client: &impl network::Client,
+	cfg: &SyncClientConfig,
+	block_number: u32,
+) -> Result<Option<(DaHeader, (Option<f64>, Option<Unavailability>))>> {
+	if client.is_confidence_stored(block_number)? {
+		return Ok(None);
+	}
+
+	let (header, header_hash) = client.get_header_by_block_number(block_number).await?;
+	let outcome = sample_block(client, network_client, &header, header_hash, cfg).await?;
+	Ok(Some((header, outcome)))
+}
+
 /// Runs sync client.
 ///
 /// # Arguments
@@ -247,7 +366,10 @@ mod tests {
 	use std::time::Duration;
 
 	use super::*;
-	use crate::types::{self, RuntimeConfig};
+	use crate::{
+		data::UnavailabilityReason,
+		types::{self, RuntimeConfig},
+	};
 	use avail_subxt::{
 		api::runtime_types::avail_core::{
 			data_lookup::compact::CompactDataLookup,
@@ -371,9 +493,23 @@ mod tests {
 			.with(eq(2))
 			.returning(|_| Ok(true));
 		mock_client
-			.expect_store_confidence()
-			.withf(move |_, block_number| *block_number == 2)
-			.returning(move |_, _| Ok(()));
+			.expect_sampling_secret()
+			.returning(|| Ok([0u8; 32]));
+		mock_client
+			.expect_store_sampling_audit()
+			.withf(move |block_number, audit| {
+				*block_number == 2 && audit.seed == rpc::sampling_seed(&[0u8; 32], &header_hash)
+			})
+			.times(1)
+			.returning(|_, _| Ok(()));
+		mock_client
+			.expect_store_unavailability()
+			.withf(|block_number, _| *block_number == 2)
+			.returning(|_, _| Ok(()));
+		mock_client
+			.expect_store_block()
+			.withf(move |header, _, _| header.number == 2)
+			.returning(move |_, _, _| Ok(()));
 		process_block(
 			&mock_client,
 			&mock_network_client,
@@ -457,9 +593,23 @@ mod tests {
 			});
 
 		mock_client
-			.expect_store_confidence()
-			.withf(move |_, block_number| *block_number == 2)
-			.returning(move |_, _| Ok(()));
+			.expect_sampling_secret()
+			.returning(|| Ok([0u8; 32]));
+		mock_client
+			.expect_store_sampling_audit()
+			.withf(move |block_number, audit| {
+				*block_number == 2 && audit.seed == rpc::sampling_seed(&[0u8; 32], &header_hash)
+			})
+			.times(1)
+			.returning(|_, _| Ok(()));
+		mock_client
+			.expect_store_unavailability()
+			.withf(|block_number, _| *block_number == 2)
+			.returning(|_, _| Ok(()));
+		mock_client
+			.expect_store_block()
+			.withf(move |header, _, _| header.number == 2)
+			.returning(move |_, _, _| Ok(()));
 		process_block(
 			&mock_client,
 			&mock_network_client,
@@ -471,4 +621,68 @@ mod tests {
 		.await
 		.unwrap();
 	}
+
+	#[tokio::test]
+	pub async fn test_backfill_processed_block() {
+		let cfg = SyncClientConfig::from(&RuntimeConfig::default());
+		let mut mock_client = MockClient::new();
+		let mock_network_client = network::MockClient::new();
+		mock_client
+			.expect_is_confidence_stored()
+			.withf(|block_number| *block_number == 2)
+			.returning(|_| Ok(true));
+		mock_client.expect_get_header_by_block_number().never();
+
+		let backfilled = backfill_block(&mock_client, &mock_network_client, &cfg, 2)
+			.await
+			.unwrap();
+		assert!(backfilled.is_none());
+	}
+
+	#[tokio::test]
+	pub async fn test_backfill_unavailable_block() {
+		let cfg = SyncClientConfig::from(&RuntimeConfig::default());
+		let mut mock_client = MockClient::new();
+		let mut mock_network_client = network::MockClient::new();
+		mock_client
+			.expect_is_confidence_stored()
+			.returning(|_| Ok(false));
+		mock_client
+			.expect_get_header_by_block_number()
+			.returning(|_| Ok((default_header(), H256::default())));
+		mock_client
+			.expect_sampling_secret()
+			.returning(|| Ok([0u8; 32]));
+		mock_client
+			.expect_store_sampling_audit()
+			.returning(|_, _| Ok(()));
+		mock_network_client
+			.expect_fetch_verified()
+			.returning(|_, _, _, _, positions| {
+				let unfetched = positions.to_vec();
+				let stats =
+					network::FetchStats::new(positions.len(), 0, Duration::from_secs(0), None);
+				Box::pin(async move { Ok((vec![], unfetched, stats)) })
+			});
+		mock_client
+			.expect_store_unavailability()
+			.withf(|block_number, unavailability| {
+				*block_number == 2 && unavailability.reason == UnavailabilityReason::CellsNotFetched
+			})
+			.times(1)
+			.returning(|_, _| Ok(()));
+		mock_client.expect_store_block().never();
+
+		let (header, (confidence, unavailability)) =
+			backfill_block(&mock_client, &mock_network_client, &cfg, 2)
+				.await
+				.unwrap()
+				.unwrap();
+		assert_eq!(header.number, 2);
+		assert!(confidence.is_none());
+		assert_eq!(
+			unavailability.map(|unavailability| unavailability.reason),
+			Some(UnavailabilityReason::CellsNotFetched)
+		);
+	}
 }
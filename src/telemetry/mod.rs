@@ -18,6 +18,7 @@ pub enum MetricCounter {
 	ConnectionEstablished,
 	IncomingPutRecord,
 	IncomingGetRecord,
+	RejectedPutRecord,
 }
 
 impl Display for MetricCounter {
@@ -30,6 +31,7 @@ impl Display for MetricCounter {
 			MetricCounter::ConnectionEstablished => write!(f, "established_connections"),
 			MetricCounter::IncomingPutRecord => write!(f, "incoming_put_record_counter"),
 			MetricCounter::IncomingGetRecord => write!(f, "incoming_get_record_counter"),
+			MetricCounter::RejectedPutRecord => write!(f, "rejected_put_record_counter"),
 		}
 	}
 }
@@ -45,6 +47,7 @@ impl MetricCounter {
 			MetricCounter::ConnectionEstablished,
 			MetricCounter::IncomingPutRecord,
 			MetricCounter::IncomingGetRecord,
+			MetricCounter::RejectedPutRecord,
 		] {
 			counter_map.insert(
 				counter.to_string(),
@@ -73,6 +76,14 @@ pub enum MetricValue {
 	PingLatency(f64),
 	ReplicationFactor(u16),
 	QueryTimeout(u32),
+	DBEstimatedKeys(&'static str, u64),
+	DBLiveDataSize(&'static str, u64),
+	DBSstFilesSize(&'static str, u64),
+	DBPendingCompactionBytes(&'static str, u64),
+	ProofVerificationQueueDepth(usize),
+	ProofVerificationThroughput(f64),
+	PenalisedPeersNum(usize),
+	BlockedPeersNum(usize),
 	#[cfg(feature = "crawl")]
 	CrawlCellsSuccessRate(f64),
 	#[cfg(feature = "crawl")]
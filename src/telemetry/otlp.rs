@@ -59,6 +59,22 @@ impl Metrics {
 		Ok(())
 	}
 
+	async fn record_column_family_u64(
+		&self,
+		name: &'static str,
+		column_family: &'static str,
+		value: u64,
+	) -> Result<()> {
+		let instrument = self.meter.u64_observable_gauge(name).try_init()?;
+		let mut attributes = self.attributes().await.to_vec();
+		attributes.push(KeyValue::new("column_family", column_family));
+		self.meter
+			.register_callback(&[instrument.as_any()], move |observer| {
+				observer.observe_u64(&instrument, value, &attributes)
+			})?;
+		Ok(())
+	}
+
 	async fn record_f64(&self, name: &'static str, value: f64) -> Result<()> {
 		let instrument = self.meter.f64_observable_gauge(name).try_init()?;
 		let attributes = self.attributes().await;
@@ -135,6 +151,37 @@ impl super::Metrics for Metrics {
 			super::MetricValue::PingLatency(number) => {
 				self.record_f64("ping_latency", number).await?;
 			},
+			super::MetricValue::DBEstimatedKeys(column_family, number) => {
+				self.record_column_family_u64("db_estimated_keys", column_family, number)
+					.await?;
+			},
+			super::MetricValue::DBLiveDataSize(column_family, number) => {
+				self.record_column_family_u64("db_live_data_size", column_family, number)
+					.await?;
+			},
+			super::MetricValue::DBSstFilesSize(column_family, number) => {
+				self.record_column_family_u64("db_sst_files_size", column_family, number)
+					.await?;
+			},
+			super::MetricValue::DBPendingCompactionBytes(column_family, number) => {
+				self.record_column_family_u64("db_pending_compaction_bytes", column_family, number)
+					.await?;
+			},
+			super::MetricValue::ProofVerificationQueueDepth(number) => {
+				self.record_u64("proof_verification_queue_depth", number as u64)
+					.await?;
+			},
+			super::MetricValue::ProofVerificationThroughput(number) => {
+				self.record_f64("proof_verification_throughput", number)
+					.await?;
+			},
+			super::MetricValue::PenalisedPeersNum(number) => {
+				self.record_u64("penalised_peers_num", number as u64)
+					.await?;
+			},
+			super::MetricValue::BlockedPeersNum(number) => {
+				self.record_u64("blocked_peers_num", number as u64).await?;
+			},
 			#[cfg(feature = "crawl")]
 			super::MetricValue::CrawlCellsSuccessRate(number) => {
 				self.record_f64("crawl_cells_success_rate", number).await?;
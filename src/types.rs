@@ -1,12 +1,15 @@
 //! Shared light client structs and enums.
 
+use crate::confidence::ConfidenceModel;
+use crate::data::Unavailability;
+use crate::encryption::{self, EncryptionSecret};
 use crate::network::p2p::MemoryStoreConfig;
 use crate::network::rpc::{Event, Node as RpcNode};
 use crate::utils::{extract_app_lookup, extract_kate};
 use avail_core::DataLookup;
 use avail_subxt::{primitives::Header as DaHeader, utils::H256};
 use bip39::{Language, Mnemonic, MnemonicType};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use codec::{Decode, Encode};
 use color_eyre::{
 	eyre::{eyre, WrapErr},
@@ -37,6 +40,8 @@ pub const CELL_WITH_PROOF_SIZE: usize = CELL_SIZE + PROOF_SIZE;
 
 pub const DEV_FLAG_GENHASH: &str = "DEV";
 pub const IDENTITY_PROTOCOL: &str = "/avail_kad/id/1.0.0";
+pub const CELL_EXCHANGE_PROTOCOL: &str = "/avail_kad/cells/1.0.0";
+pub const ANNOUNCEMENTS_TOPIC: &str = "/avail_kad/announcements/1.0.0";
 pub const IDENTITY_AGENT_BASE: &str = "avail-light-client";
 pub const IDENTITY_AGENT_CLIENT_TYPE: &str = "rust-client";
 
@@ -73,12 +78,39 @@ pub struct CliOpts {
 	/// Avail secret seed phrase password
 	#[arg(long)]
 	pub avail_passphrase: Option<String>,
+	/// Passphrase used to derive the key for encryption of the app data and the identity file
+	#[arg(long)]
+	pub encryption_passphrase: Option<String>,
 	/// Seed string for libp2p keypair generation
 	#[arg(long)]
 	pub seed: Option<String>,
 	/// ed25519 private key for libp2p keypair generation
 	#[arg(long)]
 	pub private_key: Option<String>,
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+	/// Export block range from the local database into the snapshot file
+	Export {
+		/// First block of the range
+		#[arg(long)]
+		from: u32,
+		/// Last block of the range (inclusive)
+		#[arg(long)]
+		to: u32,
+		/// Path to the snapshot file
+		#[arg(long, value_name = "FILE")]
+		file: String,
+	},
+	/// Import snapshot file into the empty local database
+	Import {
+		/// Path to the snapshot file
+		#[arg(long, value_name = "FILE")]
+		file: String,
+	},
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -102,6 +134,8 @@ pub struct BlockVerified {
 	pub lookup: DataLookup,
 	pub commitments: Vec<[u8; 48]>,
 	pub confidence: Option<f64>,
+	/// Set if the block sampling failed, and the block is considered unavailable
+	pub unavailability: Option<Unavailability>,
 }
 
 pub struct ClientChannels {
@@ -126,6 +160,7 @@ impl TryFrom<(DaHeader, Option<f64>)> for BlockVerified {
 			lookup,
 			commitments: commitments::from_slice(&commitment)?,
 			confidence,
+			unavailability: None,
 		})
 	}
 }
@@ -169,6 +204,41 @@ impl TryFrom<String> for KademliaMode {
 	}
 }
 
+/// Kademlia record store type
+///
+/// * `Memory` - records are kept in memory
+/// * `RocksDB` - records are kept in the database, and served after the restart
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(try_from = "String")]
+pub enum RecordStoreType {
+	#[default]
+	Memory,
+	RocksDB,
+}
+
+impl Display for RecordStoreType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			RecordStoreType::Memory => write!(f, "memory"),
+			RecordStoreType::RocksDB => write!(f, "rocksdb"),
+		}
+	}
+}
+
+impl TryFrom<String> for RecordStoreType {
+	type Error = color_eyre::Report;
+
+	fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+		match value.to_lowercase().as_str() {
+			"memory" => Ok(RecordStoreType::Memory),
+			"rocksdb" => Ok(RecordStoreType::RocksDB),
+			_ => Err(eyre!(
+				"Wrong Kademlia record store. Expecting 'memory' or 'rocksdb'."
+			)),
+		}
+	}
+}
+
 /// Client mode
 ///
 /// * `LightClient` - light client is running
@@ -319,6 +389,20 @@ pub struct FibonacciConfig {
 	pub retries: usize,
 }
 
+/// Retention policy for the column family. Blocks which are violating any of the set limits are pruned.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RetentionPolicy {
+	/// Number of the latest blocks to keep (default: None).
+	pub max_blocks: Option<u32>,
+	/// Maximum age of the kept blocks in seconds (default: None).
+	/// Age is approximated as a number of blocks, assuming 20 seconds block time,
+	/// so the retained period is shorter or longer if the actual block time differs.
+	pub max_age: Option<u64>,
+	/// Maximum estimated size of the column family on disk in bytes (default: None).
+	pub max_size: Option<u64>,
+}
+
 /// Representation of a configuration used by this project.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
@@ -327,6 +411,8 @@ pub struct RuntimeConfig {
 	pub http_server_host: String,
 	/// Light client HTTP server port (default: 7000).
 	pub http_server_port: u16,
+	/// Enables admin API routes, which expose database statistics and compaction (default: false).
+	pub admin_api_enable: bool,
 	/// Secret key for libp2p keypair. Can be either set to `seed` or to `key`.
 	/// If set to seed, keypair will be generated from that seed.
 	/// If set to key, a valid ed25519 private key must be provided, else the client will fail
@@ -360,8 +446,32 @@ pub struct RuntimeConfig {
 	pub app_id: Option<u32>,
 	/// Confidence threshold, used to calculate how many cells need to be sampled to achieve desired confidence (default: 92.0).
 	pub confidence: f64,
+	/// Model used to calculate the confidence from the number of verified cells, `naive` or `erasure-coded` (default: naive).
+	/// Erasure coded model accounts for the block matrix dimensions and sampling without replacement.
+	pub confidence_model: ConfidenceModel,
 	/// File system path where RocksDB used by light client, stores its data.
+	/// In-memory database is used if set to `:memory:`, and state is not persisted between runs.
 	pub avail_path: String,
+	/// Interval in which the client state is persisted into the database, in seconds (default: 30).
+	pub state_snapshot_interval: u64,
+	/// Path to the file with hex encoded 32 bytes key, used to encrypt app data and the identity file.
+	/// Key can be derived from the passphrase instead, using `--encryption-passphrase` flag (default: None).
+	pub encryption_key_file: Option<String>,
+	/// Path to the file with KZG public parameters, serialized with `PublicParameters::to_var_bytes`.
+	/// If not set, public parameters bundled with the client are used (default: None).
+	pub public_params_file: Option<String>,
+	/// Hex encoded Blake2-256 hash of the public parameters file, required if `public_params_file` is set (default: None).
+	pub public_params_hash: Option<String>,
+	/// Retention policy for block headers and finality proofs. If not set, block headers are never pruned (default: None).
+	pub block_header_retention: Option<RetentionPolicy>,
+	/// Retention policy for verified cell counts. If not set, cell counts are never pruned (default: None).
+	pub confidence_retention: Option<RetentionPolicy>,
+	/// Retention policy for app data. If not set, app data is never pruned (default: None).
+	pub app_data_retention: Option<RetentionPolicy>,
+	/// Retention policy for verified cells and their proofs. If not set, verified cells are never pruned (default: None).
+	pub verified_cells_retention: Option<RetentionPolicy>,
+	/// Interval in blocks in which retention policies are applied (default: 180).
+	pub retention_pruning_interval: u32,
 	/// Log level, default is `INFO`. See `<https://docs.rs/log/0.4.14/log/enum.LevelFilter.html>` for possible log level values. (default: `INFO`).
 	pub log_level: String,
 	pub origin: String,
@@ -377,9 +487,21 @@ pub struct RuntimeConfig {
 	pub query_proof_rpc_parallel_tasks: usize,
 	/// Number of seconds to postpone block processing after block finalized message arrives (default: 0).
 	pub block_processing_delay: Option<u32>,
+	/// Time budget for sampling of a single block, in seconds. Cells which failed to fetch or verify are fetched again
+	/// until the budget is spent, after which the block is considered unavailable (default: 10).
+	pub sampling_time_budget: u64,
+	/// Maximum number of finalized blocks processed by the light client concurrently.
+	/// Blocks are still marked as verified in block order (default: 4).
+	pub block_processing_concurrency: usize,
+	/// HTTP endpoint to which block unavailability events are posted as JSON, if sampling of a block fails.
+	/// Requests which don't complete within 10 seconds are abandoned (default: None).
+	pub unavailability_webhook: Option<String>,
 	/// Fraction and number of the block matrix part to fetch (e.g. 2/20 means second 1/20 part of a matrix) (default: None)
 	#[serde(with = "block_matrix_partition_format")]
 	pub block_matrix_partition: Option<Partition>,
+	/// Fraction of the block matrix partitions provided by fat clients (e.g. 20 for 1/20 partitions).
+	/// If set, cells and rows which are not found in the DHT are requested from the partition providers (default: None).
+	pub provider_partition_fraction: Option<u8>,
 	/// Starting block of the syncing process. Omitting it will disable syncing. (default: None).
 	pub sync_start_block: Option<u32>,
 	/// Enable or disable synchronizing finality. If disabled, finality is assumed to be verified until the starting block at the point the LC is started and is only checked for new blocks. (default: true)
@@ -432,6 +554,13 @@ pub struct RuntimeConfig {
 	pub max_kad_record_size: u64,
 	/// The maximum number of provider records for which the local node is the provider. (default: 1024).
 	pub max_kad_provided_keys: u64,
+	/// Kademlia record store, `memory` or `rocksdb`. Records in the `rocksdb` store are kept in `avail_path` and served after restart. (default: memory).
+	pub kad_record_store: RecordStoreType,
+	/// Peer score below which the peer is blocked. Peers are penalised for serving invalid cells, malformed records and for timeouts.
+	/// Scores recover by 1 point per minute, and bootstrap peers are never penalised (default: -100).
+	pub peer_block_threshold: i32,
+	/// Duration for which misbehaving peers are blocked, in seconds. Peer score is reset once the block expires (default: 3600).
+	pub peer_block_duration: u64,
 	/// Set the configuration based on which the retries will be orchestrated, max duration [in seconds] between retries and number of tries.
 	/// (default:
 	/// fibonacci:
@@ -451,12 +580,48 @@ impl RuntimeConfig {
 	}
 }
 
+/// Retention configuration (see [RuntimeConfig] for details)
+#[derive(Clone)]
+pub struct RetentionConfig {
+	pub block_header: Option<RetentionPolicy>,
+	pub confidence: Option<RetentionPolicy>,
+	pub app_data: Option<RetentionPolicy>,
+	pub verified_cells: Option<RetentionPolicy>,
+	pub app_id: Option<u32>,
+	pub pruning_interval: u32,
+}
+
+impl RetentionConfig {
+	pub fn is_enabled(&self) -> bool {
+		self.block_header.is_some()
+			|| self.confidence.is_some()
+			|| self.app_data.is_some()
+			|| self.verified_cells.is_some()
+	}
+}
+
+impl From<&RuntimeConfig> for RetentionConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		RetentionConfig {
+			block_header: val.block_header_retention.clone(),
+			confidence: val.confidence_retention.clone(),
+			app_data: val.app_data_retention.clone(),
+			verified_cells: val.verified_cells_retention.clone(),
+			app_id: val.app_id,
+			pruning_interval: val.retention_pruning_interval,
+		}
+	}
+}
+
 pub struct Delay(pub Option<Duration>);
 
 /// Light client configuration (see [RuntimeConfig] for details)
 pub struct LightClientConfig {
 	pub confidence: f64,
+	pub confidence_model: ConfidenceModel,
 	pub block_processing_delay: Delay,
+	pub sampling_time_budget: Duration,
+	pub block_processing_concurrency: usize,
 }
 
 impl Delay {
@@ -475,7 +640,10 @@ impl From<&RuntimeConfig> for LightClientConfig {
 
 		LightClientConfig {
 			confidence: val.confidence,
+			confidence_model: val.confidence_model,
 			block_processing_delay: Delay(block_processing_delay),
+			sampling_time_budget: Duration::from_secs(val.sampling_time_budget),
+			block_processing_concurrency: val.block_processing_concurrency,
 		}
 	}
 }
@@ -525,6 +693,8 @@ pub struct LibP2PConfig {
 	pub task_command_buffer_size: NonZeroUsize,
 	pub per_connection_event_buffer_size: usize,
 	pub dial_concurrency_factor: NonZeroU8,
+	pub peer_block_threshold: i32,
+	pub peer_block_duration: Duration,
 }
 
 impl From<&LibP2PConfig> for libp2p::kad::Config {
@@ -580,6 +750,8 @@ impl From<&RuntimeConfig> for LibP2PConfig {
 			per_connection_event_buffer_size: val.per_connection_event_buffer_size,
 			dial_concurrency_factor: std::num::NonZeroU8::new(val.dial_concurrency_factor)
 				.expect("Invalid dial concurrency factor"),
+			peer_block_threshold: val.peer_block_threshold,
+			peer_block_duration: Duration::from_secs(val.peer_block_duration),
 		}
 	}
 }
@@ -598,6 +770,7 @@ pub struct KademliaConfig {
 	pub max_kad_record_number: usize,
 	pub max_kad_record_size: usize,
 	pub max_kad_provided_keys: usize,
+	pub kad_record_store: RecordStoreType,
 	pub kademlia_mode: KademliaMode,
 }
 
@@ -617,6 +790,7 @@ impl From<&RuntimeConfig> for KademliaConfig {
 			max_kad_record_number: val.max_kad_record_number as usize,
 			max_kad_record_size: val.max_kad_record_size as usize,
 			max_kad_provided_keys: val.max_kad_provided_keys as usize,
+			kad_record_store: val.kad_record_store,
 			kademlia_mode: val.operation_mode,
 		}
 	}
@@ -649,6 +823,10 @@ pub struct IdentifyConfig {
 	pub agent_version: AgentVersion,
 	/// Contains Avail genesis hash
 	pub protocol_version: String,
+	/// Protocol name used for direct cell and row requests, contains Avail genesis hash
+	pub cell_exchange_protocol: String,
+	/// Gossipsub topic used for block availability announcements, contains Avail genesis hash
+	pub announcements_topic: String,
 }
 
 #[derive(Clone)]
@@ -711,6 +889,16 @@ impl From<&RuntimeConfig> for IdentifyConfig {
 				id = IDENTITY_PROTOCOL,
 				gen_hash = genhash_short
 			),
+			cell_exchange_protocol: format!(
+				"{id}-{gen_hash}",
+				id = CELL_EXCHANGE_PROTOCOL,
+				gen_hash = genhash_short
+			),
+			announcements_topic: format!(
+				"{id}-{gen_hash}",
+				id = ANNOUNCEMENTS_TOPIC,
+				gen_hash = genhash_short
+			),
 		}
 	}
 }
@@ -719,6 +907,7 @@ impl From<&RuntimeConfig> for IdentifyConfig {
 #[derive(Clone)]
 pub struct SyncClientConfig {
 	pub confidence: f64,
+	pub confidence_model: ConfidenceModel,
 	pub disable_rpc: bool,
 	pub dht_parallelization_limit: usize,
 	pub is_last_step: bool,
@@ -728,6 +917,7 @@ impl From<&RuntimeConfig> for SyncClientConfig {
 	fn from(val: &RuntimeConfig) -> Self {
 		SyncClientConfig {
 			confidence: val.confidence,
+			confidence_model: val.confidence_model,
 			disable_rpc: val.disable_rpc,
 			dht_parallelization_limit: val.dht_parallelization_limit,
 			is_last_step: val.app_id.is_none(),
@@ -756,6 +946,7 @@ impl Default for RuntimeConfig {
 		RuntimeConfig {
 			http_server_host: "127.0.0.1".to_owned(),
 			http_server_port: 7000,
+			admin_api_enable: false,
 			port: 37000,
 			ws_transport_enable: false,
 			secret_key: None,
@@ -771,7 +962,17 @@ impl Default for RuntimeConfig {
 			genesis_hash: "DEV".to_owned(),
 			app_id: None,
 			confidence: 99.9,
+			confidence_model: ConfidenceModel::Naive,
 			avail_path: "avail_path".to_owned(),
+			state_snapshot_interval: 30,
+			encryption_key_file: None,
+			public_params_file: None,
+			public_params_hash: None,
+			block_header_retention: None,
+			confidence_retention: None,
+			app_data_retention: None,
+			verified_cells_retention: None,
+			retention_pruning_interval: 180,
 			log_level: "INFO".to_owned(),
 			log_format_json: false,
 			ot_collector_endpoint: "http://127.0.0.1:4317".to_string(),
@@ -779,7 +980,11 @@ impl Default for RuntimeConfig {
 			dht_parallelization_limit: 20,
 			query_proof_rpc_parallel_tasks: 8,
 			block_processing_delay: Some(20),
+			sampling_time_budget: 10,
+			block_processing_concurrency: 4,
+			unavailability_webhook: None,
 			block_matrix_partition: None,
+			provider_partition_fraction: None,
 			sync_start_block: None,
 			sync_finality_enable: false,
 			max_cells_per_rpc: Some(30),
@@ -801,6 +1006,9 @@ impl Default for RuntimeConfig {
 			max_kad_record_number: 2400000,
 			max_kad_record_size: 8192,
 			max_kad_provided_keys: 1024,
+			kad_record_store: RecordStoreType::Memory,
+			peer_block_threshold: -100,
+			peer_block_duration: 60 * 60,
 			#[cfg(feature = "crawl")]
 			crawl: crate::crawl_client::CrawlConfig::default(),
 			origin: "external".to_string(),
@@ -949,6 +1157,49 @@ impl RuntimeConfig {
 			})
 		}
 
+		if self.confidence_model == ConfidenceModel::ErasureCoded
+			&& !(self.confidence > 0.0 && self.confidence < 100.0)
+		{
+			return Err(eyre!(
+				"Confidence has to be between 0 and 100 with the erasure coded confidence model"
+			));
+		}
+
+		if self.block_processing_concurrency == 0 {
+			return Err(eyre!(
+				"Block processing concurrency has to be greater than 0"
+			));
+		}
+
+		if self.retention_pruning_interval == 0 {
+			return Err(eyre!("Retention pruning interval has to be greater than 0"));
+		}
+
+		if self.public_params_file.is_some() && self.public_params_hash.is_none() {
+			return Err(eyre!(
+				"Public parameters hash has to be set if public parameters file is configured"
+			));
+		}
+
+		if self.peer_block_threshold >= 0 {
+			return Err(eyre!("Peer block threshold has to be negative"));
+		}
+
+		if self.provider_partition_fraction == Some(0) {
+			return Err(eyre!(
+				"Provider partition fraction has to be greater than 0"
+			));
+		}
+
+		if let Some(webhook) = &self.unavailability_webhook {
+			let uri: hyper::Uri = webhook
+				.parse()
+				.wrap_err("Invalid unavailability webhook URL")?;
+			if uri.scheme() != Some(&hyper::http::uri::Scheme::HTTP) {
+				return Err(eyre!("Unavailability webhook URL has to use http scheme"));
+			}
+		}
+
 		Ok(())
 	}
 }
@@ -960,22 +1211,92 @@ pub struct IdentityConfig {
 	pub avail_address: String,
 }
 
+/// Avail secret seed phrase encrypted with the key obtained from the encryption secret.
+#[derive(Serialize, Deserialize)]
+struct EncryptedSeedPhrase {
+	/// Hex encoded salt, used for the key derivation from the passphrase
+	salt: String,
+	/// Hex encoded nonce and encrypted seed phrase
+	ciphertext: String,
+}
+
+impl EncryptedSeedPhrase {
+	fn new(phrase: &str, secret: &EncryptionSecret) -> Result<Self> {
+		let salt = encryption::generate_salt();
+		let ciphertext = secret.key(&salt).encrypt(phrase.as_bytes())?;
+		Ok(EncryptedSeedPhrase {
+			salt: hex::encode(salt),
+			ciphertext: hex::encode(ciphertext),
+		})
+	}
+
+	fn decrypt(&self, secret: &EncryptionSecret) -> Result<String> {
+		let salt = hex::decode(&self.salt).wrap_err("Invalid encrypted seed phrase salt")?;
+		let ciphertext =
+			hex::decode(&self.ciphertext).wrap_err("Invalid encrypted seed phrase ciphertext")?;
+		let phrase = secret
+			.key(&salt)
+			.decrypt(&ciphertext)
+			.wrap_err("Failed to decrypt Avail secret seed phrase")?;
+		String::from_utf8(phrase).wrap_err("Decrypted Avail secret seed phrase is not valid")
+	}
+}
+
 impl IdentityConfig {
-	pub fn load_or_init(path: &str, password: Option<&str>) -> Result<Self> {
+	/// Loads identity from the file, or generates and stores a new one.
+	/// If encryption secret is provided, seed phrase is stored encrypted,
+	/// and existing plaintext seed phrase is replaced with the encrypted one.
+	pub fn load_or_init(
+		path: &str,
+		password: Option<&str>,
+		encryption: Option<&EncryptionSecret>,
+	) -> Result<Self> {
 		#[derive(Default, Serialize, Deserialize)]
 		struct Config {
+			#[serde(skip_serializing_if = "Option::is_none")]
 			pub avail_secret_seed_phrase: Option<String>,
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub encrypted_avail_secret_seed_phrase: Option<EncryptedSeedPhrase>,
 		}
 
 		let mut config: Config = confy::load_path(path)?;
 
-		let phrase = match config.avail_secret_seed_phrase.as_ref() {
-			None => Mnemonic::new(MnemonicType::Words24, Language::English).into_phrase(),
-			Some(phrase) => phrase.to_owned(),
+		let phrase = match (
+			config.avail_secret_seed_phrase.as_ref(),
+			config.encrypted_avail_secret_seed_phrase.as_ref(),
+			encryption,
+		) {
+			(_, Some(_), None) => {
+				return Err(eyre!(
+					"Identity file is encrypted, encryption passphrase or key file is required"
+				))
+			},
+			(_, Some(encrypted), Some(secret)) => encrypted.decrypt(secret)?,
+			(Some(phrase), None, _) => phrase.to_owned(),
+			(None, None, _) => {
+				Mnemonic::new(MnemonicType::Words24, Language::English).into_phrase()
+			},
 		};
 
-		if config.avail_secret_seed_phrase.is_none() {
-			config.avail_secret_seed_phrase = Some(phrase.clone());
+		let store = match encryption {
+			Some(secret) => {
+				let store = config.encrypted_avail_secret_seed_phrase.is_none()
+					|| config.avail_secret_seed_phrase.is_some();
+				if config.encrypted_avail_secret_seed_phrase.is_none() {
+					config.encrypted_avail_secret_seed_phrase =
+						Some(EncryptedSeedPhrase::new(&phrase, secret)?);
+				}
+				config.avail_secret_seed_phrase = None;
+				store
+			},
+			None => {
+				let store = config.avail_secret_seed_phrase.is_none();
+				config.avail_secret_seed_phrase = Some(phrase.clone());
+				store
+			},
+		};
+
+		if store {
 			confy::store_path(path, &config)?;
 		}
 
@@ -989,7 +1310,7 @@ impl IdentityConfig {
 	}
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Decode, Encode)]
 pub struct BlockRange {
 	pub first: u32,
 	pub last: u32,
@@ -1004,6 +1325,17 @@ impl BlockRange {
 	pub fn contains(&self, block_number: u32) -> bool {
 		self.first <= block_number && block_number <= self.last
 	}
+
+	/// Returns union of two ranges, if ranges are overlapping or adjacent.
+	pub fn merge(&self, other: &BlockRange) -> Option<BlockRange> {
+		if self.first > other.last.saturating_add(1) || other.first > self.last.saturating_add(1) {
+			return None;
+		}
+		Some(BlockRange {
+			first: self.first.min(other.first),
+			last: self.last.max(other.last),
+		})
+	}
 }
 
 #[derive(Default)]
@@ -1019,6 +1351,135 @@ pub struct State {
 	pub sync_data_verified: Option<BlockRange>,
 	pub finality_synced: bool,
 	pub connected_node: RpcNode,
+	pub retained: Retained,
+}
+
+/// First blocks retained in the column families, set once the blocks are pruned.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize, Decode, Encode)]
+pub struct Retained {
+	pub block_header: Option<u32>,
+	pub confidence: Option<u32>,
+	pub app_data: Option<u32>,
+	pub verified_cells: Option<u32>,
+}
+
+impl State {
+	/// Moves block ranges restored from the previous run into the historical sync ranges,
+	/// in case there is a gap between the last restored header and the first block
+	/// received after startup. Ranges are left untouched if there is no gap.
+	pub fn detach_restored_ranges(&mut self, restored_last: Option<u32>, first_block: u32) {
+		let Some(restored_last) = restored_last else {
+			return;
+		};
+		if restored_last.saturating_add(1) >= first_block {
+			return;
+		}
+
+		fn detach(
+			live: &mut Option<BlockRange>,
+			sync: &mut Option<BlockRange>,
+			restored_last: u32,
+			first_block: u32,
+		) {
+			let Some(range) = live.take() else {
+				return;
+			};
+			if range.last >= first_block {
+				*live = Some(BlockRange {
+					first: range.first.max(first_block),
+					last: range.last,
+				});
+			}
+			if range.first > restored_last {
+				return;
+			}
+			let history = BlockRange {
+				first: range.first,
+				last: range.last.min(restored_last),
+			};
+			*sync = match sync.as_ref() {
+				None => Some(history),
+				Some(sync_range) => sync_range.merge(&history).or(Some(sync_range.clone())),
+			};
+		}
+
+		detach(
+			&mut self.header_verified,
+			&mut self.sync_header_verified,
+			restored_last,
+			first_block,
+		);
+		detach(
+			&mut self.confidence_achieved,
+			&mut self.sync_confidence_achieved,
+			restored_last,
+			first_block,
+		);
+		detach(
+			&mut self.data_verified,
+			&mut self.sync_data_verified,
+			restored_last,
+			first_block,
+		);
+
+		if self.sync_header_verified.is_some() {
+			self.synced.get_or_insert(true);
+		}
+	}
+}
+
+/// Part of the [`State`] which is persisted in the database and restored on startup.
+#[derive(Clone, Default, Debug, Serialize, Deserialize, Decode, Encode)]
+pub struct StateSnapshot {
+	pub synced: Option<bool>,
+	pub latest: u32,
+	pub header_verified: Option<BlockRange>,
+	pub confidence_achieved: Option<BlockRange>,
+	pub data_verified: Option<BlockRange>,
+	pub sync_latest: Option<u32>,
+	pub sync_header_verified: Option<BlockRange>,
+	pub sync_confidence_achieved: Option<BlockRange>,
+	pub sync_data_verified: Option<BlockRange>,
+	pub finality_synced: bool,
+	pub retained: Retained,
+}
+
+impl From<&State> for StateSnapshot {
+	fn from(state: &State) -> Self {
+		StateSnapshot {
+			synced: state.synced,
+			latest: state.latest,
+			header_verified: state.header_verified.clone(),
+			confidence_achieved: state.confidence_achieved.clone(),
+			data_verified: state.data_verified.clone(),
+			sync_latest: state.sync_latest,
+			sync_header_verified: state.sync_header_verified.clone(),
+			sync_confidence_achieved: state.sync_confidence_achieved.clone(),
+			sync_data_verified: state.sync_data_verified.clone(),
+			finality_synced: state.finality_synced,
+			retained: state.retained.clone(),
+		}
+	}
+}
+
+impl From<StateSnapshot> for State {
+	fn from(snapshot: StateSnapshot) -> Self {
+		State {
+			synced: snapshot.synced,
+			latest: snapshot.latest,
+			header_verified: snapshot.header_verified,
+			confidence_achieved: snapshot.confidence_achieved,
+			data_verified: snapshot.data_verified,
+			sync_latest: snapshot.sync_latest,
+			sync_header_verified: snapshot.sync_header_verified,
+			sync_confidence_achieved: snapshot.sync_confidence_achieved,
+			sync_data_verified: snapshot.sync_data_verified,
+			// Finality has to be synced again, since blocks could be finalized while client was offline
+			finality_synced: false,
+			connected_node: Default::default(),
+			retained: snapshot.retained,
+		}
+	}
 }
 
 pub trait OptionBlockRange {
@@ -1064,7 +1525,7 @@ pub struct Precommit {
 	pub target_number: u32,
 }
 
-#[derive(Clone, Debug, Decode, Deserialize)]
+#[derive(Clone, Debug, Decode, Encode, Deserialize)]
 pub struct SignedPrecommit {
 	pub precommit: Precommit,
 	/// The signature on the message.
@@ -1072,7 +1533,7 @@ pub struct SignedPrecommit {
 	/// The Id of the signer.
 	pub id: ed25519::Public,
 }
-#[derive(Clone, Debug, Decode, Deserialize)]
+#[derive(Clone, Debug, Decode, Encode, Deserialize)]
 pub struct Commit {
 	pub target_hash: H256,
 	/// The target block's number.
@@ -1081,7 +1542,7 @@ pub struct Commit {
 	pub precommits: Vec<SignedPrecommit>,
 }
 
-#[derive(Clone, Debug, Decode)]
+#[derive(Clone, Debug, Decode, Encode)]
 pub struct GrandpaJustification {
 	pub round: u64,
 	pub commit: Commit,
@@ -111,6 +111,33 @@ fn diff_positions(positions: &[Position], cells: &[Cell]) -> Vec<Position> {
 		.collect::<Vec<_>>()
 }
 
+#[cfg(test)]
+pub(crate) mod test_utils {
+	use std::{env, fs, path::PathBuf};
+	use uuid::Uuid;
+
+	/// Temporary directory, removed together with its content when dropped
+	pub struct TempDir(pub String);
+
+	impl TempDir {
+		pub fn new() -> Self {
+			let path = env::temp_dir().join(format!("avail_light_{}", Uuid::new_v4()));
+			fs::create_dir_all(&path).unwrap();
+			TempDir(path.to_string_lossy().to_string())
+		}
+
+		pub fn join(&self, path: &str) -> PathBuf {
+			PathBuf::from(&self.0).join(path)
+		}
+	}
+
+	impl Drop for TempDir {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.0);
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::{can_reconstruct, diff_positions};